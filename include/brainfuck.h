@@ -0,0 +1,57 @@
+/* C interface of the brainfuck interpreter; see src/ffi.rs. */
+#ifndef BRAINFUCK_H
+#define BRAINFUCK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BF_OK 0
+#define BF_DONE 1 /* bf_step: the program has ended */
+#define BF_NULL_ARGUMENT (-1)
+#define BF_INVALID_UTF8 (-2)
+#define BF_UNMATCHED_JUMP_FORWARD (-3)
+#define BF_UNMATCHED_JUMP_BACKWARD (-4)
+#define BF_POINTER_ERROR (-5)
+#define BF_OUTPUT_ERROR (-6)
+#define BF_INTERRUPTED (-7)
+#define BF_STEP_LIMIT_EXCEEDED (-8)
+#define BF_INPUT_ERROR (-9)
+#define BF_NON_TERMINATING (-10)
+#define BF_INPUT_EXHAUSTED (-11)
+#define BF_NESTING_TOO_DEEP (-12)
+
+/* A compiled program with its tape, input and output. Not thread-safe. */
+typedef struct BfMachine BfMachine;
+
+/* Stores a new machine in *out, to be freed with bf_free. */
+int bf_compile(const char *source, BfMachine **out);
+void bf_free(BfMachine *machine);
+
+/* The input is copied. ',' reads 0 once it is exhausted. */
+int bf_set_input(BfMachine *machine, const uint8_t *data, size_t len);
+/* 0 means no limit. */
+int bf_set_step_limit(BfMachine *machine, uint64_t limit);
+
+/* BF_OK after one command, BF_DONE at the end, or an error code. */
+int bf_step(BfMachine *machine);
+/* Runs to the end: BF_OK or an error code. */
+int bf_run(BfMachine *machine);
+
+/* The returned pointers are valid until the next call taking the machine. */
+size_t bf_get_tape(const BfMachine *machine, const uint8_t **cells, size_t *pointer);
+size_t bf_get_output(BfMachine *machine, const uint8_t **data);
+/* BF_OK with the location of the last error, or BF_DONE if there was none. */
+int bf_error_location(const BfMachine *machine, size_t *line, size_t *col);
+/* The message of the last error, such as the OS's words for a failed input,
+   or NULL if there was none. */
+const char *bf_error_message(BfMachine *machine);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
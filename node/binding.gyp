@@ -0,0 +1,11 @@
+{
+  "targets": [
+    {
+      "target_name": "brainfuck",
+      "sources": ["brainfuck_napi.c"],
+      "include_dirs": ["../include"],
+      "libraries": ["-L<(module_root_dir)/../target/release", "-lbrainfuck"],
+      "ldflags": ["-Wl,-rpath,<(module_root_dir)/../target/release"]
+    }
+  ]
+}
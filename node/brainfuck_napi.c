@@ -0,0 +1,214 @@
+/* N-API addon over the C interface in include/brainfuck.h. */
+#include <node_api.h>
+#include <stdlib.h>
+#include <string.h>
+#include "brainfuck.h"
+
+#define CHECK(call)                                                        \
+    do {                                                                   \
+        if ((call) != napi_ok) {                                           \
+            napi_throw_error(env, NULL, "N-API call failed: " #call);      \
+            return NULL;                                                   \
+        }                                                                  \
+    } while (0)
+
+static const char *error_message(int code) {
+    switch (code) {
+    case BF_NULL_ARGUMENT: return "null argument";
+    case BF_INVALID_UTF8: return "the program is not valid UTF-8";
+    case BF_UNMATCHED_JUMP_FORWARD: return "unmatched '['";
+    case BF_UNMATCHED_JUMP_BACKWARD: return "unmatched ']'";
+    case BF_POINTER_ERROR: return "pointer moved below cell 0";
+    case BF_OUTPUT_ERROR: return "could not write the output";
+    case BF_INTERRUPTED: return "interrupted";
+    case BF_STEP_LIMIT_EXCEEDED: return "step limit exceeded";
+    case BF_INPUT_ERROR: return "could not read the input";
+    default: return "unknown error";
+    }
+}
+
+/* Throws an Error with `code`, `line` and `col` properties. */
+static void throw_bf_error(napi_env env, BfMachine *machine, int code) {
+    napi_value message, error, value;
+    size_t line = 0, col = 0;
+    const char *text = machine ? bf_error_message(machine) : NULL;
+    napi_create_string_utf8(env, text ? text : error_message(code), NAPI_AUTO_LENGTH, &message);
+    napi_create_error(env, NULL, message, &error);
+    napi_create_int32(env, code, &value);
+    napi_set_named_property(env, error, "code", value);
+    if (machine && bf_error_location(machine, &line, &col) == BF_OK) {
+        napi_create_uint32(env, (uint32_t)line, &value);
+        napi_set_named_property(env, error, "line", value);
+        napi_create_uint32(env, (uint32_t)col, &value);
+        napi_set_named_property(env, error, "col", value);
+    }
+    napi_throw(env, error);
+}
+
+static void finalize(napi_env env, void *data, void *hint) {
+    (void)env;
+    (void)hint;
+    bf_free((BfMachine *)data);
+}
+
+static BfMachine *unwrap(napi_env env, napi_callback_info info, size_t *argc, napi_value *argv) {
+    napi_value self;
+    void *machine = NULL;
+    if (napi_get_cb_info(env, info, argc, argv, &self, NULL) != napi_ok ||
+        napi_unwrap(env, self, &machine) != napi_ok) {
+        napi_throw_error(env, NULL, "not a Machine");
+        return NULL;
+    }
+    return (BfMachine *)machine;
+}
+
+static int set_input(napi_env env, BfMachine *machine, napi_value input) {
+    void *data = NULL;
+    size_t len = 0;
+    napi_valuetype type;
+    napi_typeof(env, input, &type);
+    if (type == napi_undefined) {
+        return BF_OK;
+    }
+    if (napi_get_buffer_info(env, input, &data, &len) != napi_ok) {
+        napi_throw_type_error(env, NULL, "input must be a Buffer");
+        return BF_NULL_ARGUMENT;
+    }
+    return bf_set_input(machine, data, len);
+}
+
+/* new Machine(source, input?, stepLimit?) */
+static napi_value machine_new(napi_env env, napi_callback_info info) {
+    size_t argc = 3;
+    napi_value argv[3], self;
+    char *source;
+    size_t len;
+    int64_t limit = 0;
+    BfMachine *machine = NULL;
+    int code;
+    CHECK(napi_get_cb_info(env, info, &argc, argv, &self, NULL));
+    if (argc < 1 || napi_get_value_string_utf8(env, argv[0], NULL, 0, &len) != napi_ok) {
+        napi_throw_type_error(env, NULL, "source must be a string");
+        return NULL;
+    }
+    source = malloc(len + 1);
+    napi_get_value_string_utf8(env, argv[0], source, len + 1, &len);
+    code = bf_compile(source, &machine);
+    free(source);
+    if (code != BF_OK) {
+        throw_bf_error(env, NULL, code);
+        return NULL;
+    }
+    if (argc >= 2 && set_input(env, machine, argv[1]) != BF_OK) {
+        bf_free(machine);
+        return NULL;
+    }
+    if (argc >= 3 && napi_get_value_int64(env, argv[2], &limit) == napi_ok && limit > 0) {
+        bf_set_step_limit(machine, (uint64_t)limit);
+    }
+    CHECK(napi_wrap(env, self, machine, finalize, NULL, NULL));
+    return self;
+}
+
+static napi_value machine_set_input(napi_env env, napi_callback_info info) {
+    size_t argc = 1;
+    napi_value argv[1];
+    BfMachine *machine = unwrap(env, info, &argc, argv);
+    if (machine) {
+        set_input(env, machine, argv[0]);
+    }
+    return NULL;
+}
+
+/* Runs one command; false once the program has ended. */
+static napi_value machine_step(napi_env env, napi_callback_info info) {
+    size_t argc = 0;
+    napi_value result;
+    BfMachine *machine = unwrap(env, info, &argc, NULL);
+    int code;
+    if (!machine) {
+        return NULL;
+    }
+    code = bf_step(machine);
+    if (code < 0) {
+        throw_bf_error(env, machine, code);
+        return NULL;
+    }
+    CHECK(napi_get_boolean(env, code == BF_OK, &result));
+    return result;
+}
+
+static napi_value output_buffer(napi_env env, BfMachine *machine) {
+    const uint8_t *data;
+    size_t len = bf_get_output(machine, &data);
+    napi_value buffer;
+    CHECK(napi_create_buffer_copy(env, len, data, NULL, &buffer));
+    return buffer;
+}
+
+/* Runs to the end and returns the output as a Buffer. */
+static napi_value machine_run(napi_env env, napi_callback_info info) {
+    size_t argc = 0;
+    BfMachine *machine = unwrap(env, info, &argc, NULL);
+    int code;
+    if (!machine) {
+        return NULL;
+    }
+    code = bf_run(machine);
+    if (code != BF_OK) {
+        throw_bf_error(env, machine, code);
+        return NULL;
+    }
+    return output_buffer(env, machine);
+}
+
+static napi_value machine_output(napi_env env, napi_callback_info info) {
+    size_t argc = 0;
+    BfMachine *machine = unwrap(env, info, &argc, NULL);
+    return machine ? output_buffer(env, machine) : NULL;
+}
+
+static napi_value machine_tape(napi_env env, napi_callback_info info) {
+    size_t argc = 0;
+    const uint8_t *cells;
+    napi_value buffer;
+    BfMachine *machine = unwrap(env, info, &argc, NULL);
+    size_t len;
+    if (!machine) {
+        return NULL;
+    }
+    len = bf_get_tape(machine, &cells, NULL);
+    CHECK(napi_create_buffer_copy(env, len, cells, NULL, &buffer));
+    return buffer;
+}
+
+static napi_value machine_pointer(napi_env env, napi_callback_info info) {
+    size_t argc = 0, pointer = 0;
+    napi_value result;
+    BfMachine *machine = unwrap(env, info, &argc, NULL);
+    if (!machine) {
+        return NULL;
+    }
+    bf_get_tape(machine, NULL, &pointer);
+    CHECK(napi_create_double(env, (double)pointer, &result));
+    return result;
+}
+
+static napi_value init(napi_env env, napi_value exports) {
+    napi_property_descriptor properties[] = {
+        {"setInput", NULL, machine_set_input, NULL, NULL, NULL, napi_default, NULL},
+        {"step", NULL, machine_step, NULL, NULL, NULL, napi_default, NULL},
+        {"run", NULL, machine_run, NULL, NULL, NULL, napi_default, NULL},
+        {"output", NULL, NULL, machine_output, NULL, NULL, napi_default, NULL},
+        {"tape", NULL, NULL, machine_tape, NULL, NULL, napi_default, NULL},
+        {"pointer", NULL, NULL, machine_pointer, NULL, NULL, napi_default, NULL},
+    };
+    napi_value constructor;
+    CHECK(napi_define_class(env, "Machine", NAPI_AUTO_LENGTH, machine_new, NULL,
+                            sizeof(properties) / sizeof(properties[0]), properties,
+                            &constructor));
+    CHECK(napi_set_named_property(env, exports, "Machine", constructor));
+    return exports;
+}
+
+NAPI_MODULE(NODE_GYP_MODULE_NAME, init)
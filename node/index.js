@@ -0,0 +1,17 @@
+'use strict';
+// Node bindings of the brainfuck interpreter. Build with `cargo build --release`
+// and then `npx node-gyp rebuild` in this directory.
+const { Machine } = require('./build/Release/brainfuck.node');
+
+// Compiles a program; throws on unmatched brackets. `input` is a Buffer or string.
+function compile(source, input = Buffer.alloc(0), stepLimit = 0) {
+  return new Machine(source, Buffer.from(input), stepLimit);
+}
+
+// Runs a program to the end and returns its output as a Buffer. Errors have
+// `code`, `line` and `col` properties.
+function run(source, input, stepLimit) {
+  return compile(source, input, stepLimit).run();
+}
+
+module.exports = { Machine, compile, run };
@@ -0,0 +1,25 @@
+'use strict';
+// Tests of the Node bindings. Run `npm test` after building the addon.
+const assert = require('node:assert');
+const test = require('node:test');
+const bf = require('.');
+
+test('run', () => {
+  assert.deepStrictEqual(bf.run(',[.,]', 'hi'), Buffer.from('hi'));
+  assert.deepStrictEqual(bf.run('++++++++[>++++++++<-]>+.'), Buffer.from('A'));
+});
+
+test('step', () => {
+  const m = bf.compile('+>++');
+  let steps = 0;
+  while (m.step()) steps++;
+  assert.strictEqual(steps, 4);
+  assert.deepStrictEqual(m.tape, Buffer.from([1, 2]));
+  assert.strictEqual(m.pointer, 1);
+});
+
+test('errors', () => {
+  assert.throws(() => bf.run('['), { code: -3 });
+  assert.throws(() => bf.run('+\n<'), { code: -5, line: 2, col: 1 });
+  assert.throws(() => bf.run('+[]', '', 100), { code: -8, message: 'step limit exceeded' });
+});
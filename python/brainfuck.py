@@ -0,0 +1,146 @@
+"""Python bindings over the C interface of the brainfuck interpreter.
+
+Build the shared library with `cargo build --release`, then point
+BRAINFUCK_LIB at it if it is not in target/release next to this directory.
+
+    >>> import brainfuck
+    >>> brainfuck.run(",[.,]", b"hi")
+    b'hi'
+"""
+
+import ctypes
+import os
+import sys
+
+OK = 0
+DONE = 1
+_ERRORS = {
+    -1: "null argument",
+    -2: "the program is not valid UTF-8",
+    -3: "unmatched '['",
+    -4: "unmatched ']'",
+    -5: "pointer moved below cell 0",
+    -6: "could not write the output",
+    -7: "interrupted",
+    -8: "step limit exceeded",
+    -9: "could not read the input",
+}
+
+
+def _library_path():
+    if "BRAINFUCK_LIB" in os.environ:
+        return os.environ["BRAINFUCK_LIB"]
+    name = {"win32": "brainfuck.dll", "darwin": "libbrainfuck.dylib"}.get(
+        sys.platform, "libbrainfuck.so"
+    )
+    root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+    return os.path.join(root, "target", "release", name)
+
+
+_lib = ctypes.CDLL(_library_path())
+_machine = ctypes.c_void_p
+_size = ctypes.c_size_t
+_bytes = ctypes.POINTER(ctypes.c_uint8)
+for name, args, result in [
+    ("bf_compile", [ctypes.c_char_p, ctypes.POINTER(_machine)], ctypes.c_int),
+    ("bf_free", [_machine], None),
+    ("bf_set_input", [_machine, ctypes.c_char_p, _size], ctypes.c_int),
+    ("bf_set_step_limit", [_machine, ctypes.c_uint64], ctypes.c_int),
+    ("bf_step", [_machine], ctypes.c_int),
+    ("bf_run", [_machine], ctypes.c_int),
+    ("bf_get_tape", [_machine, ctypes.POINTER(_bytes), ctypes.POINTER(_size)], _size),
+    ("bf_get_output", [_machine, ctypes.POINTER(_bytes)], _size),
+    ("bf_error_location", [_machine, ctypes.POINTER(_size), ctypes.POINTER(_size)], ctypes.c_int),
+    ("bf_error_message", [_machine], ctypes.c_char_p),
+]:
+    function = getattr(_lib, name)
+    function.argtypes = args
+    function.restype = result
+
+
+class BrainfuckError(Exception):
+    """A failed run; `line` and `col` are None for errors before it starts."""
+
+    def __init__(self, code, line=None, col=None, message=None):
+        if message is None:
+            message = _ERRORS.get(code, "error %d" % code)
+        if line is not None:
+            message = "%s at %d:%d" % (message, line, col)
+        super().__init__(message)
+        self.code = code
+        self.line = line
+        self.col = col
+
+
+class Interpreter:
+    """A compiled program with its tape. `,` reads 0 after the input.
+
+    `observer`, if given, is called as observer(interpreter) after every
+    command run by step or run, for tracing or animating the tape.
+    """
+
+    def __init__(self, source, input=b"", step_limit=0, observer=None):
+        self._machine = _machine()
+        code = _lib.bf_compile(source.encode("utf-8"), ctypes.byref(self._machine))
+        if code != OK:
+            raise BrainfuckError(code)
+        self.observer = observer
+        self.set_input(input)
+        _lib.bf_set_step_limit(self._machine, step_limit)
+
+    def __del__(self):
+        if getattr(self, "_machine", None):
+            _lib.bf_free(self._machine)
+            self._machine = None
+
+    def set_input(self, data):
+        _lib.bf_set_input(self._machine, bytes(data), len(data))
+
+    def _check(self, code):
+        if code < 0:
+            line, col = _size(), _size()
+            _lib.bf_error_location(self._machine, ctypes.byref(line), ctypes.byref(col))
+            message = _lib.bf_error_message(self._machine)
+            raise BrainfuckError(
+                code, line.value, col.value, message and message.decode("utf-8", "replace")
+            )
+        return code
+
+    def step(self):
+        """Runs one command. Returns False once the program has ended."""
+        running = self._check(_lib.bf_step(self._machine)) == OK
+        if running and self.observer:
+            self.observer(self)
+        return running
+
+    def run(self):
+        """Runs to the end and returns the whole output."""
+        if self.observer:
+            while self.step():
+                pass
+        else:
+            self._check(_lib.bf_run(self._machine))
+        return self.output
+
+    @property
+    def tape(self):
+        cells, pointer = _bytes(), _size()
+        n = _lib.bf_get_tape(self._machine, ctypes.byref(cells), ctypes.byref(pointer))
+        return bytes(cells[:n])
+
+    @property
+    def pointer(self):
+        pointer = _size()
+        _lib.bf_get_tape(self._machine, None, ctypes.byref(pointer))
+        return pointer.value
+
+    @property
+    def output(self):
+        data = _bytes()
+        n = _lib.bf_get_output(self._machine, ctypes.byref(data))
+        return bytes(data[:n])
+
+
+def run(source, input=b"", step_limit=0):
+    """Runs a program and returns its output."""
+    return Interpreter(source, input, step_limit).run()
@@ -0,0 +1,42 @@
+"""Tests of the Python bindings. Run after `cargo build --release` with
+
+    python3 -m unittest discover python
+"""
+
+import unittest
+
+import brainfuck
+
+
+class TestBrainfuck(unittest.TestCase):
+    def test_run(self):
+        self.assertEqual(brainfuck.run(",[.,]", b"hi"), b"hi")
+        self.assertEqual(brainfuck.run("++++++++[>++++++++<-]>+."), b"A")
+
+    def test_step(self):
+        seen = []
+        machine = brainfuck.Interpreter(
+            "+>++", observer=lambda m: seen.append(m.pointer)
+        )
+        self.assertEqual(machine.run(), b"")
+        self.assertEqual(machine.tape, b"\x01\x02")
+        self.assertEqual(machine.pointer, 1)
+        self.assertEqual(seen, [0, 1, 1, 1])
+
+    def test_errors(self):
+        with self.assertRaises(brainfuck.BrainfuckError) as caught:
+            brainfuck.run("[")
+        self.assertEqual(caught.exception.code, -3)
+        with self.assertRaises(brainfuck.BrainfuckError) as caught:
+            brainfuck.run("+\n<")
+        self.assertEqual(caught.exception.code, -5)
+        self.assertEqual((caught.exception.line, caught.exception.col), (2, 1))
+        with self.assertRaises(brainfuck.BrainfuckError) as caught:
+            brainfuck.run("+[]", step_limit=100)
+        self.assertEqual(caught.exception.code, -8)
+        # the message comes from the library
+        self.assertEqual(str(caught.exception), "step limit exceeded at 1:3")
+
+
+if __name__ == "__main__":
+    unittest.main()
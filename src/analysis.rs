@@ -0,0 +1,233 @@
+use crate::interpreter::{InterpreterError, InterpreterErrorKind};
+use crate::lexer::{Annotation, Program, TokenKind};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LoopKind {
+    // [-] or [+]
+    Clear,
+    // [>] or [<<] etc. moves until it finds a zero cell
+    Scan(isize),
+    // [->+>++<<] etc. adds multiples of the current cell to the others
+    Multiply(Vec<(isize, i32)>),
+    General,
+}
+
+impl LoopKind {
+    pub fn describe(&self) -> String {
+        match self {
+            LoopKind::Clear => "clear loop (sets the current cell to 0)".to_string(),
+            LoopKind::Scan(step) => format!("scan loop (moves by {} until a zero cell)", step),
+            LoopKind::Multiply(targets) => {
+                let targets: Vec<String> = targets
+                    .iter()
+                    .map(|(offset, factor)| format!("cell[{:+}] += {} * cell", offset, factor))
+                    .collect();
+                format!("multiply loop ({})", targets.join(", "))
+            }
+            LoopKind::General => "general loop".to_string(),
+        }
+    }
+}
+
+pub fn match_brackets(program: &Program) -> Result<HashMap<usize, usize>, InterpreterError> {
+    match_brackets_by(program, |&kind| Some(kind))
+}
+
+// For languages that add commands to brainfuck: `kind` tells which of theirs
+// are brainfuck commands.
+pub fn match_brackets_by<T>(
+    program: &[Annotation<T>],
+    kind: impl Fn(&T) -> Option<TokenKind>,
+) -> Result<HashMap<usize, usize>, InterpreterError> {
+    let mut jump_table = HashMap::new();
+    let mut forward_brackets = vec![];
+    for (i, token) in program.iter().enumerate() {
+        match kind(&token.value) {
+            Some(TokenKind::JumpForward) => forward_brackets.push(i),
+            Some(TokenKind::JumpBackward) => {
+                if let Some(forward) = forward_brackets.pop() {
+                    jump_table.insert(i, forward);
+                    jump_table.insert(forward, i);
+                } else {
+                    return Err(InterpreterError {
+                        value: InterpreterErrorKind::UnmatchedJumpBackwardError,
+                        location: token.location,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(&forward) = forward_brackets.first() {
+        return Err(InterpreterError {
+            value: InterpreterErrorKind::UnmatchedJumpForwardError,
+            location: program[forward].location,
+        });
+    }
+    Ok(jump_table)
+}
+
+// For BracketMode::Permissive: an unmatched `[` jumps to the end of the
+// program, program.len(), and an unmatched `]` is left out.
+pub fn match_brackets_permissive(program: &Program) -> HashMap<usize, usize> {
+    let mut jump_table = HashMap::new();
+    let mut forward_brackets = vec![];
+    for (i, token) in program.iter().enumerate() {
+        match token.value {
+            TokenKind::JumpForward => forward_brackets.push(i),
+            TokenKind::JumpBackward => {
+                if let Some(forward) = forward_brackets.pop() {
+                    jump_table.insert(i, forward);
+                    jump_table.insert(forward, i);
+                }
+            }
+            _ => {}
+        }
+    }
+    for forward in forward_brackets {
+        jump_table.insert(forward, program.len());
+    }
+    jump_table
+}
+
+// Unlike match_brackets, reports every unmatched bracket instead of the first one.
+pub fn unmatched_brackets(program: &Program) -> Vec<InterpreterError> {
+    let mut errors = vec![];
+    let mut forward_brackets = vec![];
+    for (i, token) in program.iter().enumerate() {
+        match token.value {
+            TokenKind::JumpForward => forward_brackets.push(i),
+            TokenKind::JumpBackward if forward_brackets.pop().is_none() => {
+                errors.push(InterpreterError {
+                    value: InterpreterErrorKind::UnmatchedJumpBackwardError,
+                    location: token.location,
+                });
+            }
+            _ => {}
+        }
+    }
+    for forward in forward_brackets {
+        errors.push(InterpreterError {
+            value: InterpreterErrorKind::UnmatchedJumpForwardError,
+            location: program[forward].location,
+        });
+    }
+    errors.sort_by_key(|e| (e.location.line, e.location.col));
+    errors
+}
+
+// Fails at the first `[` opening a loop inside `limit` others.
+pub fn check_nesting(program: &Program, limit: usize) -> Result<(), InterpreterError> {
+    let mut depth: usize = 0;
+    for token in program {
+        match token.value {
+            TokenKind::JumpForward if depth == limit => {
+                return Err(InterpreterError {
+                    value: InterpreterErrorKind::NestingTooDeep,
+                    location: token.location,
+                });
+            }
+            TokenKind::JumpForward => depth += 1,
+            TokenKind::JumpBackward => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Number of loops enclosing each token. A bracket counts the loop it delimits.
+pub fn nesting_depths(program: &Program) -> Vec<usize> {
+    let mut depths = Vec::with_capacity(program.len());
+    let mut depth: usize = 0;
+    for token in program {
+        match token.value {
+            TokenKind::JumpForward => {
+                depth += 1;
+                depths.push(depth);
+            }
+            TokenKind::JumpBackward => {
+                depths.push(depth);
+                depth = depth.saturating_sub(1);
+            }
+            _ => depths.push(depth),
+        }
+    }
+    depths
+}
+
+// `start` and `end` are the indices of a matching `[` and `]`.
+pub fn classify_loop(program: &Program, start: usize, end: usize) -> LoopKind {
+    let body = &program[start + 1..end];
+    let mut offset: isize = 0;
+    let mut deltas: Vec<(isize, i32)> = vec![];
+    for token in body {
+        match token.value {
+            TokenKind::IncrementPointer => offset += 1,
+            TokenKind::DecrementPointer => offset -= 1,
+            TokenKind::Increment | TokenKind::Decrement => {
+                let d = if token.value == TokenKind::Increment {
+                    1
+                } else {
+                    -1
+                };
+                match deltas.iter_mut().find(|(o, _)| *o == offset) {
+                    Some(entry) => entry.1 += d,
+                    None => deltas.push((offset, d)),
+                }
+            }
+            _ => return LoopKind::General,
+        }
+    }
+    deltas.retain(|(_, d)| *d != 0);
+    if offset != 0 {
+        if deltas.is_empty() {
+            return LoopKind::Scan(offset);
+        }
+        return LoopKind::General;
+    }
+    let counter = deltas.iter().find(|(o, _)| *o == 0).map(|(_, d)| *d);
+    let sign = match counter {
+        Some(-1) => 1,
+        Some(1) => -1,
+        _ => return LoopKind::General,
+    };
+    let mut targets: Vec<(isize, i32)> = deltas
+        .into_iter()
+        .filter(|(o, _)| *o != 0)
+        .map(|(o, d)| (o, d * sign))
+        .collect();
+    if targets.is_empty() {
+        return LoopKind::Clear;
+    }
+    targets.sort_by_key(|(o, _)| *o);
+    LoopKind::Multiply(targets)
+}
+
+#[test]
+fn test_analysis() {
+    use crate::lexer::Lexer;
+    let program = Lexer::lex("+[-]>[>]<[->++<]");
+    let jump_table = match_brackets(&program).unwrap();
+    assert_eq!(jump_table[&1], 3);
+    assert_eq!(classify_loop(&program, 1, 3), LoopKind::Clear);
+    assert_eq!(classify_loop(&program, 5, 7), LoopKind::Scan(1));
+    assert_eq!(
+        classify_loop(&program, 9, 15),
+        LoopKind::Multiply(vec![(1, 2)])
+    );
+    assert_eq!(nesting_depths(&program)[..4], [0, 1, 1, 1]);
+    assert_eq!(check_nesting(&program, 1), Ok(()));
+    let error = check_nesting(&Lexer::lex("[][[[]]]"), 2).unwrap_err();
+    assert_eq!(
+        (error.value, error.location.col),
+        (InterpreterErrorKind::NestingTooDeep, 5)
+    );
+
+    let errors = unmatched_brackets(&Lexer::lex("]["));
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0].value,
+        InterpreterErrorKind::UnmatchedJumpBackwardError
+    );
+}
@@ -0,0 +1,211 @@
+use crate::interpreter::{EofBehavior, InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::lexer::{Location, Program, TokenKind};
+use crate::spec::Capture;
+use std::future::{poll_fn, Future};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// Commands run between two yields to the executor.
+pub const YIELD_INTERVAL: u64 = 10_000;
+
+// Where `,` reads from without blocking the thread. Returns None at EOF.
+// These are small enough to implement on top of any runtime's reader; there
+// is no tokio feature implementing them for AsyncRead.
+pub trait AsyncByteRead {
+    fn poll_read_byte(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<u8>>>;
+}
+
+// Where `.` writes to without blocking the thread.
+pub trait AsyncByteWrite {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+// Makes a reader or writer that never blocks, such as a Cursor or a Vec,
+// usable as an async one.
+pub struct Ready<T>(pub T);
+
+impl<R: Read> AsyncByteRead for Ready<R> {
+    fn poll_read_byte(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<Option<u8>>> {
+        let mut byte = [0];
+        Poll::Ready(match self.0.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) => Err(e),
+        })
+    }
+}
+
+impl<W: Write> AsyncByteWrite for Ready<W> {
+    fn poll_write(&mut self, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.0.write(buf))
+    }
+    fn poll_flush(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.0.flush())
+    }
+}
+
+// Returns Pending once so that other tasks get to run.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// Runs programs on the simple engine as a future: `,` and `.` await the
+// reader and writer, and the run yields every YIELD_INTERVAL commands, so a
+// long program does not hold up an async worker thread.
+pub struct AsyncInterpreter {
+    machine: SimpleInterpreter,
+    output: Capture,
+}
+
+impl Default for AsyncInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncInterpreter {
+    pub fn new() -> Self {
+        let output = Capture::default();
+        let mut machine = SimpleInterpreter::new();
+        machine.set_output(Box::new(output.clone()));
+        Self { machine, output }
+    }
+
+    // For the step limit, the EOF behavior, the tape...
+    pub fn machine(&self) -> &SimpleInterpreter {
+        &self.machine
+    }
+    pub fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        &mut self.machine
+    }
+
+    // A read error ends the input, as EOF does.
+    pub async fn eval<R: AsyncByteRead, W: AsyncByteWrite>(
+        &mut self,
+        program: &Program,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<usize, InterpreterError> {
+        self.machine.load(program)?;
+        let mut since_yield = 0;
+        while let Some(command) = self.machine.next_command() {
+            let location = self.machine.next_location().unwrap();
+            if since_yield == YIELD_INTERVAL {
+                YieldNow(false).await;
+                since_yield = 0;
+            }
+            since_yield += 1;
+            if command == TokenKind::Input {
+                // the bytes written so far may be a prompt
+                flush(output).await.map_err(|_| output_error(location))?;
+                let byte = poll_fn(|cx| input.poll_read_byte(cx)).await;
+                self.machine.set_input_bytes(byte.ok().flatten());
+            }
+            self.machine.step()?;
+            if command == TokenKind::Output {
+                let bytes = std::mem::take(&mut *self.output.0.lock().unwrap());
+                write_all(output, &bytes)
+                    .await
+                    .map_err(|_| output_error(location))?;
+            }
+        }
+        let last = program.last().map(|t| t.location);
+        match (flush(output).await, last) {
+            (Err(_), Some(location)) => Err(output_error(location)),
+            _ => Ok(0),
+        }
+    }
+}
+
+fn output_error(location: Location) -> InterpreterError {
+    InterpreterError {
+        value: InterpreterErrorKind::OutputError,
+        location,
+    }
+}
+
+async fn write_all<W: AsyncByteWrite>(output: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = poll_fn(|cx| output.poll_write(cx, buf)).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+    }
+    Ok(())
+}
+
+async fn flush<W: AsyncByteWrite>(output: &mut W) -> io::Result<()> {
+    poll_fn(|cx| output.poll_flush(cx)).await
+}
+
+// Reads with EOF Zero, like a filter.
+pub async fn run<R: AsyncByteRead, W: AsyncByteWrite>(
+    program: &Program,
+    input: &mut R,
+    output: &mut W,
+) -> Result<usize, InterpreterError> {
+    let mut interpreter = AsyncInterpreter::new();
+    interpreter
+        .machine_mut()
+        .set_eof_behavior(EofBehavior::Zero);
+    interpreter.eval(program, input, output).await
+}
+
+#[test]
+fn test_async_interpreter() {
+    use crate::lexer::Lexer;
+    use std::task::Waker;
+
+    // polls until done, counting the yields
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    let mut output = Ready(Vec::new());
+    let program = Lexer::lex(",[+.,]");
+    let (result, _) = block_on(run(&program, &mut Ready(&b"HAL"[..]), &mut output));
+    assert_eq!(result, Ok(0));
+    assert_eq!(output.0, b"IBM");
+
+    // 40 * (1 + 255 * 3) commands
+    let program = Lexer::lex(&"-[-]".repeat(40));
+    let (result, yields) = block_on(run(
+        &program,
+        &mut Ready(io::empty()),
+        &mut Ready(io::sink()),
+    ));
+    assert_eq!(result, Ok(0));
+    assert_eq!(yields, 3);
+
+    let (result, _) = block_on(run(
+        &Lexer::lex("+.<"),
+        &mut Ready(io::empty()),
+        &mut output,
+    ));
+    assert_eq!(
+        result.map_err(|e| e.value),
+        Err(InterpreterErrorKind::PointerError)
+    );
+    assert_eq!(output.0, b"IBM\x01");
+}
@@ -0,0 +1,148 @@
+use crate::compiled::CompiledProgram;
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterError};
+use crate::lexer::Program;
+use crate::spec::{Capture, DEFAULT_STEP_LIMIT};
+use std::thread;
+
+// What the program did with one input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    pub output: Vec<u8>,
+    pub result: Result<usize, InterpreterError>,
+    pub steps: u64,
+}
+
+// Runs one program against many inputs, as a judge does with the test cases
+// of a submission. The program is checked and optimized once for all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batch {
+    pub engine: EngineKind,
+    pub eof: EofBehavior,
+    // per input
+    pub step_limit: Option<u64>,
+    // 1 runs the inputs one after another on the calling thread
+    pub threads: usize,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            engine: EngineKind::Auto,
+            eof: EofBehavior::Zero,
+            step_limit: Some(DEFAULT_STEP_LIMIT),
+            threads: 1,
+        }
+    }
+}
+
+impl Batch {
+    // The results are in the order of the inputs.
+    pub fn run(&self, program: &Program, inputs: &[Vec<u8>]) -> Vec<RunResult> {
+        let compiled = match CompiledProgram::new(program.clone()) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                let failed = RunResult {
+                    output: Vec::new(),
+                    result: Err(e),
+                    steps: 0,
+                };
+                return vec![failed; inputs.len()];
+            }
+        };
+        let threads = self.threads.clamp(1, inputs.len().max(1));
+        if threads == 1 {
+            return inputs
+                .iter()
+                .map(|input| self.run_one(&compiled, input))
+                .collect();
+        }
+        let chunk = inputs.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = inputs
+                .chunks(chunk)
+                .map(|inputs| {
+                    let compiled = &compiled;
+                    scope.spawn(move || {
+                        inputs
+                            .iter()
+                            .map(|input| self.run_one(compiled, input))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        })
+    }
+
+    fn run_one(&self, compiled: &CompiledProgram, input: &[u8]) -> RunResult {
+        let output = Capture::default();
+        let mut engine = self.engine.create();
+        let machine = engine.machine_mut();
+        machine.set_output(Box::new(output.clone()));
+        machine.set_input_bytes(input.iter().copied());
+        machine.set_eof_behavior(self.eof);
+        machine.set_step_limit(self.step_limit);
+        let result = engine.eval_compiled(compiled);
+        let steps = engine.machine().steps();
+        let output = output.0.lock().unwrap().clone();
+        RunResult {
+            output,
+            result,
+            steps,
+        }
+    }
+}
+
+// Runs the program against each input with the defaults of Batch.
+pub fn run_many(program: &Program, inputs: &[Vec<u8>]) -> Vec<RunResult> {
+    Batch::default().run(program, inputs)
+}
+
+#[test]
+fn test_run_many() {
+    use crate::interpreter::InterpreterErrorKind;
+    use crate::lexer::Lexer;
+
+    let program = Lexer::lex(",[+.,]");
+    let inputs = vec![b"HAL".to_vec(), vec![], b"a".to_vec()];
+    let results = run_many(&program, &inputs);
+    let outputs: Vec<&[u8]> = results.iter().map(|r| &r.output[..]).collect();
+    assert_eq!(outputs, vec![&b"IBM"[..], b"", b"b"]);
+    assert!(results.iter().all(|r| r.result == Ok(0)));
+
+    for engine in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let batch = Batch {
+            engine: *engine,
+            threads: 2,
+            ..Batch::default()
+        };
+        // the engines count steps differently
+        let runs = batch.run(&program, &inputs);
+        assert!(runs
+            .iter()
+            .zip(&results)
+            .all(|(a, b)| (&a.output, &a.result) == (&b.output, &b.result)));
+    }
+
+    let batch = Batch {
+        step_limit: Some(100),
+        ..Batch::default()
+    };
+    let results = batch.run(&Lexer::lex(",[]"), &[vec![0], vec![1]]);
+    assert_eq!(results[0].result, Ok(0));
+    assert_eq!(
+        results[1].result.as_ref().map_err(|e| e.value),
+        Err(InterpreterErrorKind::StepLimitExceeded)
+    );
+
+    let results = run_many(&Lexer::lex("+]"), &[vec![], vec![]]);
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[1].result.as_ref().map_err(|e| e.value),
+        Err(InterpreterErrorKind::UnmatchedJumpBackwardError)
+    );
+}
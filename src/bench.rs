@@ -0,0 +1,80 @@
+use crate::clock::{Clock, SystemClock};
+use crate::engine::EngineKind;
+use crate::interpreter::InterpreterError;
+use crate::lexer::Program;
+use std::io;
+use std::time::Duration;
+
+// Timings of repeated runs of one program, sorted from the fastest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    pub engine: EngineKind,
+    pub timings: Vec<Duration>,
+    // instructions executed by one run
+    pub steps: u64,
+}
+
+impl BenchResult {
+    pub fn min(&self) -> Duration {
+        self.timings[0]
+    }
+    pub fn median(&self) -> Duration {
+        self.timings[self.timings.len() / 2]
+    }
+    pub fn max(&self) -> Duration {
+        self.timings[self.timings.len() - 1]
+    }
+    pub fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.median().as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+// Runs the program `runs` times (at least once) on the same input. The output is discarded.
+pub fn bench(
+    program: &Program,
+    engine: EngineKind,
+    input: &[u8],
+    runs: usize,
+) -> Result<BenchResult, InterpreterError> {
+    bench_with_clock(program, engine, input, runs, &SystemClock::default())
+}
+
+pub fn bench_with_clock(
+    program: &Program,
+    engine: EngineKind,
+    input: &[u8],
+    runs: usize,
+    clock: &dyn Clock,
+) -> Result<BenchResult, InterpreterError> {
+    let mut interpreter = engine.create();
+    interpreter.machine_mut().set_output(Box::new(io::sink()));
+    let mut timings = Vec::with_capacity(runs.max(1));
+    for _ in 0..runs.max(1) {
+        interpreter.machine_mut().set_input_bytes(input.to_vec());
+        let started = clock.now();
+        interpreter.eval(program)?;
+        timings.push(clock.now() - started);
+    }
+    timings.sort();
+    Ok(BenchResult {
+        engine: interpreter.kind(),
+        timings,
+        steps: interpreter.machine().steps(),
+    })
+}
+
+#[test]
+fn test_bench() {
+    use crate::lexer::Lexer;
+    let program = Lexer::lex("++[->+++<]>.,");
+    let result = bench(&program, EngineKind::Simple, b"x", 3).unwrap();
+    assert_eq!(result.engine, EngineKind::Simple);
+    assert_eq!(result.timings.len(), 3);
+    assert!(result.min() <= result.median() && result.median() <= result.max());
+    assert_eq!(result.steps, 2 + 1 + 2 * 7 + 1 + 1 + 1 + 1);
+    assert!(bench(&Lexer::lex("<"), EngineKind::Optimized, b"", 3).is_err());
+
+    let clock = crate::clock::ManualClock::default();
+    let result = bench_with_clock(&program, EngineKind::Simple, b"x", 2, &clock).unwrap();
+    assert_eq!(result.timings, vec![Duration::ZERO; 2]);
+}
@@ -0,0 +1,250 @@
+use crate::analysis;
+use crate::input::{InputSource, ReaderInput};
+use crate::interpreter::{InterpreterError, InterpreterErrorKind};
+use crate::lexer::{Annotation, Lexer, Location, Program, TokenKind};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+// Boolfuck has brainfuck's commands on a tape of bits: `+` flips the current
+// bit and `;` takes the place of `.`. Programs are read into the token kinds
+// of brainfuck, `;` becoming Output, so that the analysis shared with
+// brainfuck applies; render spells them back.
+pub fn lex(input: &str) -> Program {
+    Lexer::scan(input, 0, |c| match c {
+        b';' => Some(TokenKind::Output),
+        b'+' | b',' | b'<' | b'>' | b'[' | b']' => TokenKind::from_char(c as char),
+        _ => None,
+    })
+}
+
+// One line per line of the program.
+pub fn render(program: &Program) -> String {
+    let mut text = String::new();
+    for (i, token) in program.iter().enumerate() {
+        if i > 0 && token.location.line != program[i - 1].location.line {
+            text.push('\n');
+        }
+        text.push(match token.value {
+            TokenKind::Output => ';',
+            kind => kind.to_string().chars().next().unwrap_or(' '),
+        });
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+// The usual translation: each brainfuck cell is 9 bits, a scratch bit
+// followed by the value, least significant bit first. Extensions such as
+// `%` have no translation and are dropped. The commands keep the location
+// of the brainfuck command they come from.
+pub fn from_brainfuck(program: &Program) -> Program {
+    let mut translated = Vec::new();
+    for token in program {
+        let code = match token.value {
+            TokenKind::Increment => ">[>]+<[+<]>>>>>>>>>[+]<<<<<<<<<",
+            TokenKind::Decrement => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>[+]<<<<<<<<<",
+            TokenKind::IncrementPointer => ">>>>>>>>>",
+            TokenKind::DecrementPointer => "<<<<<<<<<",
+            TokenKind::Output => ">;>;>;>;>;>;>;>;<<<<<<<<",
+            TokenKind::Input => ">,>,>,>,>,>,>,>,<<<<<<<<",
+            TokenKind::JumpForward => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>[+<<<<<<<<[>]+<[+<]",
+            TokenKind::JumpBackward => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>]<[+<]",
+            _ => "",
+        };
+        translated.extend(lex(code).into_iter().map(|t| Annotation {
+            value: t.value,
+            location: token.location,
+        }));
+    }
+    translated
+}
+
+// Runs Boolfuck. The tape is unbounded in both directions. `,` reads the
+// bits of each input byte from the least significant one, and reads 0 once
+// the input has ended; `;` collects bits the same way and writes a byte
+// every 8 of them. Bits left over at the end are written padded with zeros.
+pub struct BoolfuckInterpreter {
+    bits: VecDeque<bool>,
+    pointer: usize,
+    input: Box<dyn InputSource + Send>,
+    // the byte being read, and how many of its bits are left
+    input_byte: u8,
+    input_bits: u32,
+    output: Box<dyn Write + Send>,
+    output_byte: u8,
+    output_bits: u32,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl Default for BoolfuckInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BoolfuckInterpreter {
+    // Reads stdin as bytes and writes to stdout.
+    pub fn new() -> Self {
+        Self {
+            bits: VecDeque::from(vec![false]),
+            pointer: 0,
+            input: Box::new(ReaderInput::new(io::stdin())),
+            input_byte: 0,
+            input_bits: 0,
+            output: Box::new(io::stdout()),
+            output_byte: 0,
+            output_bits: 0,
+            steps: 0,
+            step_limit: None,
+        }
+    }
+    pub fn set_input_source(&mut self, input: Box<dyn InputSource + Send>) {
+        self.input = input;
+    }
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn eval(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        let jump_table = analysis::match_brackets(program)?;
+        self.bits = VecDeque::from(vec![false]);
+        self.pointer = 0;
+        self.input_bits = 0;
+        self.output_bits = 0;
+        self.output_byte = 0;
+        self.steps = 0;
+        let mut pc = 0;
+        while pc < program.len() {
+            let location = program[pc].location;
+            if self.step_limit == Some(self.steps) {
+                return Err(InterpreterError {
+                    value: InterpreterErrorKind::StepLimitExceeded,
+                    location,
+                });
+            }
+            self.steps += 1;
+            match program[pc].value {
+                TokenKind::Increment => self.bits[self.pointer] ^= true,
+                TokenKind::IncrementPointer => {
+                    self.pointer += 1;
+                    if self.pointer == self.bits.len() {
+                        self.bits.push_back(false);
+                    }
+                }
+                TokenKind::DecrementPointer if self.pointer == 0 => self.bits.push_front(false),
+                TokenKind::DecrementPointer => self.pointer -= 1,
+                TokenKind::Output => self.write_bit(location)?,
+                TokenKind::Input => self.bits[self.pointer] = self.read_bit(location)?,
+                TokenKind::JumpForward if !self.bits[self.pointer] => pc = jump_table[&pc],
+                TokenKind::JumpBackward if self.bits[self.pointer] => pc = jump_table[&pc],
+                _ => {}
+            }
+            pc += 1;
+        }
+        let location = program.last().map(|t| t.location);
+        if self.output_bits > 0 {
+            let byte = self.output_byte;
+            self.output_bits = 0;
+            self.write(byte, location)?;
+        }
+        if self.output.flush().is_err() {
+            return Err(output_error(location));
+        }
+        Ok(0)
+    }
+
+    fn read_bit(&mut self, location: Location) -> Result<bool, InterpreterError> {
+        if self.input_bits == 0 {
+            self.input_byte = match self.input.read_byte() {
+                Ok(byte) => byte.unwrap_or(0),
+                Err(e) => {
+                    return Err(InterpreterError {
+                        value: InterpreterErrorKind::InputError(e.kind()),
+                        location,
+                    })
+                }
+            };
+            self.input_bits = 8;
+        }
+        let bit = self.input_byte & 1 == 1;
+        self.input_byte >>= 1;
+        self.input_bits -= 1;
+        Ok(bit)
+    }
+
+    fn write_bit(&mut self, location: Location) -> Result<(), InterpreterError> {
+        if self.bits[self.pointer] {
+            self.output_byte |= 1 << self.output_bits;
+        }
+        self.output_bits += 1;
+        if self.output_bits == 8 {
+            let byte = self.output_byte;
+            self.output_byte = 0;
+            self.output_bits = 0;
+            self.write(byte, Some(location))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, byte: u8, location: Option<Location>) -> Result<(), InterpreterError> {
+        self.output
+            .write_all(&[byte])
+            .map_err(|_| output_error(location))
+    }
+}
+
+fn output_error(location: Option<Location>) -> InterpreterError {
+    InterpreterError {
+        value: InterpreterErrorKind::OutputError,
+        location: location.unwrap_or(Location {
+            line: 1,
+            col: 1,
+            file: 0,
+            offset: 0,
+            end: 0,
+        }),
+    }
+}
+
+#[test]
+fn test_boolfuck() {
+    use crate::input::BytesInput;
+    use crate::spec::Capture;
+
+    let run = |program: &Program, input: &str| {
+        let output = Capture::default();
+        let mut interpreter = BoolfuckInterpreter::new();
+        interpreter.set_input_source(Box::new(BytesInput::from(input)));
+        interpreter.set_output(Box::new(output.clone()));
+        interpreter.set_step_limit(Some(10_000_000));
+        let result = interpreter.eval(program);
+        let output = output.0.lock().unwrap().clone();
+        (result, output)
+    };
+
+    // 'A' is 0b01000001, written from the lowest bit; the last bit is padded
+    let program = lex("+;+;;;;;+;+;<+;");
+    assert_eq!(render(&program), "+;+;;;;;+;+;<+;\n");
+    assert_eq!(run(&program, ""), (Ok(0), b"A\x01".to_vec()));
+    // cat, a bit at a time
+    assert_eq!(run(&lex(",;"), "\x03").1, vec![1]);
+
+    // a translated brainfuck program behaves as the original
+    let echo = Lexer::lex("+++[>,+.<-]");
+    let translated = from_brainfuck(&echo);
+    assert_eq!(translated[0].location, echo[0].location);
+    assert_eq!(run(&translated, "HAL"), (Ok(0), b"IBM".to_vec()));
+    assert_eq!(
+        run(&lex("+]"), "").0.map_err(|e| e.value),
+        Err(InterpreterErrorKind::UnmatchedJumpBackwardError)
+    );
+}
@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Where time measurements and timeouts get the time from, so that embedders
+// without a monotonic clock, or tests, can supply their own.
+pub trait Clock: Send + Sync {
+    // Time since an arbitrary origin that stays fixed for the clock.
+    fn now(&self) -> Duration;
+}
+
+// The monotonic clock of the OS.
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+// A clock that only moves when told to. Clones share the time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+#[test]
+fn test_clock() {
+    let clock = ManualClock::default();
+    let shared = clock.clone();
+    assert_eq!(clock.now(), Duration::ZERO);
+    shared.advance(Duration::from_millis(5));
+    assert_eq!(clock.now(), Duration::from_millis(5));
+
+    let system = SystemClock::default();
+    let before = system.now();
+    assert!(system.now() >= before);
+}
@@ -0,0 +1,94 @@
+use crate::analysis;
+use crate::interpreter::{BracketMode, InterpreterError};
+use crate::lexer::Program;
+use crate::optimizer::{self, Code, Cost};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// A program checked and compiled once for both engines, to be run many times
+// or by many engines on as many threads. Clones share it, and
+// Engine::load_compiled takes a reference to it rather than a copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledProgram {
+    pub(crate) program: Arc<Program>,
+    // for the simple engine
+    pub(crate) jump_table: Arc<HashMap<usize, usize>>,
+    // for the optimized one
+    pub(crate) code: Arc<Code>,
+    pub(crate) costs: Arc<Vec<Cost>>,
+}
+
+impl CompiledProgram {
+    pub fn new(program: Program) -> Result<Self, InterpreterError> {
+        Self::with_brackets(program, BracketMode::Strict)
+    }
+
+    // Engines run it with these brackets, whatever their own bracket mode.
+    pub fn with_brackets(program: Program, mode: BracketMode) -> Result<Self, InterpreterError> {
+        let jump_table = match mode {
+            BracketMode::Strict => analysis::match_brackets(&program)?,
+            BracketMode::Permissive => analysis::match_brackets_permissive(&program),
+        };
+        let (code, costs) = optimizer::compile_with(&program, &jump_table);
+        Ok(Self {
+            program: Arc::new(program),
+            jump_table: Arc::new(jump_table),
+            code: Arc::new(code),
+            costs: Arc::new(costs),
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+}
+
+// Puts a copy of the program in `slot`, reusing its buffer unless it is
+// shared.
+pub(crate) fn replace(slot: &mut Arc<Program>, program: &Program) {
+    match Arc::get_mut(slot) {
+        Some(own) => own.clone_from(program),
+        None => *slot = Arc::new(program.clone()),
+    }
+}
+
+#[test]
+fn test_compiled_program() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    use crate::spec::Capture;
+    use std::thread;
+
+    let compiled = CompiledProgram::new(Lexer::lex(",[->+<]>.")).unwrap();
+    let outputs: Vec<Vec<u8>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..4u8)
+            .map(|i| {
+                let compiled = &compiled;
+                scope.spawn(move || {
+                    let kind = [EngineKind::Simple, EngineKind::Optimized][usize::from(i % 2)];
+                    let mut engine = kind.create();
+                    let output = Capture::default();
+                    engine.machine_mut().set_output(Box::new(output.clone()));
+                    engine.machine_mut().set_input_bytes(vec![i]);
+                    engine.load_compiled(compiled);
+                    assert_eq!(engine.run(), Ok(0));
+                    // and again, on a fresh tape
+                    engine.machine_mut().set_input_bytes(vec![i]);
+                    assert_eq!(engine.eval_compiled(compiled), Ok(0));
+                    let output = output.0.lock().unwrap().clone();
+                    output
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    assert_eq!(outputs, [[0, 0], [1, 1], [2, 2], [3, 3]]);
+
+    assert!(CompiledProgram::new(Lexer::lex("[")).is_err());
+    let permissive = CompiledProgram::with_brackets(Lexer::lex("+]."), BracketMode::Permissive);
+    assert_eq!(permissive.unwrap().code().len(), 2);
+}
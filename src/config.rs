@@ -0,0 +1,192 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{BracketMode, Buffering, EofBehavior, OutputEncoding};
+use crate::tape::TapeKind;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "bf.toml";
+
+// Defaults read from bf.toml. Command line flags take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub engine: Option<EngineKind>,
+    pub eof_behavior: Option<EofBehavior>,
+    pub buffering: Option<Buffering>,
+    pub bracket_mode: Option<BracketMode>,
+    pub tape_kind: Option<TapeKind>,
+    pub tape_size: Option<usize>,
+    pub output_encoding: Option<OutputEncoding>,
+    pub warnings: Option<bool>,
+}
+
+impl Config {
+    // Accepts the subset of TOML the settings need: top-level `key = value`
+    // lines with string, integer or boolean values, and `#` comments.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", i + 1))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            let result = match key {
+                "engine" => value.parse().map(|e| config.engine = Some(e)),
+                "eof" => value.parse().map(|e| config.eof_behavior = Some(e)),
+                "buffer" => value.parse().map(|b| config.buffering = Some(b)),
+                "brackets" => value.parse().map(|b| config.bracket_mode = Some(b)),
+                "tape" => value.parse().map(|t| config.tape_kind = Some(t)),
+                "tape_size" => value
+                    .parse()
+                    .ok()
+                    .filter(|&cells| cells > 0)
+                    .map(|cells| config.tape_size = Some(cells))
+                    .ok_or_else(|| format!("tape_size must be a positive number: {}", value)),
+                "encoding" => value.parse().map(|e| config.output_encoding = Some(e)),
+                "warnings" => value
+                    .parse()
+                    .map(|w| config.warnings = Some(w))
+                    .map_err(|_| format!("warnings must be true or false: {}", value)),
+                _ => Err(format!("unknown key: {}", key)),
+            };
+            result.map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Looks for bf.toml next to the script first, then in the working directory.
+pub fn find(script: Option<&Path>) -> Option<PathBuf> {
+    let script_dir = script.and_then(Path::parent).map(|dir| dir.join(FILE_NAME));
+    script_dir
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(FILE_NAME)))
+        .find(|path| path.is_file())
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(rest) = value.strip_prefix('"') {
+        match rest.strip_suffix('"') {
+            Some(inner) if !inner.contains('"') => Ok(inner.to_string()),
+            _ => Err(format!("invalid string: {}", value)),
+        }
+    } else if value.parse::<i64>().is_ok() || value == "true" || value == "false" {
+        Ok(value.to_string())
+    } else {
+        Err(format!("invalid value: {}", value))
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config =
+        Config::parse("# defaults\nengine = \"simple\"\neof = -1 # like C\nbuffer = \"full\"\nbrackets = \"permissive\"\ntape = \"paged\"\n")
+            .unwrap();
+    assert_eq!(
+        config,
+        Config {
+            engine: Some(EngineKind::Simple),
+            eof_behavior: Some(EofBehavior::MinusOne),
+            buffering: Some(Buffering::Full),
+            bracket_mode: Some(BracketMode::Permissive),
+            tape_kind: Some(TapeKind::Paged),
+            ..Config::default()
+        }
+    );
+    assert_eq!(Config::parse(""), Ok(Config::default()));
+    assert_eq!(
+        Config::parse("\ncell_width = 16"),
+        Err("line 2: unknown key: cell_width".to_string())
+    );
+    assert!(Config::parse("engine = fast").is_err());
+    assert!(Config::parse("eof = \"sometimes\"").is_err());
+}
+
+#[test]
+fn test_config_keys() {
+    let key = |line: &str| Config::parse(line).unwrap();
+    let defaults = Config::default();
+    assert_eq!(
+        key("engine = \"optimized\""),
+        Config {
+            engine: Some(EngineKind::Optimized),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("eof = \"unchanged\""),
+        Config {
+            eof_behavior: Some(EofBehavior::Unchanged),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("buffer = \"none\""),
+        Config {
+            buffering: Some(Buffering::Unbuffered),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("brackets = \"strict\""),
+        Config {
+            bracket_mode: Some(BracketMode::Strict),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("tape = \"mapped\""),
+        Config {
+            tape_kind: Some(TapeKind::Mapped),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("tape_size = 30000"),
+        Config {
+            tape_size: Some(30000),
+            ..defaults
+        }
+    );
+    assert!(Config::parse("tape_size = 0").is_err());
+    assert_eq!(
+        key("encoding = \"utf8\""),
+        Config {
+            output_encoding: Some(OutputEncoding::Utf8),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        key("warnings = true"),
+        Config {
+            warnings: Some(true),
+            ..defaults
+        }
+    );
+    assert_eq!(
+        Config::parse("warnings = 1"),
+        Err("line 1: warnings must be true or false: 1".to_string())
+    );
+}
@@ -0,0 +1,71 @@
+use crate::engine::EngineKind;
+use crate::spec::{TestCase, TestResult, DEFAULT_STEP_LIMIT};
+use std::path::PathBuf;
+
+// A classic program with its input and the output it must produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classic {
+    pub name: &'static str,
+    pub source: &'static str,
+    pub input: &'static [u8],
+    pub output: &'static [u8],
+}
+
+// Built into the crate, so that a change to an engine or the optimizer can be
+// checked where the crate is used, without its source tree. The files are in
+// sample_program/, where `brainfuck test` finds them too.
+pub const CLASSICS: [Classic; 4] = [
+    Classic {
+        name: "hello_world",
+        source: include_str!("../sample_program/hello_world.bf"),
+        input: b"",
+        output: include_bytes!("../sample_program/hello_world.out"),
+    },
+    Classic {
+        name: "rot13",
+        source: include_str!("../sample_program/rot13.bf"),
+        input: include_bytes!("../sample_program/rot13.in"),
+        output: include_bytes!("../sample_program/rot13.out"),
+    },
+    Classic {
+        name: "sierpinski",
+        source: include_str!("../sample_program/sierpinski.bf"),
+        input: b"",
+        output: include_bytes!("../sample_program/sierpinski.out"),
+    },
+    Classic {
+        name: "factor",
+        source: include_str!("../sample_program/factor.bf"),
+        input: include_bytes!("../sample_program/factor.in"),
+        output: include_bytes!("../sample_program/factor.out"),
+    },
+];
+
+impl Classic {
+    pub fn test_case(&self) -> TestCase {
+        TestCase {
+            path: PathBuf::from(format!("sample_program/{}.bf", self.name)),
+            source: self.source.to_string(),
+            input: self.input.to_vec(),
+            expected: self.output.to_vec(),
+            step_limit: DEFAULT_STEP_LIMIT,
+        }
+    }
+}
+
+// Runs every classic on the engine, in the order of CLASSICS.
+pub fn check(engine: EngineKind) -> Vec<(&'static str, TestResult)> {
+    CLASSICS
+        .iter()
+        .map(|classic| (classic.name, classic.test_case().run(engine)))
+        .collect()
+}
+
+#[test]
+fn test_corpus() {
+    for engine in [EngineKind::Simple, EngineKind::Optimized] {
+        for (name, result) in check(engine) {
+            assert_eq!(result, TestResult::Passed, "{} on {}", name, engine);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::boolfuck;
+use crate::json::Json;
+use crate::lexer::{Annotation, Lexer, Location, Program, TokenKind};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// Languages that are brainfuck with other spellings of the commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    Brainfuck,
+    // each command is a pair of "Ook.", "Ook?" and "Ook!"
+    Ook,
+    // not a spelling but a translation to bits; see the boolfuck module.
+    // Programs can be written in it but not read from it.
+    Boolfuck,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bf" | "brainfuck" => Ok(Dialect::Brainfuck),
+            "ook" => Ok(Dialect::Ook),
+            "bool" | "boolfuck" => Ok(Dialect::Boolfuck),
+            _ => Err(format!("unknown dialect: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Dialect::Brainfuck => write!(f, "bf"),
+            Dialect::Ook => write!(f, "ook"),
+            Dialect::Boolfuck => write!(f, "bool"),
+        }
+    }
+}
+
+const OOK: [(TokenKind, &str); 8] = [
+    (TokenKind::IncrementPointer, "Ook. Ook?"),
+    (TokenKind::DecrementPointer, "Ook? Ook."),
+    (TokenKind::Increment, "Ook. Ook."),
+    (TokenKind::Decrement, "Ook! Ook!"),
+    (TokenKind::Output, "Ook! Ook."),
+    (TokenKind::Input, "Ook. Ook!"),
+    (TokenKind::JumpForward, "Ook! Ook?"),
+    (TokenKind::JumpBackward, "Ook? Ook!"),
+];
+// commands per line when writing Ook!
+const OOK_LINE_LENGTH: usize = 8;
+
+impl Dialect {
+    // Guesses the dialect from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "bf" | "b" => Some(Dialect::Brainfuck),
+            "ook" => Some(Dialect::Ook),
+            "bool" => Some(Dialect::Boolfuck),
+            _ => None,
+        }
+    }
+
+    // Reads the commands of a program. Locations point into `source`.
+    pub fn parse(self, source: &str) -> Result<Program, String> {
+        match self {
+            Dialect::Brainfuck => Ok(Lexer::lex(source)),
+            Dialect::Ook => parse_ook(source),
+            Dialect::Boolfuck => {
+                Err("Boolfuck cannot be read as brainfuck; run it with `boolfuck`".to_string())
+            }
+        }
+    }
+
+    // Renders the program of `source` in the dialect `to`, with the map from
+    // the translation back to `source`. Boolfuck cannot be mapped, as it
+    // cannot be read back.
+    pub fn translate(self, source: &str, to: Dialect) -> Result<(String, SourceMap), String> {
+        let original = self.parse(source)?;
+        let translated = to.render(&original);
+        let origins = to
+            .parse(&translated)?
+            .into_iter()
+            .zip(original)
+            .map(|(to, from)| (to.location, from.location))
+            .collect();
+        let map = SourceMap {
+            source: String::new(),
+            origins,
+        };
+        Ok((translated, map))
+    }
+
+    pub fn render(self, program: &Program) -> String {
+        match self {
+            Dialect::Brainfuck => program.iter().map(|t| t.value.to_string()).collect(),
+            Dialect::Ook => {
+                // Ook! has no host calls or second tape
+                let commands: Vec<TokenKind> = program
+                    .iter()
+                    .map(|t| t.value)
+                    .filter(|&c| OOK.iter().any(|(k, _)| *k == c))
+                    .collect();
+                let lines: Vec<String> = commands
+                    .chunks(OOK_LINE_LENGTH)
+                    .map(|chunk| {
+                        let words: Vec<&str> = chunk.iter().map(|&c| ook_word(c)).collect();
+                        words.join(" ")
+                    })
+                    .collect();
+                let mut text = lines.join("\n");
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text
+            }
+            Dialect::Boolfuck => boolfuck::render(&boolfuck::from_brainfuck(program)),
+        }
+    }
+}
+
+// Where the commands of a translated program came from, so that a run of
+// the translation can report errors in the file the user wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    // the name of the original file
+    pub source: String,
+    // a location in the translation, and the one in the original it came from
+    pub origins: Vec<(Location, Location)>,
+}
+
+impl SourceMap {
+    // {"source": "hello.ook", "origins": [{"to": location, "from": location}]}
+    pub fn to_json(&self) -> Json {
+        let origins = self
+            .origins
+            .iter()
+            .map(|(to, from)| Json::object(vec![("to", to.to_json()), ("from", from.to_json())]))
+            .collect();
+        Json::object(vec![
+            ("source", self.source.as_str().into()),
+            ("origins", Json::Array(origins)),
+        ])
+    }
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        let source = json
+            .get("source")
+            .and_then(Json::as_str)
+            .ok_or_else(|| "a source map needs a `source`".to_string())?;
+        let origins = json
+            .get("origins")
+            .and_then(Json::as_array)
+            .ok_or_else(|| "a source map needs `origins`".to_string())?
+            .iter()
+            .map(|origin| {
+                let location = |name| {
+                    origin
+                        .get(name)
+                        .ok_or_else(|| format!("an origin needs `{}`", name))
+                        .and_then(Location::from_json)
+                };
+                Ok((location("to")?, location("from")?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            source: source.to_string(),
+            origins,
+        })
+    }
+    // Points the tokens of the translation, lexed as file `file`, back at
+    // the original. Tokens the map does not know keep their location.
+    pub fn apply(&self, program: &mut Program, file: usize) {
+        let origins: HashMap<(usize, usize), Location> = self
+            .origins
+            .iter()
+            .map(|(to, from)| ((to.line, to.col), *from))
+            .collect();
+        for token in program.iter_mut().filter(|t| t.location.file == file) {
+            if let Some(from) = origins.get(&(token.location.line, token.location.col)) {
+                token.location = Location { file, ..*from };
+            }
+        }
+    }
+}
+
+fn ook_word(kind: TokenKind) -> &'static str {
+    OOK.iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, word)| *word)
+        .expect("every command has an Ook! spelling")
+}
+
+// Anything that is not one of the three words is a comment.
+fn parse_ook(source: &str) -> Result<Program, String> {
+    let mut words: Vec<(char, Location)> = Vec::new();
+    let mut line_offset = 0;
+    for (line, text) in source.split('\n').enumerate() {
+        let mut rest = text;
+        while let Some(found) = rest.find("Ook") {
+            let start = text.len() - rest.len() + found;
+            let mark = text[start + 3..].chars().next();
+            if let Some(mark @ ('.' | '?' | '!')) = mark {
+                words.push((
+                    mark,
+                    Location {
+                        line: line + 1,
+                        col: text[..start].chars().count() + 1,
+                        file: 0,
+                        offset: line_offset + start,
+                        end: line_offset + start + 4,
+                    },
+                ));
+            }
+            rest = &text[start + 3..];
+        }
+        line_offset += text.len() + 1;
+    }
+    if !words.len().is_multiple_of(2) {
+        let (_, location) = words[words.len() - 1];
+        return Err(format!("unpaired Ook at {}", location));
+    }
+    words
+        .chunks(2)
+        .map(|pair| {
+            let spelling = format!("Ook{} Ook{}", pair[0].0, pair[1].0);
+            OOK.iter()
+                .find(|(_, word)| *word == spelling)
+                .map(|(kind, _)| Annotation {
+                    value: *kind,
+                    // both words
+                    location: pair[0].1.to(pair[1].1),
+                })
+                .ok_or_else(|| format!("{} is not a command at {}", spelling, pair[0].1))
+        })
+        .collect()
+}
+
+#[test]
+fn test_dialect() {
+    let program = Dialect::Brainfuck.parse("+[->,.<]").unwrap();
+    let ook = Dialect::Ook.render(&program);
+    assert_eq!(
+        ook,
+        "Ook. Ook. Ook! Ook? Ook! Ook! Ook. Ook? Ook. Ook! Ook! Ook. Ook? Ook. Ook? Ook!\n"
+    );
+    let parsed = Dialect::Ook.parse(&format!("a comment\n{}", ook)).unwrap();
+    assert_eq!(Dialect::Brainfuck.render(&parsed), "+[->,.<]");
+    assert_eq!(parsed[1].location.line, 2);
+    assert_eq!(parsed[1].location.col, 11);
+
+    assert!(Dialect::Ook.parse("Ook. Ook. Ook.").is_err());
+    assert_eq!(
+        Dialect::Ook.parse("Ook? Ook?"),
+        Err("Ook? Ook? is not a command at 1:1".to_string())
+    );
+    assert_eq!(
+        Dialect::from_path(Path::new("a/hello.ook")),
+        Some(Dialect::Ook)
+    );
+    assert_eq!(
+        Dialect::Boolfuck.render(&Lexer::lex("+\n>")),
+        ">[>]+<[+<]>>>>>>>>>[+]<<<<<<<<<\n>>>>>>>>>\n"
+    );
+    assert!(Dialect::Boolfuck.parse("+").is_err());
+}
+
+#[test]
+fn test_source_map() {
+    let ook = "Ook. Ook.\nOok! Ook? Ook! Ook! Ook? Ook!";
+    let (translated, mut map) = Dialect::Ook.translate(ook, Dialect::Brainfuck).unwrap();
+    assert_eq!(translated, "+[-]");
+    map.source = "a.ook".to_string();
+    let map = SourceMap::from_json(&Json::parse(&map.to_json().to_string()).unwrap()).unwrap();
+    assert_eq!(map.source, "a.ook");
+    let mut program = Lexer::lex_files(&["+", &translated]);
+    map.apply(&mut program, 1);
+    // the first file is not the translation
+    assert_eq!((program[0].location.line, program[0].location.col), (1, 1));
+    let loop_end = program[4].location;
+    assert_eq!((loop_end.line, loop_end.col, loop_end.file), (2, 21, 1));
+    assert_eq!(loop_end.text(ook), Some("Ook? Ook!"));
+    assert!(Dialect::Brainfuck
+        .translate("+", Dialect::Boolfuck)
+        .is_err());
+}
@@ -0,0 +1,238 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterError, IoEvent};
+use crate::lexer::{Program, TokenKind};
+use crate::snapshot::Snapshot;
+use std::fmt;
+use std::io;
+
+// What one engine did with the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineRun {
+    pub engine: EngineKind,
+    pub result: Result<usize, InterpreterError>,
+    pub events: Vec<IoEvent>,
+    pub snapshot: Snapshot,
+    pub steps: u64,
+}
+
+impl EngineRun {
+    pub fn new(program: &Program, engine: EngineKind, input: &[u8], eof: EofBehavior) -> Self {
+        Self::with_step_limit(program, engine, input, eof, None)
+    }
+
+    pub fn with_step_limit(
+        program: &Program,
+        engine: EngineKind,
+        input: &[u8],
+        eof: EofBehavior,
+        step_limit: Option<u64>,
+    ) -> Self {
+        let mut interpreter = engine.create();
+        let machine = interpreter.machine_mut();
+        machine.set_step_limit(step_limit);
+        machine.set_output(Box::new(io::sink()));
+        machine.set_input_bytes(input.to_vec());
+        machine.set_eof_behavior(eof);
+        machine.enable_io_trace();
+        let result = interpreter.eval(program);
+        let machine = interpreter.machine();
+        Self {
+            engine: interpreter.kind(),
+            result,
+            events: machine.io_trace().unwrap_or_default().to_vec(),
+            snapshot: machine.snapshot(),
+            steps: machine.steps(),
+        }
+    }
+}
+
+// The first difference between two runs, looking at the inputs and outputs
+// in order, then at how the runs ended, then at the final tape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    // the index of the event; None when that run had no more events
+    Event {
+        index: usize,
+        left: Option<IoEvent>,
+        right: Option<IoEvent>,
+    },
+    Result {
+        left: Result<usize, InterpreterError>,
+        right: Result<usize, InterpreterError>,
+    },
+    Tape {
+        left_pointer: usize,
+        right_pointer: usize,
+        // (cell, left value, right value)
+        cells: Vec<(usize, u8, u8)>,
+    },
+}
+
+fn same_event(left: &IoEvent, right: &IoEvent) -> bool {
+    // engines count instructions differently, so the step is not compared
+    (left.command, left.byte, left.location, left.pointer)
+        == (right.command, right.byte, right.location, right.pointer)
+}
+
+pub fn compare(left: &EngineRun, right: &EngineRun) -> Option<Divergence> {
+    let events = left.events.len().max(right.events.len());
+    for index in 0..events {
+        let (l, r) = (left.events.get(index), right.events.get(index));
+        match (l, r) {
+            (Some(l), Some(r)) if same_event(l, r) => {}
+            _ => {
+                return Some(Divergence::Event {
+                    index,
+                    left: l.copied(),
+                    right: r.copied(),
+                })
+            }
+        }
+    }
+    let same_result = match (&left.result, &right.result) {
+        (Ok(_), Ok(_)) => true,
+        (Err(l), Err(r)) => l == r,
+        _ => false,
+    };
+    if !same_result {
+        return Some(Divergence::Result {
+            left: left.result,
+            right: right.result,
+        });
+    }
+    let (l, r) = (&left.snapshot, &right.snapshot);
+    let len = l.cells.len().max(r.cells.len());
+    let cell = |cells: &[u8], i: usize| cells.get(i).copied().unwrap_or(0);
+    let cells: Vec<(usize, u8, u8)> = (0..len)
+        .map(|i| (i, cell(&l.cells, i), cell(&r.cells, i)))
+        .filter(|(_, a, b)| a != b)
+        .collect();
+    if !cells.is_empty() || l.pointer != r.pointer {
+        return Some(Divergence::Tape {
+            left_pointer: l.pointer,
+            right_pointer: r.pointer,
+            cells,
+        });
+    }
+    None
+}
+
+// Runs the program on both engines with the same input.
+pub fn difftest(
+    program: &Program,
+    engines: (EngineKind, EngineKind),
+    input: &[u8],
+    eof: EofBehavior,
+) -> (EngineRun, EngineRun, Option<Divergence>) {
+    let left = EngineRun::new(program, engines.0, input, eof);
+    let right = EngineRun::new(program, engines.1, input, eof);
+    let divergence = compare(&left, &right);
+    (left, right, divergence)
+}
+
+fn describe_event(event: &Option<IoEvent>) -> String {
+    match event {
+        Some(e) => format!(
+            "{} {} at {} (step {}, pointer {})",
+            if e.command == TokenKind::Output {
+                "wrote"
+            } else {
+                "read"
+            },
+            e.byte,
+            e.location,
+            e.step,
+            e.pointer
+        ),
+        None => "no more input or output".to_string(),
+    }
+}
+
+fn describe_result(result: &Result<usize, InterpreterError>) -> String {
+    match result {
+        Ok(_) => "finished".to_string(),
+        Err(e) => format!("{} at {}", e.value, e.location),
+    }
+}
+
+// The report of a divergence, given the names of the two engines.
+pub struct Report<'a> {
+    pub divergence: &'a Divergence,
+    pub names: (&'a str, &'a str),
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (left, right) = self.names;
+        match self.divergence {
+            Divergence::Event {
+                index,
+                left: l,
+                right: r,
+            } => {
+                writeln!(f, "I/O event #{} differs", index)?;
+                writeln!(f, "  {}: {}", left, describe_event(l))?;
+                write!(f, "  {}: {}", right, describe_event(r))
+            }
+            Divergence::Result { left: l, right: r } => {
+                writeln!(f, "the runs ended differently")?;
+                writeln!(f, "  {}: {}", left, describe_result(l))?;
+                write!(f, "  {}: {}", right, describe_result(r))
+            }
+            Divergence::Tape {
+                left_pointer,
+                right_pointer,
+                cells,
+            } => {
+                write!(f, "the final tapes differ")?;
+                if left_pointer != right_pointer {
+                    write!(
+                        f,
+                        "\n  pointer: {} {}, {} {}",
+                        left, left_pointer, right, right_pointer
+                    )?;
+                }
+                for (cell, l, r) in cells {
+                    write!(f, "\n  cell {}: {} {}, {} {}", cell, left, l, right, r)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_difftest() {
+    use crate::lexer::Lexer;
+    let programs = [
+        ",[.,]",
+        "++[->+++<]>.",
+        "+>+>+<<[>]<.",
+        "+[",
+        "+>>+<<[->+>+<<]>>[-<<+>>]<<.",
+    ];
+    for source in programs.iter() {
+        let program = Lexer::lex(source);
+        let (_, _, divergence) = difftest(
+            &program,
+            (EngineKind::Simple, EngineKind::Optimized),
+            b"ab",
+            EofBehavior::Zero,
+        );
+        assert_eq!(divergence, None, "{}", source);
+    }
+
+    let program = Lexer::lex(",.");
+    let left = EngineRun::new(&program, EngineKind::Simple, b"a", EofBehavior::Zero);
+    let right = EngineRun::new(&program, EngineKind::Simple, b"b", EofBehavior::Zero);
+    let divergence = compare(&left, &right).unwrap();
+    assert!(matches!(divergence, Divergence::Event { index: 0, .. }));
+    let report = Report {
+        divergence: &divergence,
+        names: ("a", "b"),
+    };
+    assert_eq!(
+        report.to_string(),
+        "I/O event #0 differs\n  a: read 97 at 1:1 (step 1, pointer 0)\n  b: read 98 at 1:1 (step 1, pointer 0)"
+    );
+}
@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+const CELLS_PER_LINE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TapeFormat {
+    Hex,
+    Decimal,
+    Char,
+}
+
+impl FromStr for TapeFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(TapeFormat::Hex),
+            "dec" | "decimal" => Ok(TapeFormat::Decimal),
+            "char" => Ok(TapeFormat::Char),
+            _ => Err(format!("unknown tape format: {}", s)),
+        }
+    }
+}
+
+impl TapeFormat {
+    fn cell(self, value: u8) -> String {
+        match self {
+            TapeFormat::Hex => format!("{:02x}", value),
+            TapeFormat::Decimal => format!("{:3}", value),
+            TapeFormat::Char => {
+                if value.is_ascii_graphic() || value == b' ' {
+                    format!("{}", value as char)
+                } else {
+                    ".".to_string()
+                }
+            }
+        }
+    }
+}
+
+// Parses "START:END" (END exclusive). Either bound may be omitted.
+pub fn parse_range(s: &str) -> Result<(Option<usize>, Option<usize>), String> {
+    let mut parts = s.splitn(2, ':');
+    let start = parts.next().unwrap_or("");
+    let end = parts
+        .next()
+        .ok_or_else(|| format!("range must look like START:END: {}", s))?;
+    let parse = |v: &str| -> Result<Option<usize>, String> {
+        if v.is_empty() {
+            Ok(None)
+        } else {
+            v.parse()
+                .map(Some)
+                .map_err(|_| format!("invalid range bound: {}", v))
+        }
+    };
+    Ok((parse(start)?, parse(end)?))
+}
+
+pub fn dump_tape(
+    cells: &[u8],
+    pointer: usize,
+    format: TapeFormat,
+    range: (Option<usize>, Option<usize>),
+) -> String {
+    let start = range.0.unwrap_or(0).min(cells.len());
+    let end = range.1.unwrap_or(cells.len()).min(cells.len()).max(start);
+    let mut out = format!("pointer: {}\n", pointer);
+    let mut index = start;
+    while index < end {
+        let line_end = (index + CELLS_PER_LINE).min(end);
+        out.push_str(&format!("{:06}:", index));
+        for (i, &value) in cells[index..line_end].iter().enumerate() {
+            if index + i == pointer {
+                out.push_str(&format!("[{}]", format.cell(value)));
+            } else {
+                out.push_str(&format!(" {} ", format.cell(value)));
+            }
+        }
+        out.push('\n');
+        index = line_end;
+    }
+    out
+}
+
+#[test]
+fn test_dump_tape() {
+    let cells = [72, 105, 0, 255];
+    assert_eq!(
+        dump_tape(&cells, 1, TapeFormat::Hex, (None, None)),
+        "pointer: 1\n000000: 48 [69] 00  ff \n"
+    );
+    assert_eq!(
+        dump_tape(&cells, 1, TapeFormat::Char, parse_range("1:3").unwrap()),
+        "pointer: 1\n000001:[i] . \n"
+    );
+    assert_eq!(parse_range(":8"), Ok((None, Some(8))));
+    assert!(parse_range("8").is_err());
+}
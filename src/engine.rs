@@ -0,0 +1,383 @@
+use crate::compiled::CompiledProgram;
+use crate::interpreter::{InterpreterError, InterpreterErrorKind, SimpleInterpreter, Stats};
+use crate::lexer::Program;
+use crate::optimized::OptimizedInterpreter;
+use crate::snapshot::Snapshot;
+use std::fmt;
+use std::panic;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EngineKind {
+    #[default]
+    Auto,
+    Simple,
+    Optimized,
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(EngineKind::Auto),
+            "simple" => Ok(EngineKind::Simple),
+            "optimized" => Ok(EngineKind::Optimized),
+            _ => Err(format!("unknown engine: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for EngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineKind::Auto => write!(f, "auto"),
+            EngineKind::Simple => write!(f, "simple"),
+            EngineKind::Optimized => write!(f, "optimized"),
+        }
+    }
+}
+
+impl EngineKind {
+    // Auto picks the optimized engine.
+    pub fn resolve(self) -> EngineKind {
+        match self {
+            EngineKind::Auto => EngineKind::Optimized,
+            kind => kind,
+        }
+    }
+
+    pub fn create(self) -> Box<dyn Engine> {
+        match self.resolve() {
+            EngineKind::Simple => Box::new(SimpleInterpreter::new()),
+            _ => Box::new(OptimizedInterpreter::new()),
+        }
+    }
+}
+
+// Why a run stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Completed,
+    // the step limit was reached
+    FuelExhausted,
+    // the interrupt flag was set, e.g. by RunHandle::kill
+    Cancelled,
+    Error(InterpreterError),
+}
+
+// How a run ended along with its counters, for hosts deciding what to do
+// next without asking the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalOutcome {
+    pub reason: StopReason,
+    pub stats: Stats,
+    pub elapsed: Duration,
+    // the tape and the pointer at the end
+    pub state: Snapshot,
+}
+
+impl EvalOutcome {
+    fn new(
+        result: Result<usize, InterpreterError>,
+        machine: &SimpleInterpreter,
+        elapsed: Duration,
+    ) -> Self {
+        let reason = match result {
+            Ok(_) => StopReason::Completed,
+            Err(e) => match e.value {
+                InterpreterErrorKind::StepLimitExceeded => StopReason::FuelExhausted,
+                InterpreterErrorKind::Interrupted => StopReason::Cancelled,
+                _ => StopReason::Error(e),
+            },
+        };
+        Self {
+            reason,
+            stats: machine.stats(),
+            elapsed,
+            state: machine.snapshot(),
+        }
+    }
+    pub fn is_completed(&self) -> bool {
+        self.reason == StopReason::Completed
+    }
+}
+
+// What every backend offers, so that code choosing one at run time does not
+// have to know which it got. The tape, the pointer and the I/O settings live
+// in a SimpleInterpreter, the machine, which all backends share.
+pub trait Engine: Send {
+    fn kind(&self) -> EngineKind;
+
+    // Prepares the program to run from the start on a fresh tape: checks
+    // the brackets and translates it into what the backend executes.
+    fn compile(&mut self, program: &Program) -> Result<(), InterpreterError>;
+
+    // Runs the compiled program to the end and flushes the output.
+    fn run(&mut self) -> Result<usize, InterpreterError>;
+
+    // Runs one instruction of the backend, which may stand for several
+    // commands. Returns false once the program has ended. Output is not
+    // flushed.
+    fn step(&mut self) -> Result<bool, InterpreterError>;
+
+    // Appends tokens to the program run so far and continues from where it
+    // stopped, keeping the tape and the pointer, as a REPL does with each
+    // line. The appended tokens must have balanced brackets.
+    fn eval_append(&mut self, program: &Program) -> Result<usize, InterpreterError>;
+    // Loads a program compiled earlier on a fresh tape, like compile but
+    // without copying or analyzing the program again.
+    fn load_compiled(&mut self, compiled: &CompiledProgram);
+
+    fn machine(&self) -> &SimpleInterpreter;
+    fn machine_mut(&mut self) -> &mut SimpleInterpreter;
+
+    fn eval(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        self.compile(program)?;
+        self.run()
+    }
+
+    // eval for a program compiled earlier.
+    fn eval_compiled(&mut self, compiled: &CompiledProgram) -> Result<usize, InterpreterError> {
+        self.load_compiled(compiled);
+        self.run()
+    }
+
+    // eval, telling how the run ended instead of returning Ok(0).
+    fn eval_outcome(&mut self, program: &Program) -> EvalOutcome {
+        self.eval_with_state(program, &[0], 0)
+    }
+
+    // Runs the program on a tape starting as `cells`, with the pointer at
+    // `pointer`, e.g. to pass arguments to a routine or to go on with an
+    // earlier computation. The tape grows as usual past the cells given.
+    fn eval_with_state(&mut self, program: &Program, cells: &[u8], pointer: usize) -> EvalOutcome {
+        let started = Instant::now();
+        let result = self.compile(program).and_then(|_| {
+            self.machine_mut().restore(&Snapshot {
+                cells: cells.to_vec(),
+                pointer,
+            });
+            self.run()
+        });
+        EvalOutcome::new(result, self.machine(), started.elapsed())
+    }
+}
+
+impl Engine for SimpleInterpreter {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Simple
+    }
+    fn compile(&mut self, program: &Program) -> Result<(), InterpreterError> {
+        self.load(program)
+    }
+    fn run(&mut self) -> Result<usize, InterpreterError> {
+        SimpleInterpreter::run(self)
+    }
+    fn step(&mut self) -> Result<bool, InterpreterError> {
+        SimpleInterpreter::step(self)
+    }
+    fn eval_append(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        SimpleInterpreter::eval_append(self, program)
+    }
+    fn load_compiled(&mut self, compiled: &CompiledProgram) {
+        SimpleInterpreter::load_compiled(self, compiled)
+    }
+    fn machine(&self) -> &SimpleInterpreter {
+        self
+    }
+    fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        self
+    }
+}
+
+impl Engine for OptimizedInterpreter {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Optimized
+    }
+    fn compile(&mut self, program: &Program) -> Result<(), InterpreterError> {
+        OptimizedInterpreter::compile(self, program)
+    }
+    fn run(&mut self) -> Result<usize, InterpreterError> {
+        OptimizedInterpreter::run(self)
+    }
+    fn step(&mut self) -> Result<bool, InterpreterError> {
+        OptimizedInterpreter::step(self)
+    }
+    fn eval_append(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        OptimizedInterpreter::eval_append(self, program)
+    }
+    fn load_compiled(&mut self, compiled: &CompiledProgram) {
+        OptimizedInterpreter::load_compiled(self, compiled)
+    }
+    fn machine(&self) -> &SimpleInterpreter {
+        OptimizedInterpreter::machine(self)
+    }
+    fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        OptimizedInterpreter::machine_mut(self)
+    }
+}
+
+impl dyn Engine {
+    // Runs the program on a new thread with the I/O set up on the machine.
+    // kill stops it with an Interrupted error, replacing any interrupt flag
+    // set before.
+    pub fn spawn(mut self: Box<Self>, program: Program) -> RunHandle {
+        let kill = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(AtomicU64::new(0));
+        let machine = self.machine_mut();
+        machine.set_interrupt_flag(kill.clone());
+        machine.set_progress_counter(progress.clone());
+        let thread = thread::spawn(move || {
+            let result = self.eval(&program);
+            (result, self)
+        });
+        RunHandle {
+            thread,
+            kill,
+            progress,
+        }
+    }
+}
+
+// A program running on its own thread.
+pub struct RunHandle {
+    thread: JoinHandle<(Result<usize, InterpreterError>, Box<dyn Engine>)>,
+    kill: Arc<AtomicBool>,
+    progress: Arc<AtomicU64>,
+}
+
+impl RunHandle {
+    // Waits for the run to end. The interpreter is returned for inspecting the tape.
+    pub fn join(self) -> (Result<usize, InterpreterError>, Box<dyn Engine>) {
+        match self.thread.join() {
+            Ok(ended) => ended,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    // Asks the run to stop. A program waiting for input stops once `,` returns.
+    pub fn kill(&self) {
+        self.kill.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Steps executed so far, updated every PROGRESS_INTERVAL steps.
+    pub fn steps(&self) -> u64 {
+        self.progress.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_engine() {
+    assert_eq!("optimized".parse(), Ok(EngineKind::Optimized));
+    assert!("jit".parse::<EngineKind>().is_err());
+    assert_eq!(EngineKind::Auto.create().kind(), EngineKind::Optimized);
+    assert_eq!(EngineKind::Simple.create().kind(), EngineKind::Simple);
+}
+
+#[test]
+fn test_engine_trait() {
+    use crate::lexer::Lexer;
+    let program = Lexer::lex("++[>+++<-]>[-]<+");
+    let mut instructions = vec![];
+    for kind in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let mut engine = kind.create();
+        engine.machine_mut().set_output(Box::new(std::io::sink()));
+        engine.compile(&program).unwrap();
+        let mut steps = 0;
+        while engine.step().unwrap() {
+            steps += 1;
+        }
+        assert_eq!(engine.machine().cells(), &[1, 0]);
+        instructions.push(steps);
+        assert_eq!(engine.eval(&program), Ok(0));
+        assert_eq!(engine.machine().cells(), &[1, 0]);
+        assert!(engine.compile(&Lexer::lex("[")).is_err());
+    }
+    // the optimized engine folds the commands into fewer instructions
+    assert!(instructions[0] > instructions[1]);
+}
+
+#[test]
+fn test_spawn() {
+    use crate::interpreter::{InterpreterErrorKind, PROGRESS_INTERVAL};
+    use crate::lexer::Lexer;
+    for engine in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let handle = engine.create().spawn(Lexer::lex("+++[>++<-]"));
+        let (result, interpreter) = handle.join();
+        assert_eq!(result, Ok(0));
+        assert_eq!(interpreter.machine().cells(), &[0, 6]);
+
+        let handle = engine.create().spawn(Lexer::lex("+[]"));
+        while handle.steps() < PROGRESS_INTERVAL {
+            thread::yield_now();
+        }
+        handle.kill();
+        let (result, _) = handle.join();
+        assert_eq!(
+            result.map_err(|e| e.value),
+            Err(InterpreterErrorKind::Interrupted)
+        );
+    }
+}
+
+#[test]
+fn test_eval_outcome() {
+    use crate::lexer::Lexer;
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        let machine = engine.machine_mut();
+        machine.set_output(Box::new(std::io::sink()));
+        machine.set_input_bytes(b"ab".to_vec());
+        let outcome = engine.eval_outcome(&Lexer::lex(",.,."));
+        assert!(outcome.is_completed());
+        assert_eq!(
+            (outcome.stats.bytes_read, outcome.stats.bytes_written),
+            (2, 2)
+        );
+        assert_eq!(outcome.stats.steps, 4);
+        // the inner loop is one instruction on the optimized engine
+        let stats = engine.eval_outcome(&Lexer::lex("++[>+[>+<-]<-]>[-]")).stats;
+        assert_eq!((stats.max_pointer, stats.max_depth), (2, 2));
+
+        engine.machine_mut().set_step_limit(Some(10));
+        let outcome = engine.eval_outcome(&Lexer::lex("+[]"));
+        assert_eq!(outcome.reason, StopReason::FuelExhausted);
+        engine
+            .machine_mut()
+            .set_interrupt_flag(Arc::new(AtomicBool::new(true)));
+        assert_eq!(
+            engine.eval_outcome(&Lexer::lex("+[]")).reason,
+            StopReason::Cancelled
+        );
+        engine
+            .machine_mut()
+            .set_interrupt_flag(Arc::new(AtomicBool::new(false)));
+        let outcome = engine.eval_outcome(&Lexer::lex("<"));
+        assert!(matches!(
+            outcome.reason,
+            StopReason::Error(ref e) if e.value == InterpreterErrorKind::PointerError
+        ));
+
+        // adds the first cell to the second and returns where the sum is
+        engine.machine_mut().set_step_limit(None);
+        let outcome = engine.eval_with_state(&Lexer::lex("<[->+<]>"), &[3, 4], 1);
+        assert!(outcome.is_completed());
+        assert_eq!(
+            outcome.state,
+            Snapshot {
+                cells: vec![0, 7],
+                pointer: 1
+            }
+        );
+        let outcome = engine.eval_with_state(&Lexer::lex(">+"), &[], 0);
+        assert_eq!(outcome.state.cells, [0, 1]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::batch::{Batch, RunResult};
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterErrorKind};
+use crate::lexer::{Program, TokenKind};
+use crate::random::Rng;
+
+// Random inputs tried on top of the boundary ones.
+const RANDOM_INPUTS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    // the same output and ending on every input tried; for programs that do
+    // not read, on the only input there is
+    Equivalent,
+    // an input telling the programs apart, and what each wrote
+    Different {
+        input: Vec<u8>,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    },
+    // a program did not halt within the budget on this input, and no input
+    // showed a difference
+    OutOfBudget {
+        input: Vec<u8>,
+    },
+}
+
+// Inputs likely to tell programs apart: nothing, single bytes at the edges
+// of a cell, a newline, and every byte in order.
+fn boundary_inputs() -> Vec<Vec<u8>> {
+    let mut inputs: Vec<Vec<u8>> = [0, 1, 127, 128, 255, b'\n', b'0', b'a']
+        .iter()
+        .map(|&byte| vec![byte])
+        .collect();
+    inputs.insert(0, Vec::new());
+    inputs.push((0..=255).collect());
+    inputs.push(vec![0; 16]);
+    inputs.push(vec![255; 16]);
+    inputs
+}
+
+fn ending(run: &RunResult) -> Result<(), InterpreterErrorKind> {
+    run.result.as_ref().map(|_| ()).map_err(|e| e.value)
+}
+
+// Whether the two programs write the same bytes and end the same way, with
+// `,` reading 0 after the input. Each run stops after `budget` steps. A
+// program without `,` is run once, which decides the question if it halts;
+// otherwise they are compared on boundary and pseudo-random inputs, the same
+// ones on every call.
+pub fn are_equivalent(a: &Program, b: &Program, budget: u64) -> Verdict {
+    let reads = |program: &Program| program.iter().any(|t| t.value == TokenKind::Input);
+    let inputs = if reads(a) || reads(b) {
+        let mut rng = Rng::new(0);
+        let mut inputs = boundary_inputs();
+        for _ in 0..RANDOM_INPUTS {
+            let length = rng.below(17);
+            inputs.push((0..length).map(|_| rng.byte()).collect());
+        }
+        inputs
+    } else {
+        vec![Vec::new()]
+    };
+    let batch = Batch {
+        engine: EngineKind::Auto,
+        eof: EofBehavior::Zero,
+        step_limit: Some(budget),
+        threads: 1,
+    };
+    let (left, right) = (batch.run(a, &inputs), batch.run(b, &inputs));
+    let mut out_of_budget = None;
+    for ((input, l), r) in inputs.into_iter().zip(left).zip(right) {
+        let limited = |run: &RunResult| ending(run) == Err(InterpreterErrorKind::StepLimitExceeded);
+        if limited(&l) || limited(&r) {
+            // what a cut short run wrote may still differ
+            let written = l.output.len().min(r.output.len());
+            if l.output[..written] == r.output[..written] {
+                out_of_budget.get_or_insert(input);
+                continue;
+            }
+        } else if l.output == r.output && ending(&l) == ending(&r) {
+            continue;
+        }
+        return Verdict::Different {
+            input,
+            left: l.output,
+            right: r.output,
+        };
+    }
+    match out_of_budget {
+        Some(input) => Verdict::OutOfBudget { input },
+        None => Verdict::Equivalent,
+    }
+}
+
+#[test]
+fn test_are_equivalent() {
+    use crate::lexer::Lexer;
+
+    let lex = Lexer::lex;
+    assert_eq!(
+        are_equivalent(&lex("++++[->++<]>."), &lex("++++++++."), 1000),
+        Verdict::Equivalent
+    );
+    assert_eq!(
+        are_equivalent(&lex(",[.,]"), &lex(",[.[-],]"), 10000),
+        Verdict::Equivalent
+    );
+    // the second forgets the byte 255
+    assert_eq!(
+        are_equivalent(&lex(",."), &lex(",+[-.>]"), 1000),
+        Verdict::Different {
+            input: vec![255],
+            left: vec![255],
+            right: vec![],
+        }
+    );
+    assert_eq!(
+        are_equivalent(&lex("+[]"), &lex("-[]"), 1000),
+        Verdict::OutOfBudget { input: vec![] }
+    );
+    // cut short after writing different bytes
+    assert!(matches!(
+        are_equivalent(&lex("+.[]"), &lex("++.[]"), 1000),
+        Verdict::Different { .. }
+    ));
+}
@@ -0,0 +1,57 @@
+// Turns `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` into the bytes they stand for.
+pub fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("invalid escape: \\x{}", hex))?;
+                bytes.push(byte);
+            }
+            Some(c) => return Err(format!("invalid escape: \\{}", c)),
+            None => return Err("trailing backslash".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+// The reverse of unescape, for showing bytes on one line.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    s
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(unescape(r"a\nb\x41\\\0"), Ok(b"a\nbA\\\0".to_vec()));
+    assert_eq!(unescape("é"), Ok("é".as_bytes().to_vec()));
+    assert!(unescape(r"\q").is_err());
+    assert!(unescape(r"\x4").is_err());
+    assert!(unescape("\\").is_err());
+    assert_eq!(escape(b"a\n\x01\\"), r"a\n\x01\\");
+    assert_eq!(unescape(&escape(b"\xff\t")), Ok(b"\xff\t".to_vec()));
+}
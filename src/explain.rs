@@ -0,0 +1,86 @@
+use crate::interpreter::{InterpreterError, SimpleInterpreter};
+use crate::lexer::TokenKind;
+
+// Runs the next command and says what it did in plain words, e.g. "move
+// the pointer right to cell 3" or "loop back because cell 3 is 7", for
+// showing a class what a program does step by step. None at the end of the
+// program.
+pub fn explain_step(machine: &mut SimpleInterpreter) -> Result<Option<String>, InterpreterError> {
+    let command = match machine.next_command() {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    machine.step()?;
+    let pointer = machine.pointer();
+    let cell = machine.cells()[pointer];
+    let text = match command {
+        TokenKind::IncrementPointer => format!("move the pointer right to cell {}", pointer),
+        TokenKind::DecrementPointer => format!("move the pointer left to cell {}", pointer),
+        TokenKind::Increment if cell == 0 => {
+            format!("increment cell {} from 255, wrapping to 0", pointer)
+        }
+        TokenKind::Increment => format!("increment cell {} to {}", pointer, cell),
+        TokenKind::Decrement if cell == 255 => {
+            format!("decrement cell {} from 0, wrapping to 255", pointer)
+        }
+        TokenKind::Decrement => format!("decrement cell {} to {}", pointer, cell),
+        TokenKind::Output => format!("output cell {}: {}", pointer, show(cell)),
+        TokenKind::Input => format!("read {} into cell {}", show(cell), pointer),
+        TokenKind::JumpForward if cell == 0 => {
+            format!("skip the loop because cell {} is 0", pointer)
+        }
+        TokenKind::JumpForward => format!("enter the loop because cell {} is {}", pointer, cell),
+        TokenKind::JumpBackward if cell == 0 => {
+            format!("leave the loop because cell {} is 0", pointer)
+        }
+        TokenKind::JumpBackward => {
+            format!("loop back because cell {} is {}", pointer, cell)
+        }
+        TokenKind::Call => "call the host function".to_string(),
+        TokenKind::SwitchTape => format!("switch tapes, to cell {} of the other", pointer),
+        TokenKind::Transfer => format!("copy cell {} ({}) to the other tape", pointer, cell),
+        TokenKind::End => "end the program".to_string(),
+        TokenKind::Store => format!("store cell {} ({})", pointer, cell),
+        TokenKind::Retrieve => format!("set cell {} to the stored {}", pointer, cell),
+        TokenKind::Literal(_) => format!("set cell {} to {}", pointer, cell),
+    };
+    Ok(Some(text))
+}
+
+// A byte as a number, and as the character it prints when it is one.
+fn show(byte: u8) -> String {
+    match byte {
+        b' '..=b'~' => format!("{} '{}'", byte, byte as char),
+        b'\n' => format!("{} '\\n'", byte),
+        _ => byte.to_string(),
+    }
+}
+
+#[test]
+fn test_explain_step() {
+    use crate::lexer::Lexer;
+    let mut machine = SimpleInterpreter::new();
+    machine.set_output(Box::new(std::io::sink()));
+    machine.set_input_bytes(b"A".to_vec());
+    machine.load(&Lexer::lex(",.>-[+]<[-]")).unwrap();
+    let mut steps = vec![];
+    while let Some(text) = explain_step(&mut machine).unwrap() {
+        steps.push(text);
+    }
+    assert_eq!(
+        steps[..7],
+        [
+            "read 65 'A' into cell 0",
+            "output cell 0: 65 'A'",
+            "move the pointer right to cell 1",
+            "decrement cell 1 from 0, wrapping to 255",
+            "enter the loop because cell 1 is 255",
+            "increment cell 1 from 255, wrapping to 0",
+            "leave the loop because cell 1 is 0",
+        ]
+    );
+    assert_eq!(steps[10], "loop back because cell 0 is 64");
+    // `]` goes back to the `[`, which checks the cell again
+    assert_eq!(steps[11], "enter the loop because cell 0 is 64");
+    assert_eq!(steps.len(), 8 + 65 * 3);
+}
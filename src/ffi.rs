@@ -0,0 +1,312 @@
+// The C interface, declared in include/brainfuck.h.
+//
+// Ownership: bf_compile allocates a machine that the caller frees with
+// bf_free. Pointers returned by bf_get_tape and bf_get_output point into the
+// machine and stay valid until the next call that takes the machine. Input
+// given to bf_set_input is copied. Every machine pointer must come from
+// bf_compile and must not be used from two threads at once.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::interpreter::{EofBehavior, InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::lexer::Lexer;
+use crate::spec::Capture;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+pub const BF_OK: c_int = 0;
+// bf_step: the program has ended
+pub const BF_DONE: c_int = 1;
+pub const BF_NULL_ARGUMENT: c_int = -1;
+pub const BF_INVALID_UTF8: c_int = -2;
+pub const BF_UNMATCHED_JUMP_FORWARD: c_int = -3;
+pub const BF_UNMATCHED_JUMP_BACKWARD: c_int = -4;
+pub const BF_POINTER_ERROR: c_int = -5;
+pub const BF_OUTPUT_ERROR: c_int = -6;
+pub const BF_INTERRUPTED: c_int = -7;
+pub const BF_STEP_LIMIT_EXCEEDED: c_int = -8;
+pub const BF_INPUT_ERROR: c_int = -9;
+pub const BF_NON_TERMINATING: c_int = -10;
+pub const BF_INPUT_EXHAUSTED: c_int = -11;
+pub const BF_NESTING_TOO_DEEP: c_int = -12;
+
+pub struct BfMachine {
+    machine: SimpleInterpreter,
+    output: Capture,
+    // what bf_get_output lends out
+    output_view: Vec<u8>,
+    error: Option<InterpreterError>,
+    // what bf_error_message lends out
+    message_view: CString,
+}
+
+fn error_code(kind: InterpreterErrorKind) -> c_int {
+    match kind {
+        InterpreterErrorKind::UnmatchedJumpForwardError => BF_UNMATCHED_JUMP_FORWARD,
+        InterpreterErrorKind::UnmatchedJumpBackwardError => BF_UNMATCHED_JUMP_BACKWARD,
+        InterpreterErrorKind::PointerError => BF_POINTER_ERROR,
+        InterpreterErrorKind::OutputError => BF_OUTPUT_ERROR,
+        InterpreterErrorKind::Interrupted => BF_INTERRUPTED,
+        InterpreterErrorKind::StepLimitExceeded => BF_STEP_LIMIT_EXCEEDED,
+        InterpreterErrorKind::InputError(_) => BF_INPUT_ERROR,
+        InterpreterErrorKind::NonTerminating => BF_NON_TERMINATING,
+        InterpreterErrorKind::InputExhausted => BF_INPUT_EXHAUSTED,
+        InterpreterErrorKind::NestingTooDeep => BF_NESTING_TOO_DEEP,
+    }
+}
+
+impl BfMachine {
+    fn fail(&mut self, e: InterpreterError) -> c_int {
+        let code = error_code(e.value);
+        self.error = Some(e);
+        code
+    }
+}
+
+// Compiles a NUL-terminated program into a new machine stored in `*out`.
+// `,` reads 0 once the input is exhausted. On an error `*out` is left null.
+#[no_mangle]
+pub unsafe extern "C" fn bf_compile(source: *const c_char, out: *mut *mut BfMachine) -> c_int {
+    if source.is_null() || out.is_null() {
+        return BF_NULL_ARGUMENT;
+    }
+    *out = ptr::null_mut();
+    let source = match CStr::from_ptr(source).to_str() {
+        Ok(source) => source,
+        Err(_) => return BF_INVALID_UTF8,
+    };
+    let output = Capture::default();
+    let mut machine = SimpleInterpreter::new();
+    machine.set_output(Box::new(output.clone()));
+    machine.set_input_bytes(vec![]);
+    machine.set_eof_behavior(EofBehavior::Zero);
+    if let Err(e) = machine.load(&Lexer::lex(source)) {
+        return error_code(e.value);
+    }
+    *out = Box::into_raw(Box::new(BfMachine {
+        machine,
+        output,
+        output_view: Vec::new(),
+        error: None,
+        message_view: CString::default(),
+    }));
+    BF_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bf_free(machine: *mut BfMachine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+// Replaces the input that `,` reads with a copy of `len` bytes at `data`.
+#[no_mangle]
+pub unsafe extern "C" fn bf_set_input(
+    machine: *mut BfMachine,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return BF_NULL_ARGUMENT,
+    };
+    if data.is_null() && len > 0 {
+        return BF_NULL_ARGUMENT;
+    }
+    let input = if len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    machine.machine.set_input_bytes(input.iter().copied());
+    BF_OK
+}
+
+// 0 means no limit.
+#[no_mangle]
+pub unsafe extern "C" fn bf_set_step_limit(machine: *mut BfMachine, limit: u64) -> c_int {
+    match machine.as_mut() {
+        Some(machine) => {
+            machine
+                .machine
+                .set_step_limit(Some(limit).filter(|&l| l > 0));
+            BF_OK
+        }
+        None => BF_NULL_ARGUMENT,
+    }
+}
+
+// Runs one command. Returns BF_OK, BF_DONE at the end, or an error code.
+#[no_mangle]
+pub unsafe extern "C" fn bf_step(machine: *mut BfMachine) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return BF_NULL_ARGUMENT,
+    };
+    match machine.machine.step() {
+        Ok(true) => BF_OK,
+        Ok(false) => BF_DONE,
+        Err(e) => machine.fail(e),
+    }
+}
+
+// Runs from the current command to the end. Returns BF_OK or an error code.
+#[no_mangle]
+pub unsafe extern "C" fn bf_run(machine: *mut BfMachine) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return BF_NULL_ARGUMENT,
+    };
+    loop {
+        match machine.machine.step() {
+            Ok(true) => {}
+            Ok(false) => return BF_OK,
+            Err(e) => return machine.fail(e),
+        }
+    }
+}
+
+// Lends out the cells; returns their number and stores the pointer position
+// in `*pointer` unless it is null.
+#[no_mangle]
+pub unsafe extern "C" fn bf_get_tape(
+    machine: *const BfMachine,
+    cells: *mut *const u8,
+    pointer: *mut usize,
+) -> usize {
+    let machine = match machine.as_ref() {
+        Some(machine) => machine,
+        None => return 0,
+    };
+    if !cells.is_null() {
+        *cells = machine.machine.cells().as_ptr();
+    }
+    if !pointer.is_null() {
+        *pointer = machine.machine.pointer();
+    }
+    machine.machine.cells().len()
+}
+
+// Lends out everything `.` has written so far; returns its length.
+#[no_mangle]
+pub unsafe extern "C" fn bf_get_output(machine: *mut BfMachine, data: *mut *const u8) -> usize {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return 0,
+    };
+    machine.output_view = machine.output.0.lock().unwrap().clone();
+    if !data.is_null() {
+        *data = machine.output_view.as_ptr();
+    }
+    machine.output_view.len()
+}
+
+// Stores where the last error happened. Returns BF_OK, or BF_DONE if there
+// was no error.
+#[no_mangle]
+pub unsafe extern "C" fn bf_error_location(
+    machine: *const BfMachine,
+    line: *mut usize,
+    col: *mut usize,
+) -> c_int {
+    let machine = match machine.as_ref() {
+        Some(machine) => machine,
+        None => return BF_NULL_ARGUMENT,
+    };
+    match machine.error {
+        Some(ref e) => {
+            if !line.is_null() {
+                *line = e.location.line;
+            }
+            if !col.is_null() {
+                *col = e.location.col;
+            }
+            BF_OK
+        }
+        None => BF_DONE,
+    }
+}
+
+// Lends out the NUL-terminated message of the last error, such as what the
+// OS said when the input failed, or returns null if there was none.
+#[no_mangle]
+pub unsafe extern "C" fn bf_error_message(machine: *mut BfMachine) -> *const c_char {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return ptr::null(),
+    };
+    match machine.error {
+        Some(ref e) => {
+            let message = machine.machine.error_message(e.value).replace('\0', "");
+            machine.message_view = CString::new(message).unwrap_or_default();
+            machine.message_view.as_ptr()
+        }
+        None => ptr::null(),
+    }
+}
+
+#[test]
+fn test_ffi() {
+    unsafe {
+        let mut machine = ptr::null_mut();
+        assert_eq!(
+            bf_compile(b"[\0".as_ptr() as *const c_char, &mut machine),
+            BF_UNMATCHED_JUMP_FORWARD
+        );
+        assert!(machine.is_null());
+
+        assert_eq!(
+            bf_compile(b",+.>+<<\0".as_ptr() as *const c_char, &mut machine),
+            BF_OK
+        );
+        assert_eq!(bf_set_input(machine, b"a".as_ptr(), 1), BF_OK);
+        assert_eq!(bf_step(machine), BF_OK);
+        let mut cells = ptr::null();
+        let mut pointer = 9;
+        assert_eq!(bf_get_tape(machine, &mut cells, &mut pointer), 1);
+        assert_eq!((*cells, pointer), (b'a', 0));
+
+        assert_eq!(bf_run(machine), BF_POINTER_ERROR);
+        let (mut line, mut col) = (0, 0);
+        assert_eq!(bf_error_location(machine, &mut line, &mut col), BF_OK);
+        assert_eq!((line, col), (1, 7));
+        let mut output = ptr::null();
+        assert_eq!(bf_get_output(machine, &mut output), 1);
+        assert_eq!(*output, b'b');
+        bf_free(machine);
+
+        assert_eq!(
+            bf_compile(b"+[]\0".as_ptr() as *const c_char, &mut machine),
+            BF_OK
+        );
+        assert_eq!(bf_set_step_limit(machine, 100), BF_OK);
+        assert!(bf_error_message(machine).is_null());
+        assert_eq!(bf_run(machine), BF_STEP_LIMIT_EXCEEDED);
+        let message = CStr::from_ptr(bf_error_message(machine));
+        assert_eq!(message.to_str(), Ok("step limit exceeded"));
+
+        // the OS's words for an input that failed
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("device unplugged"))
+            }
+        }
+        bf_free(machine);
+        assert_eq!(
+            bf_compile(b",\0".as_ptr() as *const c_char, &mut machine),
+            BF_OK
+        );
+        (*machine).machine.set_input(Box::new(Broken));
+        assert_eq!(bf_run(machine), BF_INPUT_ERROR);
+        let message = CStr::from_ptr(bf_error_message(machine));
+        assert_eq!(
+            message.to_str(),
+            Ok("could not read the input: device unplugged")
+        );
+        bf_free(machine);
+        assert_eq!(bf_step(ptr::null_mut()), BF_NULL_ARGUMENT);
+    }
+}
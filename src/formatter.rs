@@ -0,0 +1,43 @@
+const INDENT: &str = "  ";
+
+// Re-indents every line by the loop depth at its start. Comments and the
+// placement of commands are left as they are.
+pub fn format(source: &str) -> String {
+    let mut formatted = String::with_capacity(source.len());
+    let mut depth: usize = 0;
+    for (i, line) in source.lines().enumerate() {
+        if i > 0 {
+            formatted.push('\n');
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let leading_closes = trimmed
+            .chars()
+            .take_while(|c| *c == ']' || c.is_whitespace())
+            .filter(|c| *c == ']')
+            .count();
+        for _ in 0..depth.saturating_sub(leading_closes) {
+            formatted.push_str(INDENT);
+        }
+        formatted.push_str(trimmed);
+        for c in trimmed.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    if source.ends_with('\n') {
+        formatted.push('\n');
+    }
+    formatted
+}
+
+#[test]
+fn test_format() {
+    let source = "+[\n>[-]\n    <\n]\n\n  ]\n";
+    assert_eq!(format(source), "+[\n  >[-]\n  <\n]\n\n]\n");
+}
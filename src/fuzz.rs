@@ -0,0 +1,276 @@
+use crate::difftest::{self, EngineRun, Report};
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterErrorKind};
+use crate::lexer::Lexer;
+use crate::random::Rng;
+use std::panic::{self, AssertUnwindSafe};
+
+// Shapes the random programs. Chances are in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Generator {
+    // the number of commands, brackets included, picked between the two
+    pub min_length: usize,
+    pub max_length: usize,
+    // of opening a loop at each command, and of closing one when inside
+    pub loops: u64,
+    // of a command other than a bracket being `.` or `,`
+    pub io: u64,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self {
+            min_length: 0,
+            max_length: 100,
+            loops: 10,
+            io: 33,
+        }
+    }
+}
+
+impl Generator {
+    // Makes a random program whose brackets are balanced.
+    pub fn generate(&self, rng: &mut Rng) -> String {
+        let min = self.min_length.min(self.max_length);
+        let len = min + rng.below((self.max_length - min) as u64 + 1) as usize;
+        let mut program = String::with_capacity(len);
+        let mut depth = 0;
+        // the closing brackets still owed must fit
+        while program.len() + depth < len {
+            let roll = rng.below(100);
+            if roll < self.loops && program.len() + depth + 2 <= len {
+                program.push('[');
+                depth += 1;
+            } else if roll < 2 * self.loops && depth > 0 {
+                program.push(']');
+                depth -= 1;
+            } else {
+                let commands = if rng.below(100) < self.io {
+                    ".,"
+                } else {
+                    "+-<>"
+                };
+                let i = rng.below(commands.len() as u64) as usize;
+                program.push_str(&commands[i..i + 1]);
+            }
+        }
+        program.extend(std::iter::repeat_n(']', depth));
+        program
+    }
+}
+
+// Makes a random program of at most `max_len` commands whose brackets are balanced.
+pub fn generate(rng: &mut Rng, max_len: usize) -> String {
+    Generator {
+        max_length: max_len,
+        ..Generator::default()
+    }
+    .generate(rng)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    // both engines behaved the same
+    Passed,
+    // the simple engine did not halt within the step limit
+    StepLimitExceeded,
+    Panic(String),
+    Divergence(String),
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn run(
+    source: &str,
+    engine: EngineKind,
+    input: &[u8],
+    step_limit: u64,
+) -> Result<EngineRun, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        EngineRun::with_step_limit(
+            &Lexer::lex(source),
+            engine,
+            input,
+            EofBehavior::Zero,
+            Some(step_limit),
+        )
+    }))
+    .map_err(|payload| format!("{} engine panicked: {}", engine, panic_message(payload)))
+}
+
+// Runs the program on the simple engine, and then on the optimized one if
+// it halted within `step_limit` steps. The optimized engine never needs more
+// steps than the simple one, so both runs get the same limit.
+pub fn check(source: &str, input: &[u8], step_limit: u64) -> Finding {
+    let simple = match run(source, EngineKind::Simple, input, step_limit) {
+        Ok(run) => run,
+        Err(message) => return Finding::Panic(message),
+    };
+    if let Err(ref e) = simple.result {
+        if e.value == InterpreterErrorKind::StepLimitExceeded {
+            return Finding::StepLimitExceeded;
+        }
+    }
+    let optimized = match run(source, EngineKind::Optimized, input, step_limit) {
+        Ok(run) => run,
+        Err(message) => return Finding::Panic(message),
+    };
+    match difftest::compare(&simple, &optimized) {
+        None => Finding::Passed,
+        Some(ref divergence) => Finding::Divergence(
+            Report {
+                divergence,
+                names: ("simple", "optimized"),
+            }
+            .to_string(),
+        ),
+    }
+}
+
+// A property test of the optimizer for the tests of a new pass: each program
+// is run on the simple and the optimized engine with random inputs, and the
+// two must read, write and end alike and leave the same tape. Runs are
+// bounded by `step_limit`; programs that do not halt within it are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Equivalence {
+    // random inputs per program, of 0 to `input_length` bytes
+    pub inputs: usize,
+    pub input_length: usize,
+    pub step_limit: u64,
+    // the same seed checks the same inputs and programs
+    pub seed: u64,
+}
+
+impl Default for Equivalence {
+    fn default() -> Self {
+        Self {
+            inputs: 8,
+            input_length: 16,
+            step_limit: 10000,
+            seed: 0,
+        }
+    }
+}
+
+impl Equivalence {
+    // The first difference found, with the input that shows it.
+    pub fn check(&self, source: &str) -> Result<(), String> {
+        let mut rng = Rng::new(self.seed);
+        self.check_with(source, &mut rng)
+    }
+
+    // Checks `count` programs made by the generator, and returns the first
+    // that the engines disagree on along with the difference.
+    pub fn check_generated(
+        &self,
+        generator: &Generator,
+        count: usize,
+    ) -> Result<(), (String, String)> {
+        let mut rng = Rng::new(self.seed);
+        for _ in 0..count {
+            let source = generator.generate(&mut rng);
+            self.check_with(&source, &mut rng)
+                .map_err(|message| (source, message))?;
+        }
+        Ok(())
+    }
+
+    fn check_with(&self, source: &str, rng: &mut Rng) -> Result<(), String> {
+        for _ in 0..self.inputs {
+            let length = rng.below(self.input_length as u64 + 1) as usize;
+            let input: Vec<u8> = (0..length).map(|_| rng.byte()).collect();
+            match check(source, &input, self.step_limit) {
+                Finding::Passed | Finding::StepLimitExceeded => {}
+                Finding::Panic(message) | Finding::Divergence(message) => {
+                    return Err(format!("with input {:?}: {}", input, message));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Panics unless both engines behave the same on the program, for #[test]s.
+pub fn assert_equivalent(source: &str) {
+    if let Err(message) = Equivalence::default().check(source) {
+        panic!("{}: {}", source, message);
+    }
+}
+
+#[test]
+fn test_generate() {
+    let mut rng = Rng::new(7);
+    for _ in 0..100 {
+        let program = generate(&mut rng, 50);
+        assert!(program.len() <= 50);
+        let mut depth = 0;
+        for c in program.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
+            }
+            assert!(depth >= 0);
+        }
+        assert_eq!(depth, 0);
+    }
+
+    let generator = Generator {
+        min_length: 200,
+        max_length: 200,
+        loops: 0,
+        io: 100,
+    };
+    let program = generator.generate(&mut rng);
+    assert_eq!(program.len(), 200);
+    assert!(program.chars().all(|c| c == '.' || c == ','));
+    let nested = Generator {
+        loops: 50,
+        io: 0,
+        ..generator
+    }
+    .generate(&mut rng);
+    assert_eq!(nested.len(), 200);
+    assert!(nested.matches('[').count() > 20);
+    assert!(!nested.contains('.') && !nested.contains(','));
+}
+
+#[test]
+fn test_check() {
+    assert_eq!(check("++[->+<]>.", b"", 1000), Finding::Passed);
+    assert_eq!(check("+[]", b"", 1000), Finding::StepLimitExceeded);
+    let mut rng = Rng::new(1);
+    for _ in 0..200 {
+        let program = generate(&mut rng, 40);
+        let finding = check(&program, b"xyz", 2000);
+        assert!(
+            matches!(finding, Finding::Passed | Finding::StepLimitExceeded),
+            "{}: {:?}",
+            program,
+            finding
+        );
+    }
+}
+
+#[test]
+fn test_equivalence() {
+    assert_equivalent(",[->+>+<<]>[-<+>]>[.-]");
+    let equivalence = Equivalence {
+        inputs: 2,
+        ..Equivalence::default()
+    };
+    let generator = Generator {
+        max_length: 40,
+        ..Generator::default()
+    };
+    assert_eq!(equivalence.check_generated(&generator, 100), Ok(()));
+    assert_eq!(equivalence.check("+[]"), Ok(()));
+}
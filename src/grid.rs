@@ -0,0 +1,184 @@
+use crate::analysis;
+use crate::interpreter::{InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::lexer::{Annotation, Lexer, TokenKind};
+use crate::tape::Tape;
+use std::collections::HashMap;
+
+// A command of 2D brainfuck: the eight of brainfuck, where `<` and `>` move
+// along a row, plus `^` and `v` to move to the row above or below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Bf(TokenKind),
+    Up,
+    Down,
+}
+pub type GridProgram = Vec<Annotation<Command>>;
+
+pub fn lex(input: &str) -> GridProgram {
+    Lexer::scan(input, 0, |c| match c {
+        b'^' => Some(Command::Up),
+        b'v' => Some(Command::Down),
+        // not `%`, `~`...
+        b'>' | b'<' | b'+' | b'-' | b'.' | b',' | b'[' | b']' => {
+            TokenKind::from_char(c as char).map(Command::Bf)
+        }
+        _ => None,
+    })
+}
+
+// Runs 2D brainfuck on a grid that grows to the right and downwards. Row 0
+// is the top one and moving above it is a pointer error, as moving left of
+// column 0 is. The column is kept when changing rows.
+//
+// The current row is the tape of an inner SimpleInterpreter, which also holds
+// the I/O settings, the step limit and the statistics, as with the optimized
+// engine.
+pub struct GridInterpreter {
+    machine: SimpleInterpreter,
+    // the other rows; the slot of the current one is left empty
+    rows: Vec<Vec<u8>>,
+    row: usize,
+}
+
+impl Default for GridInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GridInterpreter {
+    pub fn new() -> Self {
+        Self {
+            machine: SimpleInterpreter::new(),
+            rows: vec![Vec::new()],
+            row: 0,
+        }
+    }
+    pub fn machine(&self) -> &SimpleInterpreter {
+        &self.machine
+    }
+    pub fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        &mut self.machine
+    }
+    // Row and column of the pointer.
+    pub fn position(&self) -> (usize, usize) {
+        (self.row, self.machine.pointer)
+    }
+    // The cells of the grid, one row per line; rows never visited are empty.
+    pub fn rows(&self) -> Vec<&[u8]> {
+        (0..self.rows.len())
+            .map(|row| {
+                if row == self.row {
+                    &self.machine.cells[..]
+                } else {
+                    &self.rows[row][..]
+                }
+            })
+            .collect()
+    }
+
+    pub fn eval(&mut self, program: &GridProgram) -> Result<usize, InterpreterError> {
+        let jump_table = analysis::match_brackets_by(program, |command| match command {
+            Command::Bf(kind) => Some(*kind),
+            _ => None,
+        })?;
+        self.machine.reset_tape();
+        self.rows = vec![Vec::new()];
+        self.row = 0;
+        let result = self.run(program, &jump_table);
+        self.machine
+            .finish(result, program.last().map(|c| c.location))
+    }
+
+    fn run(
+        &mut self,
+        program: &GridProgram,
+        jump_table: &HashMap<usize, usize>,
+    ) -> Result<usize, InterpreterError> {
+        let mut pc = 0;
+        while pc < program.len() {
+            let location = program[pc].location;
+            self.machine.count_step(location)?;
+            let m = &mut self.machine;
+            match program[pc].value {
+                Command::Up if self.row == 0 => {
+                    return Err(InterpreterError {
+                        value: InterpreterErrorKind::PointerError,
+                        location,
+                    });
+                }
+                Command::Up => self.change_row(self.row - 1),
+                Command::Down => self.change_row(self.row + 1),
+                Command::Bf(kind) => match kind {
+                    TokenKind::IncrementPointer => m.move_pointer(1, location)?,
+                    TokenKind::DecrementPointer => m.move_pointer(-1, location)?,
+                    TokenKind::Increment => m.cells[m.pointer] = m.cells[m.pointer].wrapping_add(1),
+                    TokenKind::Decrement => m.cells[m.pointer] = m.cells[m.pointer].wrapping_sub(1),
+                    TokenKind::Output => m.write_byte(location)?,
+                    TokenKind::Input => m.cells[m.pointer] = m.read_byte(location)?,
+                    TokenKind::JumpForward if m.cells[m.pointer] == 0 => pc = jump_table[&pc],
+                    TokenKind::JumpBackward if m.cells[m.pointer] != 0 => {
+                        m.check_interrupt(location)?;
+                        pc = jump_table[&pc];
+                    }
+                    // lex does not read the other commands
+                    _ => {}
+                },
+            }
+            pc += 1;
+        }
+        Ok(0)
+    }
+
+    fn change_row(&mut self, row: usize) {
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, Vec::new());
+        }
+        let mut cells = std::mem::take(&mut self.rows[row]);
+        if cells.len() <= self.machine.pointer {
+            cells.resize(self.machine.pointer + 1, 0);
+        }
+        self.rows[self.row] =
+            std::mem::replace(&mut self.machine.cells, Tape::from(cells)).to_vec();
+        self.row = row;
+    }
+}
+
+#[test]
+fn test_grid() {
+    use crate::lexer::Location;
+    use crate::spec::Capture;
+
+    // 'A' counted up on the top row by a loop whose counter is on the row
+    // below
+    let program = lex("v>++++++++[^++++++++v-]^+.v<+ prints A");
+    assert_eq!(
+        program[0],
+        Annotation {
+            value: Command::Down,
+            location: Location {
+                line: 1,
+                col: 1,
+                file: 0,
+                offset: 0,
+                end: 1
+            }
+        }
+    );
+    let output = Capture::default();
+    let mut grid = GridInterpreter::new();
+    grid.machine_mut().set_output(Box::new(output.clone()));
+    assert!(grid.eval(&program).is_ok());
+    assert_eq!(*output.0.lock().unwrap(), b"A");
+    assert_eq!(grid.position(), (1, 0));
+    let rows: Vec<&[u8]> = vec![&[0, 65], &[1, 0]];
+    assert_eq!(grid.rows(), rows);
+
+    let error = grid.eval(&lex("v+^^")).unwrap_err();
+    assert_eq!(error.value, InterpreterErrorKind::PointerError);
+    assert_eq!(error.location.col, 4);
+    assert_eq!(
+        grid.eval(&lex("v[")).map_err(|e| e.value),
+        Err(InterpreterErrorKind::UnmatchedJumpForwardError)
+    );
+}
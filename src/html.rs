@@ -0,0 +1,171 @@
+use crate::lexer::{Lexer, TokenKind};
+
+const STYLE: &str = "body { background: #1e1e1e; color: #d4d4d4; }
+pre { font-family: monospace; font-size: 14px; line-height: 1.4; }
+.bf-comment { color: #6a9955; }
+.bf-move { color: #569cd6; }
+.bf-arith { color: #ce9178; }
+.bf-io { color: #dcdcaa; font-weight: bold; }
+.bf-loop { color: #c586c0; font-weight: bold; }
+.bf-unmatched { background: #f44747; color: #ffffff; }
+.bf-pair-active { background: #264f78; outline: 1px solid #c586c0; }
+.bf-hot { border-bottom: 2px solid #f44747; }
+.bf-cold { opacity: 0.5; }";
+
+const SCRIPT: &str = "document.querySelectorAll('[data-pair]').forEach(function (el) {
+  var pair = document.querySelectorAll('[data-pair=\"' + el.dataset.pair + '\"]');
+  el.addEventListener('mouseenter', function () {
+    pair.forEach(function (p) { p.classList.add('bf-pair-active'); });
+  });
+  el.addEventListener('mouseleave', function () {
+    pair.forEach(function (p) { p.classList.remove('bf-pair-active'); });
+  });
+});";
+
+fn escape(c: char, out: &mut String) {
+    match c {
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '&' => out.push_str("&amp;"),
+        '"' => out.push_str("&quot;"),
+        c => out.push(c),
+    }
+}
+
+fn class_of(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::IncrementPointer
+        | TokenKind::DecrementPointer
+        | TokenKind::SwitchTape
+        | TokenKind::Transfer => "bf-move",
+        TokenKind::Increment
+        | TokenKind::Decrement
+        | TokenKind::Store
+        | TokenKind::Retrieve
+        | TokenKind::Literal(_) => "bf-arith",
+        TokenKind::Output | TokenKind::Input | TokenKind::Call => "bf-io",
+        TokenKind::JumpForward | TokenKind::JumpBackward | TokenKind::End => "bf-loop",
+    }
+}
+
+// Renders the source as a standalone HTML page. `counts` holds per-instruction
+// execution counts as produced by SimpleInterpreter::profile.
+pub fn to_html(source: &str, title: &str, counts: Option<&[u64]>) -> String {
+    let program = Lexer::lex(source);
+
+    // the pair id of a bracket is the token index of its `[`
+    let mut pairs: Vec<Option<usize>> = vec![None; program.len()];
+    let mut forward_brackets = vec![];
+    for (i, token) in program.iter().enumerate() {
+        match token.value {
+            TokenKind::JumpForward => forward_brackets.push(i),
+            TokenKind::JumpBackward => {
+                if let Some(forward) = forward_brackets.pop() {
+                    pairs[forward] = Some(forward);
+                    pairs[i] = Some(forward);
+                }
+            }
+            _ => {}
+        }
+    }
+    let max_count = counts.and_then(|c| c.iter().max().copied()).unwrap_or(0);
+
+    let mut body = String::with_capacity(source.len() * 8);
+    let mut tokens = program.iter().enumerate().peekable();
+    let mut line = 1;
+    let mut col = 1;
+    let mut in_comment = false;
+    for c in source.chars() {
+        let token = match tokens.peek() {
+            Some((_, t)) if t.location.line == line && t.location.col == col => tokens.next(),
+            _ => None,
+        };
+        match token {
+            Some((i, t)) => {
+                if in_comment {
+                    body.push_str("</span>");
+                    in_comment = false;
+                }
+                let mut classes = vec![class_of(t.value)];
+                let mut attributes = String::new();
+                if let TokenKind::JumpForward | TokenKind::JumpBackward = t.value {
+                    match pairs[i] {
+                        Some(pair) => attributes.push_str(&format!(" data-pair=\"{}\"", pair)),
+                        None => classes.push("bf-unmatched"),
+                    }
+                }
+                if let Some(counts) = counts {
+                    let count = counts.get(i).copied().unwrap_or(0);
+                    attributes.push_str(&format!(" title=\"executed {} times\"", count));
+                    if count == 0 {
+                        classes.push("bf-cold");
+                    } else if count * 10 >= max_count * 9 {
+                        classes.push("bf-hot");
+                    }
+                }
+                body.push_str(&format!(
+                    "<span class=\"{}\"{}>",
+                    classes.join(" "),
+                    attributes
+                ));
+                escape(c, &mut body);
+                body.push_str("</span>");
+            }
+            None => {
+                if !in_comment && !c.is_whitespace() {
+                    body.push_str("<span class=\"bf-comment\">");
+                    in_comment = true;
+                }
+                escape(c, &mut body);
+            }
+        }
+        // columns are counted in bytes, as the lexer does
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += c.len_utf8();
+        }
+    }
+    if in_comment {
+        body.push_str("</span>");
+    }
+
+    let mut escaped_title = String::new();
+    title.chars().for_each(|c| escape(c, &mut escaped_title));
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+{style}
+</style>
+</head>
+<body>
+<pre>{body}</pre>
+<script>
+{script}
+</script>
+</body>
+</html>
+",
+        title = escaped_title,
+        style = STYLE,
+        body = body,
+        script = SCRIPT
+    )
+}
+
+#[test]
+fn test_to_html() {
+    let html = to_html("+[>]<\n]", "a<b", Some(&[1, 2, 3, 2, 0, 0]));
+    assert!(html.contains("<title>a&lt;b</title>"));
+    assert!(html
+        .contains("<span class=\"bf-loop\" data-pair=\"1\" title=\"executed 2 times\">[</span>"));
+    assert!(html.contains("<span class=\"bf-move bf-hot\" title=\"executed 3 times\">&gt;</span>"));
+    assert!(html.contains(
+        "<span class=\"bf-loop bf-unmatched bf-cold\" title=\"executed 0 times\">]</span>"
+    ));
+}
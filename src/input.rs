@@ -0,0 +1,492 @@
+use crate::clock::{Clock, SystemClock};
+use crate::random::Rng;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// How often a read with a timeout looks at its clock.
+const TIMEOUT_POLL: Duration = Duration::from_millis(10);
+
+// Where `,` gets its bytes from.
+pub trait InputSource {
+    // The next byte, or None once the input has ended; `,` then stores the
+    // EOF value.
+    fn next_byte(&mut self) -> Option<u8>;
+
+    // What `,` calls. Sources that can fail override this to report the
+    // error, which stops the program, instead of ending the input.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.next_byte())
+    }
+
+    // False when next_byte may wait, e.g. for the user to type. Pending
+    // output is flushed before such a read so that prompts show up.
+    fn is_ready(&self) -> bool {
+        true
+    }
+}
+
+// Bytes in memory, such as a string given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BytesInput {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl BytesInput {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, position: 0 }
+    }
+}
+
+impl From<Vec<u8>> for BytesInput {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&str> for BytesInput {
+    fn from(text: &str) -> Self {
+        Self::new(text.as_bytes().to_vec())
+    }
+}
+
+impl InputSource for BytesInput {
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.bytes.get(self.position).copied()?;
+        self.position += 1;
+        Some(byte)
+    }
+}
+
+// Bytes yielded by an iterator, read only as `,` asks for them.
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = u8>> InputSource for IterInput<I> {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.0.next()
+    }
+}
+
+// Bytes made by a closure as `,` asks for them, until it returns None, e.g.
+// from a generator.
+pub struct FnInput<F>(pub F);
+
+impl<F: FnMut() -> Option<u8>> InputSource for FnInput<F> {
+    fn next_byte(&mut self) -> Option<u8> {
+        (self.0)()
+    }
+}
+
+// Bytes of a reader such as a file or a socket, read one at a time. A read
+// timeout, as set on a socket, ends the input. Once ended, the reader is not
+// read again: a terminal would wait for more after Ctrl-D.
+pub struct ReaderInput<R> {
+    reader: BufReader<R>,
+    ended: bool,
+}
+
+impl<R: Read> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            ended: false,
+        }
+    }
+}
+
+impl<R: Read> InputSource for ReaderInput<R> {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.read_byte().ok().flatten()
+    }
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.ended {
+            return Ok(None);
+        }
+        let mut byte = [0];
+        match self.reader.read_exact(&mut byte) {
+            Ok(()) => Ok(Some(byte[0])),
+            Err(e) => match e.kind() {
+                io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut => {
+                    self.ended = true;
+                    Ok(None)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+    // only a read with nothing buffered may block
+    fn is_ready(&self) -> bool {
+        self.ended || !self.reader.buffer().is_empty()
+    }
+}
+
+// Pseudo-random bytes from a seed, the same on every run and machine, for
+// fuzzing and benchmarking programs that read. Ends after `length` bytes if
+// one is given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RandomInput {
+    rng: Rng,
+    remaining: Option<u64>,
+}
+
+impl RandomInput {
+    pub fn new(seed: u64, length: Option<u64>) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            remaining: length,
+        }
+    }
+}
+
+impl InputSource for RandomInput {
+    fn next_byte(&mut self) -> Option<u8> {
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining = remaining.checked_sub(1)?;
+        }
+        Some(self.rng.byte())
+    }
+}
+
+// The console: each `,` reads a line typed by the user and takes its first
+// byte, so that one key and Enter answer one `,`. Ctrl-D, or the end of
+// piped input, ends the input for good.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdinInput {
+    ended: bool,
+}
+
+impl InputSource for StdinInput {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.read_byte().ok().flatten()
+    }
+    // Bytes that are not UTF-8 are read as they are.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.ended {
+            return Ok(None);
+        }
+        let mut line = Vec::new();
+        if io::stdin().lock().read_until(b'\n', &mut line)? == 0 {
+            self.ended = true;
+        }
+        Ok(line.first().map(|&byte| match byte {
+            // an empty line typed on Windows
+            b'\r' => b'\n',
+            byte => byte,
+        }))
+    }
+    fn is_ready(&self) -> bool {
+        self.ended
+    }
+}
+
+// Sources read one after the other, each until it returns None, e.g. a
+// scripted start and then the console for the rest.
+pub struct ChainInput {
+    sources: VecDeque<Box<dyn InputSource + Send>>,
+}
+
+impl ChainInput {
+    pub fn new(sources: Vec<Box<dyn InputSource + Send>>) -> Self {
+        Self {
+            sources: sources.into(),
+        }
+    }
+
+    pub fn push(&mut self, source: Box<dyn InputSource + Send>) {
+        self.sources.push_back(source);
+    }
+}
+
+impl InputSource for ChainInput {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.read_byte().ok().flatten()
+    }
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        while let Some(source) = self.sources.front_mut() {
+            if let Some(byte) = source.read_byte()? {
+                return Ok(Some(byte));
+            }
+            self.sources.pop_front();
+        }
+        Ok(None)
+    }
+    fn is_ready(&self) -> bool {
+        self.sources.front().is_none_or(|source| source.is_ready())
+    }
+}
+
+// Writes a prompt such as `input> ` before each read that may wait, so that
+// whoever runs an interactive program knows it waits for them. Reads that
+// will not wait, e.g. the rest of a line already typed, get no prompt.
+pub struct PromptInput<W> {
+    source: Box<dyn InputSource + Send>,
+    prompt: String,
+    writer: W,
+}
+
+impl<W: Write> PromptInput<W> {
+    pub fn new(source: Box<dyn InputSource + Send>, prompt: &str, writer: W) -> Self {
+        Self {
+            source,
+            prompt: prompt.to_string(),
+            writer,
+        }
+    }
+}
+
+impl<W: Write> InputSource for PromptInput<W> {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.read_byte().ok().flatten()
+    }
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if !self.source.is_ready() {
+            let _ = self.writer.write_all(self.prompt.as_bytes());
+            let _ = self.writer.flush();
+        }
+        self.source.read_byte()
+    }
+    fn is_ready(&self) -> bool {
+        self.source.is_ready()
+    }
+}
+
+// Asks the reading thread for a byte, and gets it.
+type Reader = (Sender<()>, Receiver<io::Result<Option<u8>>>);
+
+// What `,` does when its input is late.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutFallback {
+    // stores the EOF value and goes on; the next `,` waits again
+    Eof,
+    // stops the program with a "timed out" input error
+    Error,
+}
+
+// Makes `,` wait at most `timeout` for another source, so that a program
+// waiting for input that never comes cannot wedge a server or a GUI. The
+// source is read on a thread of its own, started by the first `,`; a read
+// that timed out goes on, and the next `,` gets its byte. On wasm, which
+// has no threads, `,` waits for the source as usual.
+pub struct TimeoutInput {
+    // until the first read
+    source: Option<Box<dyn InputSource + Send>>,
+    reader: Option<Reader>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+    fallback: TimeoutFallback,
+    // a read that timed out has not returned yet
+    waiting: bool,
+}
+
+impl TimeoutInput {
+    pub fn new(
+        source: Box<dyn InputSource + Send>,
+        timeout: Duration,
+        fallback: TimeoutFallback,
+    ) -> Self {
+        Self::with_clock(source, timeout, fallback, Arc::new(SystemClock::default()))
+    }
+
+    // Timeouts measured on `clock`, e.g. a ManualClock in tests.
+    pub fn with_clock(
+        source: Box<dyn InputSource + Send>,
+        timeout: Duration,
+        fallback: TimeoutFallback,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            source: Some(source),
+            reader: None,
+            clock,
+            timeout,
+            fallback,
+            waiting: false,
+        }
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn reader(&mut self) -> &Reader {
+        let source = &mut self.source;
+        self.reader.get_or_insert_with(|| {
+            let mut source = source.take().unwrap();
+            let (requests, requested) = mpsc::channel();
+            let (sender, bytes) = mpsc::channel();
+            // reads only when asked to, and ends when the TimeoutInput is dropped
+            thread::spawn(move || {
+                while requested.recv().is_ok() {
+                    if sender.send(source.read_byte()).is_err() {
+                        break;
+                    }
+                }
+            });
+            (requests, bytes)
+        })
+    }
+}
+
+impl InputSource for TimeoutInput {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.read_byte().ok().flatten()
+    }
+    #[cfg(target_family = "wasm")]
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.source.as_mut().unwrap().read_byte()
+    }
+    #[cfg(not(target_family = "wasm"))]
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let deadline = self.clock.now() + self.timeout;
+        let clock = self.clock.clone();
+        let waiting = self.waiting;
+        self.waiting = true;
+        let (requests, bytes) = self.reader();
+        if !waiting && requests.send(()).is_err() {
+            return Ok(None);
+        }
+        let read = loop {
+            let left = deadline.saturating_sub(clock.now());
+            if left.is_zero() {
+                return match self.fallback {
+                    TimeoutFallback::Eof => Ok(None),
+                    TimeoutFallback::Error => Err(io::ErrorKind::TimedOut.into()),
+                };
+            }
+            match bytes.recv_timeout(left.min(TIMEOUT_POLL)) {
+                Ok(read) => break read,
+                Err(RecvTimeoutError::Timeout) => {}
+                // the source panicked
+                Err(RecvTimeoutError::Disconnected) => break Ok(None),
+            }
+        };
+        self.waiting = false;
+        read
+    }
+    fn is_ready(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn test_input_sources() {
+    let mut bytes = BytesInput::from("ab");
+    assert_eq!(
+        (bytes.next_byte(), bytes.next_byte()),
+        (Some(b'a'), Some(b'b'))
+    );
+    assert_eq!(bytes.next_byte(), None);
+
+    let mut iter = IterInput((1..=2).map(|n| n * 10));
+    assert_eq!((iter.next_byte(), iter.next_byte()), (Some(10), Some(20)));
+    assert_eq!(iter.next_byte(), None);
+
+    let mut count = 0;
+    let mut generated = FnInput(|| {
+        count += 1;
+        Some(count).filter(|&n| n < 3)
+    });
+    assert_eq!(
+        (generated.next_byte(), generated.next_byte()),
+        (Some(1), Some(2))
+    );
+    assert_eq!(generated.next_byte(), None);
+
+    let mut reader = ReaderInput::new(io::Cursor::new(b"xy".to_vec()));
+    assert!(!reader.is_ready());
+    assert_eq!(reader.next_byte(), Some(b'x'));
+    assert!(reader.is_ready());
+    assert_eq!((reader.next_byte(), reader.next_byte()), (Some(b'y'), None));
+
+    // a terminal reads nothing on Ctrl-D and goes on, but the input has ended
+    struct Terminal(Vec<&'static [u8]>);
+    impl Read for Terminal {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let typed = self.0.remove(0);
+            buf[..typed.len()].copy_from_slice(typed);
+            Ok(typed.len())
+        }
+    }
+    let mut console = ReaderInput::new(Terminal(vec![b"a", b"", b"b"]));
+    assert_eq!(console.next_byte(), Some(b'a'));
+    assert_eq!((console.next_byte(), console.next_byte()), (None, None));
+    assert!(console.is_ready());
+
+    let mut chain = ChainInput::new(vec![
+        Box::new(BytesInput::from("a")),
+        Box::new(BytesInput::default()),
+    ]);
+    chain.push(Box::new(ReaderInput::new(io::Cursor::new(b"b".to_vec()))));
+    assert_eq!((chain.next_byte(), chain.is_ready()), (Some(b'a'), true));
+    assert_eq!((chain.next_byte(), chain.next_byte()), (Some(b'b'), None));
+
+    let mut shown = Vec::new();
+    let mut prompted = PromptInput::new(
+        Box::new(ReaderInput::new(io::Cursor::new(b"ab".to_vec()))),
+        "> ",
+        &mut shown,
+    );
+    let bytes: Vec<_> = (0..3).map(|_| prompted.next_byte()).collect();
+    assert_eq!(bytes, [Some(b'a'), Some(b'b'), None]);
+    // none before the second byte, buffered with the first
+    assert_eq!(shown, b"> > ");
+
+    let bytes = |mut input: RandomInput| (0..5).map(|_| input.next_byte()).collect::<Vec<_>>();
+    let random = bytes(RandomInput::new(7, None));
+    assert_eq!(random, bytes(RandomInput::new(7, None)));
+    assert_ne!(random, bytes(RandomInput::new(8, None)));
+    assert_eq!(
+        bytes(RandomInput::new(7, Some(2)))[..],
+        [random[0], random[1], None, None, None]
+    );
+
+    // a source answering when the test says so
+    struct Gated(Receiver<u8>);
+    impl InputSource for Gated {
+        fn next_byte(&mut self) -> Option<u8> {
+            self.0.recv().ok()
+        }
+    }
+    // a clock moving on by the step each time it is read: with a step of
+    // `timeout`, a read not answered yet times out, and with none it waits
+    #[derive(Clone, Default)]
+    struct Ticking(crate::clock::ManualClock, Arc<std::sync::Mutex<Duration>>);
+    impl Clock for Ticking {
+        fn now(&self) -> Duration {
+            self.0.advance(*self.1.lock().unwrap());
+            self.0.now()
+        }
+    }
+    let timeout = Duration::from_millis(20);
+    let clock = Ticking::default();
+    let timed = |source: Box<dyn InputSource + Send>, fallback| {
+        TimeoutInput::with_clock(source, timeout, fallback, Arc::new(clock.clone()))
+    };
+    let (gate, gated) = mpsc::channel();
+    let mut late = timed(Box::new(Gated(gated)), TimeoutFallback::Eof);
+    *clock.1.lock().unwrap() = timeout;
+    assert_eq!(late.read_byte().unwrap(), None);
+    // the byte of the read that timed out goes to the next `,`
+    gate.send(1).unwrap();
+    *clock.1.lock().unwrap() = Duration::ZERO;
+    assert_eq!(late.read_byte().unwrap(), Some(1));
+
+    let (_gate, gated) = mpsc::channel();
+    let mut late = timed(Box::new(Gated(gated)), TimeoutFallback::Error);
+    *clock.1.lock().unwrap() = timeout;
+    assert_eq!(
+        late.read_byte().map_err(|e| e.kind()),
+        Err(io::ErrorKind::TimedOut)
+    );
+
+    *clock.1.lock().unwrap() = Duration::ZERO;
+    let mut quick = timed(Box::new(BytesInput::from("a")), TimeoutFallback::Error);
+    assert_eq!((quick.next_byte(), quick.next_byte()), (Some(b'a'), None));
+    // no thread before the first read
+    assert!(timed(Box::new(BytesInput::from("a")), TimeoutFallback::Eof)
+        .reader
+        .is_none());
+}
@@ -1,21 +1,329 @@
-use crate::lexer::{Annotation, Program, Token, TokenKind};
-use std::collections::HashMap;
+use crate::analysis;
+use crate::compiled::{self, CompiledProgram};
+use crate::input::{
+    BytesInput, ChainInput, FnInput, InputSource, IterInput, PromptInput, ReaderInput, StdinInput,
+    TimeoutFallback, TimeoutInput,
+};
+use crate::json::Json;
+use crate::lexer::{Annotation, Location, Program, Token, TokenKind};
+use crate::snapshot::Snapshot;
+use crate::tape::{Tape, TapeKind};
+use crate::tee::Tee;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const PROGRESS_INTERVAL: u64 = 1 << 16;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum InterpreterErrorKind {
     UnmatchedJumpForwardError,
     UnmatchedJumpBackwardError,
     PointerError,
+    OutputError,
+    // reading the input failed, e.g. with a broken pipe
+    InputError(io::ErrorKind),
+    Interrupted,
+    StepLimitExceeded,
+    // a loop came back to a state it had been in; see enable_loop_detection
+    NonTerminating,
+    // `,` after the end of the input, with EofBehavior::Error
+    InputExhausted,
+    // at the `[` nested deeper than set_nesting_limit allows
+    NestingTooDeep,
 }
-type InterpreterError = Annotation<InterpreterErrorKind>;
+pub type InterpreterError = Annotation<InterpreterErrorKind>;
 
-pub struct SimpleInterpreter {
+impl InterpreterErrorKind {
+    // A stable identifier, for tools and scripts to match on.
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterpreterErrorKind::UnmatchedJumpForwardError => "unmatched_jump_forward",
+            InterpreterErrorKind::UnmatchedJumpBackwardError => "unmatched_jump_backward",
+            InterpreterErrorKind::PointerError => "pointer",
+            InterpreterErrorKind::OutputError => "output",
+            InterpreterErrorKind::InputError(_) => "input",
+            InterpreterErrorKind::Interrupted => "interrupted",
+            InterpreterErrorKind::StepLimitExceeded => "step_limit_exceeded",
+            InterpreterErrorKind::NonTerminating => "non_terminating",
+            InterpreterErrorKind::InputExhausted => "input_exhausted",
+            InterpreterErrorKind::NestingTooDeep => "nesting_too_deep",
+        }
+    }
+}
+
+impl fmt::Display for InterpreterErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpreterErrorKind::UnmatchedJumpForwardError => write!(f, "unmatched '['"),
+            InterpreterErrorKind::UnmatchedJumpBackwardError => write!(f, "unmatched ']'"),
+            InterpreterErrorKind::PointerError => write!(f, "pointer moved below cell 0"),
+            InterpreterErrorKind::OutputError => write!(f, "could not write the output"),
+            InterpreterErrorKind::InputError(kind) => {
+                write!(f, "could not read the input: {}", kind)
+            }
+            InterpreterErrorKind::Interrupted => write!(f, "interrupted"),
+            InterpreterErrorKind::StepLimitExceeded => write!(f, "step limit exceeded"),
+            InterpreterErrorKind::NonTerminating => write!(f, "non-terminating loop detected"),
+            InterpreterErrorKind::InputExhausted => {
+                write!(f, "program requested more input than provided")
+            }
+            InterpreterErrorKind::NestingTooDeep => write!(f, "loops nested too deeply"),
+        }
+    }
+}
+
+// What `,` stores once the input stream is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EofBehavior {
+    #[default]
+    Newline,
+    Zero,
+    MinusOne,
+    Unchanged,
+    // stop the program with InputExhausted, for fixed inputs that a program
+    // should not read past
+    Error,
+}
+
+impl FromStr for EofBehavior {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newline" | "10" => Ok(EofBehavior::Newline),
+            "zero" | "0" => Ok(EofBehavior::Zero),
+            "minus-one" | "-1" | "255" => Ok(EofBehavior::MinusOne),
+            "unchanged" => Ok(EofBehavior::Unchanged),
+            "error" => Ok(EofBehavior::Error),
+            _ => Err(format!("unknown EOF behavior: {}", s)),
+        }
+    }
+}
+
+// What unmatched brackets do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BracketMode {
+    // the program does not run
+    #[default]
+    Strict,
+    // as with some historic interpreters, an unmatched `[` on a zero cell
+    // jumps to the end of the program, and an unmatched `]` does nothing
+    Permissive,
+}
+
+impl FromStr for BracketMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(BracketMode::Strict),
+            "permissive" => Ok(BracketMode::Permissive),
+            _ => Err(format!("unknown bracket mode: {}", s)),
+        }
+    }
+}
+
+// When the output of `.` reaches its destination. Pending output is also
+// flushed before `,` waits for input and when the run ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Buffering {
+    // flush after every byte
+    Unbuffered,
+    // flush at each newline
+    Line,
+    Full,
+}
+
+impl FromStr for Buffering {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Buffering::Unbuffered),
+            "line" => Ok(Buffering::Line),
+            "full" => Ok(Buffering::Full),
+            _ => Err(format!("unknown buffering: {}", s)),
+        }
+    }
+}
+
+// How `.` turns the current cell into output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputEncoding {
+    // the value as one byte
+    #[default]
+    Bytes,
+    // the value as a Unicode code point, encoded in UTF-8; with 8-bit cells
+    // that is U+0000 to U+00FF, i.e. Latin-1
+    Utf8,
+}
+
+impl FromStr for OutputEncoding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bytes" => Ok(OutputEncoding::Bytes),
+            "utf8" | "utf-8" => Ok(OutputEncoding::Utf8),
+            _ => Err(format!("unknown output encoding: {}", s)),
+        }
+    }
+}
+
+impl Buffering {
+    // Wraps `output` in a writer for this policy, to be passed to set_output.
+    pub fn wrap<W: Write + Send + 'static>(self, output: W) -> Box<dyn Write + Send> {
+        match self {
+            Buffering::Unbuffered => Box::new(Unbuffered(output)),
+            Buffering::Line => Box::new(io::LineWriter::new(output)),
+            Buffering::Full => Box::new(io::BufWriter::with_capacity(1 << 16, output)),
+        }
+    }
+}
+
+struct Unbuffered<W>(W);
+
+impl<W: Write> Write for Unbuffered<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.0.write(buf)?;
+        self.0.flush()?;
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// A byte written by `.` or read by `,`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoEvent {
+    // TokenKind::Output or TokenKind::Input
+    pub command: TokenKind,
+    pub byte: u8,
+    pub location: Location,
+    pub pointer: usize,
+    // the instruction count when the event happened
+    pub step: u64,
+}
+
+// Something a program did that is allowed but often a mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    // `+` on 255 or `-` on 0
+    CellWrapped,
+    // `,` read after the end of the input and stored the EOF value
+    ReadPastEnd,
+}
+pub type Warning = Annotation<WarningKind>;
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::CellWrapped => write!(f, "cell wrapped around"),
+            WarningKind::ReadPastEnd => write!(f, "',' read past the end of the input"),
+        }
+    }
+}
+
+// Counters of the last run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Stats {
+    // instructions executed; the optimized engine counts its own instructions
+    pub steps: u64,
+    // the same on every engine, see SimpleInterpreter::cycles
+    pub cycles: u64,
+    pub peak_tape: usize,
+    // the highest cell the pointer got to
+    pub max_pointer: usize,
+    // times the tape was reallocated to grow
+    pub tape_growths: u64,
+    // the most loops the run was inside of at once
+    pub max_depth: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+// What a host function sees when a program runs `%`.
+pub struct HostContext<'a> {
+    cells: &'a mut Tape,
     pointer: usize,
-    program_cursor: usize,
-    cells: Vec<u8>,
-    program: Program,
-    input_stream: Option<Vec<u8>>,
-    jump_table: HashMap<usize, usize>,
+    limit: usize,
+}
+
+impl HostContext<'_> {
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+    // The cell `offset` cells from the pointer; 0 if it is outside the tape.
+    pub fn cell(&self, offset: isize) -> u8 {
+        self.pointer
+            .checked_add_signed(offset)
+            .and_then(|i| self.cells.get(i).copied())
+            .unwrap_or(0)
+    }
+    // Cells below cell 0 are ignored; the tape grows to reach higher ones.
+    pub fn set_cell(&mut self, offset: isize, value: u8) {
+        if let Some(i) = self.pointer.checked_add_signed(offset) {
+            if i < self.limit && self.cells.grow(i + 1).is_ok() {
+                self.cells[i] = value;
+            }
+        }
+    }
+}
+
+pub type HostFunction = Box<dyn FnMut(&mut HostContext) + Send>;
+
+pub struct SimpleInterpreter {
+    pub(crate) pointer: usize,
+    pub(crate) program_cursor: usize,
+    pub(crate) cells: Tape,
+    tape_kind: TapeKind,
+    // the tape `~` switches to, with its own pointer
+    other_cells: Tape,
+    other_pointer: usize,
+    // the storage cell of `$` and `!`
+    storage: u8,
+    pub(crate) program: Arc<Program>,
+    input: Box<dyn InputSource + Send>,
+    // what the OS said when the input last failed
+    input_error: Option<String>,
+    jump_table: Arc<HashMap<usize, usize>>,
+    profile: Option<Vec<u64>>,
+    pub(crate) steps: u64,
+    pub(crate) cycles: u64,
+    pub(crate) max_pointer: usize,
+    pub(crate) tape_growths: u64,
+    // the loops the run is inside of
+    pub(crate) depth: usize,
+    pub(crate) max_depth: usize,
+    step_limit: Option<u64>,
+    nesting_limit: Option<usize>,
+    // the most cells the tape may have
+    tape_size: Option<usize>,
+    // hashes of the states seen at the ends of loops
+    loop_states: Option<HashSet<u64>>,
+    pub(crate) bytes_read: u64,
+    pub(crate) bytes_written: u64,
+    interrupt: Option<Arc<AtomicBool>>,
+    progress: Option<Arc<AtomicU64>>,
+    input_record: Option<Vec<u8>>,
+    io_trace: Option<Vec<IoEvent>>,
+    step_trace: Option<Box<dyn Write + Send>>,
+    warnings: Option<Vec<Warning>>,
+    eof_behavior: EofBehavior,
+    bracket_mode: BracketMode,
+    output: Box<dyn Write + Send>,
+    output_encoding: OutputEncoding,
+    host_call: Option<HostFunction>,
+}
+
+impl Default for SimpleInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SimpleInterpreter {
@@ -23,22 +331,277 @@ impl SimpleInterpreter {
         Self {
             pointer: 0,
             program_cursor: 0,
-            cells: vec![0 as u8],
-            program: Vec::new(),
-            input_stream: None,
-            jump_table: HashMap::new(),
+            cells: Tape::default(),
+            tape_kind: TapeKind::default(),
+            other_cells: Tape::default(),
+            other_pointer: 0,
+            storage: 0,
+            program: Arc::new(Vec::new()),
+            input: Box::new(StdinInput::default()),
+            input_error: None,
+            jump_table: Arc::new(HashMap::new()),
+            profile: None,
+            steps: 0,
+            cycles: 0,
+            max_pointer: 0,
+            tape_growths: 0,
+            depth: 0,
+            max_depth: 0,
+            step_limit: None,
+            nesting_limit: None,
+            tape_size: None,
+            loop_states: None,
+            bytes_read: 0,
+            bytes_written: 0,
+            interrupt: None,
+            progress: None,
+            input_record: None,
+            io_trace: None,
+            step_trace: None,
+            warnings: None,
+            eof_behavior: EofBehavior::default(),
+            bracket_mode: BracketMode::default(),
+            output: Box::new(io::stdout()),
+            output_encoding: OutputEncoding::default(),
+            host_call: None,
         }
     }
-    pub fn set_input_stream(&mut self, input_stream: String) {
-        let mut v: Vec<u8> = input_stream.replace("\n", "").bytes().collect();
-        v.reverse();
-        self.input_stream = Some(v);
+    // eval stops with an Interrupted error once the flag is set.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+    // The counter is set to the number of steps every PROGRESS_INTERVAL steps
+    // and at the end of a run, for watching a run from another thread.
+    pub fn set_progress_counter(&mut self, counter: Arc<AtomicU64>) {
+        self.progress = Some(counter);
+    }
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    // Cycles of the run so far, one per command stepped through: each `[`
+    // and `]` reached counts, a `[` on 0 reaching its `]` too. Unlike the
+    // steps, every engine counts the same cycles for a program and an input,
+    // for scoring runs whatever engine made them. Only a run cut short by
+    // an error inside a folded instruction may count a few less.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
-    fn eval_increment_pointer(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
-        self.pointer += 1;
-        if self.cells.len() <= self.pointer {
-            self.cells.push(0);
+    // eval stops with a StepLimitExceeded error instead of running more
+    // instructions than this.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+    // Programs with loops nested deeper than this do not run, failing with
+    // NestingTooDeep at the first `[` too deep: a guard for servers running
+    // whatever they are sent. Programs loaded already compiled are not
+    // checked.
+    pub fn set_nesting_limit(&mut self, limit: Option<usize>) {
+        self.nesting_limit = limit;
+    }
+    // Moving the pointer right of the last of `cells` cells fails with a
+    // PointerError, as moving left of cell 0 does. By default the tape grows
+    // as far as memory allows.
+    pub fn set_tape_size(&mut self, cells: Option<usize>) {
+        self.tape_size = cells;
+    }
+    fn tape_limit(&self) -> usize {
+        self.cells.limit().min(self.tape_size.unwrap_or(usize::MAX))
+    }
+    pub(crate) fn check_nesting(&self, program: &Program) -> Result<(), InterpreterError> {
+        match self.nesting_limit {
+            Some(limit) => analysis::check_nesting(program, limit),
+            None => Ok(()),
+        }
+    }
+    // eval stops with a NonTerminating error when a loop is about to go
+    // round again with the tapes, the pointers and the input read the same
+    // as on an earlier round: it would repeat itself forever. This catches
+    // loops such as `+[]` or `+[>+<]` early, but not ones that keep moving
+    // to new cells. The whole tape is hashed on every round, and `%` forgets
+    // the states seen, since a host function may change anything.
+    pub fn enable_loop_detection(&mut self) {
+        self.loop_states = Some(HashSet::new());
+    }
+    pub fn stats(&self) -> Stats {
+        Stats {
+            steps: self.steps,
+            cycles: self.cycles,
+            // the tape only grows during a run
+            peak_tape: self.cells.len(),
+            max_pointer: self.max_pointer,
+            tape_growths: self.tape_growths,
+            max_depth: self.max_depth,
+            bytes_read: self.bytes_read,
+            bytes_written: self.bytes_written,
         }
+    }
+    // Counts how many times each instruction of the program gets executed.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Vec::new());
+    }
+    pub fn profile(&self) -> Option<&[u64]> {
+        self.profile.as_deref()
+    }
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+    // Where the cells of the next runs live.
+    pub fn set_tape_kind(&mut self, kind: TapeKind) {
+        self.tape_kind = kind;
+        self.cells = Tape::new(kind);
+        self.pointer = 0;
+    }
+    // Runs go on on `tape` from `pointer`, until the tape is reset by the
+    // next compile or eval, e.g. on a tape opened with Tape::open_file.
+    pub fn set_tape(&mut self, tape: Tape, pointer: usize) {
+        self.cells = tape;
+        self.pointer = pointer;
+        self.reach_pointer();
+    }
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cells: self.cells.to_vec(),
+            pointer: self.pointer,
+        }
+    }
+    // Replaces the tape and the pointer. The program and its position are kept.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if snapshot.pointer >= self.cells.limit() || self.cells.assign(&snapshot.cells).is_err() {
+            // too large for where the cells are
+            self.cells = Tape::from(snapshot.cells.clone());
+        }
+        self.pointer = snapshot.pointer;
+        self.max_pointer = self.max_pointer.max(self.pointer);
+        self.grow_tape(self.pointer + 1);
+    }
+    // `.` writes to stdout unless another writer is set.
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+    // `.` writes to `output` too, besides the outputs set so far.
+    pub fn add_output(&mut self, output: Box<dyn Write + Send>) {
+        let outputs = std::mem::replace(&mut self.output, Box::new(io::sink()));
+        self.output = Box::new(Tee::new(vec![outputs, output]));
+    }
+    pub fn set_output_encoding(&mut self, encoding: OutputEncoding) {
+        self.output_encoding = encoding;
+    }
+    // Called for each `%` of a program lexed with Lexer::lex_with_calls, e.g.
+    // to give programs random numbers or the time. Without one, `%` does nothing.
+    pub fn set_host_call(&mut self, function: HostFunction) {
+        self.host_call = Some(function);
+    }
+    pub fn set_eof_behavior(&mut self, eof_behavior: EofBehavior) {
+        self.eof_behavior = eof_behavior;
+    }
+    // Applies to eval on both engines. eval_append and the other tools
+    // still need matched brackets.
+    pub fn set_bracket_mode(&mut self, mode: BracketMode) {
+        self.bracket_mode = mode;
+    }
+    pub fn bracket_mode(&self) -> BracketMode {
+        self.bracket_mode
+    }
+    // Keeps every byte consumed by `,` so that it can be replayed later.
+    pub fn enable_input_recording(&mut self) {
+        self.input_record = Some(Vec::new());
+    }
+    pub fn recorded_input(&self) -> Option<&[u8]> {
+        self.input_record.as_deref()
+    }
+    // Keeps every input and output of the following runs, in order.
+    pub fn enable_io_trace(&mut self) {
+        self.io_trace = Some(Vec::new());
+    }
+    pub fn io_trace(&self) -> Option<&[IoEvent]> {
+        self.io_trace.as_deref()
+    }
+    // Writes a JSON line to `trace` before each instruction run: the step,
+    // the pointer, the current cell and the location. The optimized engine
+    // writes one per instruction of its code, e.g. one for a whole `[-]`.
+    // The trace is written as the program runs rather than kept, and is
+    // flushed at the end of each run.
+    pub fn set_step_trace(&mut self, trace: Box<dyn Write + Send>) {
+        self.step_trace = Some(trace);
+    }
+    // Keeps the warnings of the following runs, each kind once per location
+    // so that a loop does not repeat them. The optimized engine folds runs
+    // of `+` and `-`, and loops such as `[->+<]`, and warns when their sum
+    // wraps a cell, at the first command of the run or the loop.
+    pub fn enable_warnings(&mut self) {
+        self.warnings = Some(Vec::new());
+    }
+    pub fn warnings(&self) -> Option<&[Warning]> {
+        self.warnings.as_deref()
+    }
+    // The message of an error of this machine. That of an InputError has the
+    // OS's own words, which the kind alone does not keep.
+    pub fn error_message(&self, kind: InterpreterErrorKind) -> String {
+        match (kind, &self.input_error) {
+            (InterpreterErrorKind::InputError(_), Some(message)) => {
+                format!("could not read the input: {}", message)
+            }
+            _ => kind.to_string(),
+        }
+    }
+    // `,` reads a line from stdin and takes its first byte unless another
+    // source is set with one of these.
+    pub fn set_input_source(&mut self, input: Box<dyn InputSource + Send>) {
+        self.input = input;
+    }
+    // False when `,` may have to wait for its input.
+    pub fn input_ready(&self) -> bool {
+        self.input.is_ready()
+    }
+    // `,` reads one byte at a time from the reader.
+    pub fn set_input(&mut self, input: Box<dyn Read + Send>) {
+        self.input = Box::new(ReaderInput::new(input));
+    }
+    // Once the input set so far has ended, `,` goes on reading from `next`.
+    pub fn chain_input(&mut self, next: Box<dyn InputSource + Send>) {
+        let source = std::mem::replace(&mut self.input, Box::new(BytesInput::default()));
+        self.input = Box::new(ChainInput::new(vec![source, next]));
+    }
+    // Writes `prompt` to stderr before each `,` that waits for the input set
+    // so far, e.g. for the user to type.
+    pub fn set_input_prompt(&mut self, prompt: &str) {
+        let source = std::mem::replace(&mut self.input, Box::new(BytesInput::default()));
+        self.input = Box::new(PromptInput::new(source, prompt, io::stderr()));
+    }
+    // Makes `,` wait at most `timeout` for the input set so far, then do
+    // what `fallback` says.
+    pub fn set_input_timeout(&mut self, timeout: Duration, fallback: TimeoutFallback) {
+        let source = std::mem::replace(&mut self.input, Box::new(BytesInput::default()));
+        self.input = Box::new(TimeoutInput::new(source, timeout, fallback));
+    }
+    // Takes anything yielding bytes: a Vec, a slice iterator,
+    // `str::bytes()`...
+    pub fn set_input_bytes<I: IntoIterator<Item = u8>>(&mut self, input: I) {
+        self.input = Box::new(BytesInput::new(input.into_iter().collect()));
+    }
+    // `,` reads the bytes of the iterator as it gets to them, so a large or
+    // endless input need not be held in memory.
+    pub fn set_input_iter<I>(&mut self, input: I)
+    where
+        I: IntoIterator<Item = u8>,
+        I::IntoIter: Send + 'static,
+    {
+        self.input = Box::new(IterInput(input.into_iter()));
+    }
+    // `,` reads what the closure returns, the EOF value once it returns None.
+    pub fn set_input_fn<F: FnMut() -> Option<u8> + Send + 'static>(&mut self, input: F) {
+        self.input = Box::new(FnInput(input));
+    }
+    // `,` reads the text a byte at a time, newlines included, then gets the
+    // EOF value.
+    pub fn set_input_stream(&mut self, input_stream: String) {
+        self.input = Box::new(BytesInput::from(input_stream.as_str()));
+    }
+    fn eval_increment_pointer(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        self.move_pointer(1, command.location)?;
         self.program_cursor += 1;
         Ok(self.pointer)
     }
@@ -53,113 +616,485 @@ impl SimpleInterpreter {
         self.program_cursor += 1;
         Ok(self.pointer)
     }
-    fn eval_increment(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+    fn eval_increment(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        self.check_wrap(self.cells[self.pointer], 1, command.location);
         self.cells[self.pointer] = self.cells[self.pointer].wrapping_add(1);
         self.program_cursor += 1;
         Ok(self.cells[self.pointer] as usize)
     }
 
-    fn eval_decrement(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+    fn eval_decrement(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        self.check_wrap(self.cells[self.pointer], -1, command.location);
         self.cells[self.pointer] = self.cells[self.pointer].wrapping_sub(1);
         self.program_cursor += 1;
         Ok(self.cells[self.pointer] as usize)
     }
 
-    fn eval_output(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
-        print!("{}", self.cells[self.pointer] as char);
+    fn eval_output(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        self.write_byte(command.location)?;
         self.program_cursor += 1;
         Ok(self.cells[self.pointer] as usize)
     }
-    fn eval_input(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
-        let value = if let Some(ref mut input) = self.input_stream {
-            if input.len() > 0 {
-                input.pop().unwrap()
-            } else {
-                // 改行の値
-                10
-            }
-        } else {
-            let mut buf = String::new();
-            std::io::stdin()
-                .read_line(&mut buf)
-                .expect("read_line error");
-            buf.as_bytes()[0]
-        };
-        self.cells[self.pointer] = value;
+    fn eval_call(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.call_host();
         self.program_cursor += 1;
         Ok(self.cells[self.pointer] as usize)
     }
+    fn eval_switch_tape(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.switch_tape();
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_transfer(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.transfer();
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_end(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.program_cursor = self.program.len();
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_store(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.store();
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_retrieve(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
+        self.retrieve();
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_literal(&mut self, digit: u8) -> Result<usize, InterpreterError> {
+        self.cells[self.pointer] = digit * 16;
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+    fn eval_input(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        self.cells[self.pointer] = self.read_byte(command.location)?;
+        self.program_cursor += 1;
+        Ok(self.cells[self.pointer] as usize)
+    }
+
+    // The helpers below are shared with the other engines.
+
+    pub(crate) fn write_byte(&mut self, location: Location) -> Result<(), InterpreterError> {
+        let value = self.cells[self.pointer];
+        let mut utf8 = [0; 4];
+        let bytes = match self.output_encoding {
+            OutputEncoding::Bytes => std::slice::from_ref(&value),
+            OutputEncoding::Utf8 => char::from(value).encode_utf8(&mut utf8).as_bytes(),
+        };
+        if self.output.write_all(bytes).is_err() {
+            return Err(InterpreterError {
+                value: InterpreterErrorKind::OutputError,
+                location,
+            });
+        }
+        self.bytes_written += bytes.len() as u64;
+        self.trace(TokenKind::Output, self.cells[self.pointer], location);
+        Ok(())
+    }
+    pub(crate) fn call_host(&mut self) {
+        if let Some(ref mut states) = self.loop_states {
+            states.clear();
+        }
+        let limit = self.tape_limit();
+        if let Some(ref mut function) = self.host_call {
+            function(&mut HostContext {
+                cells: &mut self.cells,
+                pointer: self.pointer,
+                limit,
+            });
+        }
+    }
+    pub(crate) fn switch_tape(&mut self) {
+        std::mem::swap(&mut self.cells, &mut self.other_cells);
+        std::mem::swap(&mut self.pointer, &mut self.other_pointer);
+    }
+    pub(crate) fn transfer(&mut self) {
+        // both pointers always point into their tape
+        self.other_cells[self.other_pointer] = self.cells[self.pointer];
+    }
+    pub(crate) fn store(&mut self) {
+        self.storage = self.cells[self.pointer];
+    }
+    pub(crate) fn retrieve(&mut self) {
+        self.cells[self.pointer] = self.storage;
+    }
+    pub(crate) fn read_byte(&mut self, location: Location) -> Result<u8, InterpreterError> {
+        // show prompts written without a trailing newline, but only when
+        // the read may block
+        if !self.input.is_ready() {
+            let _ = self.output.flush();
+        }
+        let value = match self.input.read_byte() {
+            Ok(Some(value)) => value,
+            Ok(None) if self.eof_behavior == EofBehavior::Error => {
+                return Err(InterpreterError {
+                    value: InterpreterErrorKind::InputExhausted,
+                    location,
+                })
+            }
+            Ok(None) => {
+                self.warn(WarningKind::ReadPastEnd, location);
+                self.eof_value()
+            }
+            Err(e) => {
+                self.input_error = Some(e.to_string());
+                return Err(InterpreterError {
+                    value: InterpreterErrorKind::InputError(e.kind()),
+                    location,
+                });
+            }
+        };
+        if let Some(ref mut record) = self.input_record {
+            record.push(value);
+        }
+        self.bytes_read += 1;
+        self.trace(TokenKind::Input, value, location);
+        Ok(value)
+    }
+    fn trace(&mut self, command: TokenKind, byte: u8, location: Location) {
+        if let Some(ref mut trace) = self.io_trace {
+            trace.push(IoEvent {
+                command,
+                byte,
+                location,
+                pointer: self.pointer,
+                step: self.steps,
+            });
+        }
+    }
+    fn warn(&mut self, kind: WarningKind, location: Location) {
+        if let Some(ref mut warnings) = self.warnings {
+            let warning = Warning {
+                value: kind,
+                location,
+            };
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+    }
+    // Before adding `n` to a cell holding `cell`.
+    pub(crate) fn check_wrap(&mut self, cell: u8, n: i32, location: Location) {
+        if !(0..=255).contains(&(i32::from(cell) + n)) {
+            self.warn(WarningKind::CellWrapped, location);
+        }
+    }
+    fn eof_value(&self) -> u8 {
+        match self.eof_behavior {
+            // 改行の値
+            EofBehavior::Newline => 10,
+            // Error stops the program before
+            EofBehavior::Zero | EofBehavior::Error => 0,
+            EofBehavior::MinusOne => 255,
+            EofBehavior::Unchanged => self.cells[self.pointer],
+        }
+    }
+    pub(crate) fn count_step(&mut self, location: Location) -> Result<(), InterpreterError> {
+        if self.step_limit == Some(self.steps) {
+            return Err(InterpreterError {
+                value: InterpreterErrorKind::StepLimitExceeded,
+                location,
+            });
+        }
+        self.steps += 1;
+        if let Some(ref mut trace) = self.step_trace {
+            let event = Json::object(vec![
+                ("step", self.steps.into()),
+                ("pointer", self.pointer.into()),
+                ("cell", u64::from(self.cells[self.pointer]).into()),
+                ("location", location.to_json()),
+            ]);
+            let _ = writeln!(trace, "{}", event);
+        }
+        if self.steps.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report_progress();
+        }
+        Ok(())
+    }
+    pub(crate) fn check_interrupt(&self, location: Location) -> Result<(), InterpreterError> {
+        match self.interrupt {
+            Some(ref interrupt) if interrupt.load(Ordering::Relaxed) => Err(InterpreterError {
+                value: InterpreterErrorKind::Interrupted,
+                location,
+            }),
+            _ => Ok(()),
+        }
+    }
+    // Called at the jump back of a loop, `pc` telling the loop apart.
+    pub(crate) fn check_loop(
+        &mut self,
+        pc: usize,
+        location: Location,
+    ) -> Result<(), InterpreterError> {
+        let states = match self.loop_states {
+            Some(ref mut states) => states,
+            None => return Ok(()),
+        };
+        let mut hasher = DefaultHasher::new();
+        (pc, self.pointer, &self.cells[..], self.bytes_read).hash(&mut hasher);
+        (self.other_pointer, &self.other_cells[..], self.storage).hash(&mut hasher);
+        if states.insert(hasher.finish()) {
+            Ok(())
+        } else {
+            Err(InterpreterError {
+                value: InterpreterErrorKind::NonTerminating,
+                location,
+            })
+        }
+    }
+    pub(crate) fn move_pointer(
+        &mut self,
+        offset: isize,
+        location: Location,
+    ) -> Result<(), InterpreterError> {
+        if offset < 0 && self.pointer < offset.unsigned_abs() {
+            return Err(InterpreterError {
+                value: InterpreterErrorKind::PointerError,
+                location,
+            });
+        }
+        let pointer = self.pointer;
+        self.pointer = (pointer as isize + offset) as usize;
+        if !self.reach_pointer() {
+            self.pointer = pointer;
+            return Err(InterpreterError {
+                value: InterpreterErrorKind::PointerError,
+                location,
+            });
+        }
+        Ok(())
+    }
+    // Grows the tape up to the pointer, which has just moved. False if the
+    // tape cannot hold it.
+    pub(crate) fn reach_pointer(&mut self) -> bool {
+        self.max_pointer = self.max_pointer.max(self.pointer);
+        self.grow_tape(self.pointer + 1)
+    }
+    // False if the tape cannot hold `len` cells, past its limit or because
+    // its file could not grow.
+    pub(crate) fn grow_tape(&mut self, len: usize) -> bool {
+        if self.cells.len() < len {
+            if len > self.tape_limit() {
+                return false;
+            }
+            if self.cells.reallocates(len) {
+                self.tape_growths += 1;
+            }
+            return self.cells.grow(len).is_ok();
+        }
+        true
+    }
+    // Called when a loop is entered, to track the nesting reached.
+    pub(crate) fn enter_loop(&mut self) {
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+    pub(crate) fn leave_loop(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+    pub(crate) fn reset_tape(&mut self) {
+        self.cells = Tape::new(self.tape_kind);
+        self.pointer = 0;
+        self.other_cells = Tape::default();
+        self.other_pointer = 0;
+        self.storage = 0;
+        self.steps = 0;
+        self.cycles = 0;
+        self.max_pointer = 0;
+        self.tape_growths = 0;
+        self.depth = 0;
+        self.max_depth = 0;
+        self.bytes_read = 0;
+        self.bytes_written = 0;
+        if let Some(ref mut states) = self.loop_states {
+            states.clear();
+        }
+        if let Some(ref mut warnings) = self.warnings {
+            warnings.clear();
+        }
+    }
+    fn report_progress(&self) {
+        if let Some(ref progress) = self.progress {
+            progress.store(self.steps, Ordering::Relaxed);
+        }
+    }
+    // Flushes the output at the end of a run. A flush failure is reported at `location`.
+    pub(crate) fn finish(
+        &mut self,
+        result: Result<usize, InterpreterError>,
+        location: Option<Location>,
+    ) -> Result<usize, InterpreterError> {
+        self.report_progress();
+        self.cells.save_pointer(self.pointer);
+        self.other_cells.save_pointer(self.other_pointer);
+        if let Some(ref mut trace) = self.step_trace {
+            let _ = trace.flush();
+        }
+        if self.output.flush().is_err() && result.is_ok() {
+            if let Some(location) = location {
+                return Err(InterpreterError {
+                    value: InterpreterErrorKind::OutputError,
+                    location,
+                });
+            }
+        }
+        result
+    }
     fn eval_jump_forward(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
         if self.cells[self.pointer] != 0 {
+            self.enter_loop();
             self.program_cursor += 1;
             return Ok(self.cells[self.pointer] as usize);
         }
 
         self.program_cursor = *self.jump_table.get(&self.program_cursor).unwrap();
+        if self.program_cursor < self.program.len() {
+            // the `]` it jumps to leaves the loop
+            self.depth += 1;
+        }
         Ok(self.cells[self.pointer] as usize)
     }
-    fn eval_jump_backward(&mut self, _command: &Token) -> Result<usize, InterpreterError> {
-        if self.cells[self.pointer] == 0 {
-            self.program_cursor += 1;
-            return Ok(self.cells[self.pointer] as usize);
-        }
-        self.program_cursor = *self.jump_table.get(&self.program_cursor).unwrap();
+    fn eval_jump_backward(&mut self, command: &Token) -> Result<usize, InterpreterError> {
+        // an unmatched `]` in permissive mode has no jump
+        let target = match self.jump_table.get(&self.program_cursor) {
+            // the `[` it jumps back to enters the loop again
+            Some(&target) if self.cells[self.pointer] != 0 => {
+                self.leave_loop();
+                target
+            }
+            Some(_) => {
+                self.leave_loop();
+                self.program_cursor += 1;
+                return Ok(self.cells[self.pointer] as usize);
+            }
+            None => {
+                self.program_cursor += 1;
+                return Ok(self.cells[self.pointer] as usize);
+            }
+        };
+        self.check_loop(self.program_cursor, command.location)?;
+        self.program_cursor = target;
         Ok(self.cells[self.pointer] as usize)
     }
     fn init(&mut self) -> Result<usize, InterpreterError> {
-        self.cells = vec![0 as u8];
-        let mut forward_brackets = vec![];
-        let mut dep = 1;
-        for i in 0..self.program.len() {
-            match self.program[i].value {
-                TokenKind::JumpForward => forward_brackets.push(i),
-                TokenKind::JumpBackward => {
-                    if forward_brackets.len() > 0 {
-                        let forward = forward_brackets.pop().unwrap();
-                        self.jump_table.insert(i, forward);
-                        self.jump_table.insert(forward, i);
-                    } else {
-                        return Err(InterpreterError {
-                            value: InterpreterErrorKind::UnmatchedJumpForwardError,
-                            location: self.program[i].location,
-                        });
-                    }
-                }
-                _ => {}
-            };
-        }
-        if forward_brackets.len() > 0 {
-            return Err(InterpreterError {
-                value: InterpreterErrorKind::UnmatchedJumpForwardError,
-                location: self.program[forward_brackets[0]].location,
-            });
+        self.reset_tape();
+        self.program_cursor = 0;
+        self.check_nesting(&self.program)?;
+        self.jump_table = Arc::new(match self.bracket_mode {
+            BracketMode::Strict => analysis::match_brackets(&self.program)?,
+            BracketMode::Permissive => analysis::match_brackets_permissive(&self.program),
+        });
+        if let Some(ref mut profile) = self.profile {
+            *profile = vec![0; self.program.len()];
         }
         Ok(0)
     }
 
     pub fn eval(&mut self, program: &Program) -> Result<usize, InterpreterError> {
-        self.program = (*program).clone();
-        if let Err(e) = self.init() {
-            return Err(e);
-        };
-        while self.program_cursor < self.program.len() {
-            let command = &self.program[self.program_cursor].clone();
-            let res = match command.value {
-                TokenKind::IncrementPointer => self.eval_increment_pointer(command),
-                TokenKind::DecrementPointer => self.eval_decrement_pointer(command),
-                TokenKind::Increment => self.eval_increment(command),
-                TokenKind::Decrement => self.eval_decrement(command),
-                TokenKind::Output => self.eval_output(command),
-                TokenKind::Input => self.eval_input(command),
-                TokenKind::JumpForward => self.eval_jump_forward(command),
-                TokenKind::JumpBackward => self.eval_jump_backward(command),
-            };
-            if res.is_err() {
-                return Err(res.err().unwrap());
-            }
+        compiled::replace(&mut self.program, program);
+        self.init()?;
+        self.run()
+    }
+
+    // Appends tokens to the program evaluated so far and continues from where
+    // it stopped, keeping the tape and the pointer. The appended tokens must
+    // have balanced brackets. If they fail, the rest of them is skipped.
+    pub fn eval_append(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        let jump_table = analysis::match_brackets(program)?;
+        self.check_nesting(program)?;
+        let offset = self.program.len();
+        self.program_cursor = offset;
+        Arc::make_mut(&mut self.program).extend_from_slice(program);
+        let table = Arc::make_mut(&mut self.jump_table);
+        for (&from, &to) in jump_table.iter() {
+            table.insert(from + offset, to + offset);
+        }
+        if let Some(ref mut profile) = self.profile {
+            profile.resize(self.program.len(), 0);
         }
+        let result = self.run();
+        if result.is_err() {
+            self.program_cursor = self.program.len();
+        }
+        result
+    }
+
+    // Prepares a program compiled earlier for step or run, sharing it.
+    pub fn load_compiled(&mut self, compiled: &CompiledProgram) {
+        self.reset_tape();
+        self.program_cursor = 0;
+        self.program = compiled.program.clone();
+        self.jump_table = compiled.jump_table.clone();
+        if let Some(ref mut profile) = self.profile {
+            *profile = vec![0; self.program.len()];
+        }
+    }
+
+    // Runs the loaded program from where it stopped to the end and flushes
+    // the output.
+    pub fn run(&mut self) -> Result<usize, InterpreterError> {
+        let result = self.run_commands();
+        let last = self.program.last().map(|t| t.location);
+        self.finish(result, last)
+    }
+
+    fn run_commands(&mut self) -> Result<usize, InterpreterError> {
+        while self.step()? {}
         Ok(0)
     }
+
+    // Prepares the program for step without running it.
+    pub fn load(&mut self, program: &Program) -> Result<(), InterpreterError> {
+        compiled::replace(&mut self.program, program);
+        self.init().map(|_| ())
+    }
+
+    // Runs the next command of the program given to load. Returns false once
+    // the program has ended. Output is not flushed; see set_output.
+    pub fn step(&mut self) -> Result<bool, InterpreterError> {
+        if self.program_cursor >= self.program.len() {
+            return Ok(false);
+        }
+        let location = self.program[self.program_cursor].location;
+        self.check_interrupt(location)?;
+        self.count_step(location)?;
+        self.cycles += 1;
+        if let Some(ref mut profile) = self.profile {
+            profile[self.program_cursor] += 1;
+        }
+        // a copy, tokens being small
+        let command = &{ self.program[self.program_cursor] };
+        match command.value {
+            TokenKind::IncrementPointer => self.eval_increment_pointer(command),
+            TokenKind::DecrementPointer => self.eval_decrement_pointer(command),
+            TokenKind::Increment => self.eval_increment(command),
+            TokenKind::Decrement => self.eval_decrement(command),
+            TokenKind::Output => self.eval_output(command),
+            TokenKind::Input => self.eval_input(command),
+            TokenKind::JumpForward => self.eval_jump_forward(command),
+            TokenKind::JumpBackward => self.eval_jump_backward(command),
+            TokenKind::Call => self.eval_call(command),
+            TokenKind::SwitchTape => self.eval_switch_tape(command),
+            TokenKind::Transfer => self.eval_transfer(command),
+            TokenKind::End => self.eval_end(command),
+            TokenKind::Store => self.eval_store(command),
+            TokenKind::Retrieve => self.eval_retrieve(command),
+            TokenKind::Literal(digit) => self.eval_literal(digit),
+        }?;
+        Ok(true)
+    }
+
+    // Where the next command to step is, or None at the end of the program.
+    pub fn next_location(&self) -> Option<Location> {
+        self.program.get(self.program_cursor).map(|t| t.location)
+    }
+    // The next command to step, or None at the end of the program.
+    pub fn next_command(&self) -> Option<TokenKind> {
+        self.program.get(self.program_cursor).map(|t| t.value)
+    }
 }
 
 #[test]
@@ -167,14 +1102,62 @@ fn test_interpreter() {
     use crate::lexer::{Location, Token};
     let mut interpreter = SimpleInterpreter::new();
     let program = vec![
-        Token::increment(Location { line: 1, col: 1 }),
-        Token::increment(Location { line: 1, col: 2 }),
-        Token::increment_pointer(Location { line: 1, col: 3 }),
-        Token::increment(Location { line: 1, col: 4 }),
-        Token::increment(Location { line: 1, col: 5 }),
-        Token::decrement(Location { line: 2, col: 1 }),
-        Token::increment_pointer(Location { line: 2, col: 2 }),
-        Token::decrement_pointer(Location { line: 2, col: 3 }),
+        Token::increment(Location {
+            line: 1,
+            col: 1,
+            file: 0,
+            offset: 0,
+            end: 1,
+        }),
+        Token::increment(Location {
+            line: 1,
+            col: 2,
+            file: 0,
+            offset: 1,
+            end: 2,
+        }),
+        Token::increment_pointer(Location {
+            line: 1,
+            col: 3,
+            file: 0,
+            offset: 2,
+            end: 3,
+        }),
+        Token::increment(Location {
+            line: 1,
+            col: 4,
+            file: 0,
+            offset: 3,
+            end: 4,
+        }),
+        Token::increment(Location {
+            line: 1,
+            col: 5,
+            file: 0,
+            offset: 4,
+            end: 5,
+        }),
+        Token::decrement(Location {
+            line: 2,
+            col: 1,
+            file: 0,
+            offset: 6,
+            end: 7,
+        }),
+        Token::increment_pointer(Location {
+            line: 2,
+            col: 2,
+            file: 0,
+            offset: 7,
+            end: 8,
+        }),
+        Token::decrement_pointer(Location {
+            line: 2,
+            col: 3,
+            file: 0,
+            offset: 8,
+            end: 9,
+        }),
     ];
 
     assert_eq!(interpreter.cells, vec![0]);
@@ -182,3 +1165,426 @@ fn test_interpreter() {
     assert_eq!(interpreter.cells, vec![2, 1, 0]);
     assert_eq!(interpreter.pointer, 1);
 }
+
+#[test]
+fn test_interrupt() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    let flag = Arc::new(AtomicBool::new(true));
+    interpreter.set_interrupt_flag(flag);
+    let result = interpreter.eval(&Lexer::lex("+[]"));
+    assert_eq!(
+        result.map_err(|e| e.value),
+        Err(InterpreterErrorKind::Interrupted)
+    );
+    assert_eq!(interpreter.steps(), 0);
+}
+
+#[test]
+fn test_eval_append() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        assert_eq!(engine.eval(&Lexer::lex("+++>")), Ok(0));
+        assert_eq!(engine.eval_append(&Lexer::lex("++[-<+>]")), Ok(0));
+        assert_eq!(engine.machine().cells, vec![5, 0]);
+        let error = engine.eval_append(&Lexer::lex("<<+")).unwrap_err();
+        assert_eq!(error.location.col, 2);
+        assert!(engine.eval_append(&Lexer::lex("[")).is_err());
+        assert_eq!(engine.eval_append(&Lexer::lex("+")), Ok(0));
+        assert_eq!(engine.machine().cells, vec![6, 0]);
+    }
+}
+
+#[test]
+fn test_input_recording() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.enable_input_recording();
+    interpreter.set_input_bytes(b"a\nb".to_vec());
+    assert_eq!(interpreter.eval(&Lexer::lex(",,,")), Ok(0));
+    assert_eq!(interpreter.recorded_input(), Some(&b"a\nb"[..]));
+}
+
+#[test]
+fn test_input_bytes_from_iterator() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_input_bytes("xy".bytes().rev());
+    assert_eq!(interpreter.eval(&Lexer::lex(",>,")), Ok(0));
+    assert_eq!(interpreter.cells, vec![b'y', b'x']);
+}
+
+#[test]
+fn test_eof_behavior() {
+    use crate::engine::Engine;
+    use crate::lexer::Lexer;
+    use crate::optimized::OptimizedInterpreter;
+    let program = Lexer::lex("+++,");
+    for (behavior, expected) in [
+        (EofBehavior::Newline, 10),
+        (EofBehavior::Zero, 0),
+        (EofBehavior::MinusOne, 255),
+        (EofBehavior::Unchanged, 3),
+    ]
+    .iter()
+    {
+        let mut interpreter = SimpleInterpreter::new();
+        interpreter.set_input_bytes(vec![]);
+        interpreter.set_eof_behavior(*behavior);
+        assert_eq!(interpreter.eval(&program), Ok(0));
+        assert_eq!(interpreter.cells, vec![*expected]);
+    }
+    assert_eq!("-1".parse(), Ok(EofBehavior::MinusOne));
+
+    // input piped in that ends mid-program, for every read after the end
+    let program = Lexer::lex(",>,>+,");
+    for engine in [
+        &mut SimpleInterpreter::new() as &mut dyn Engine,
+        &mut OptimizedInterpreter::new(),
+    ] {
+        let machine = engine.machine_mut();
+        machine.set_input(Box::new(io::Cursor::new(b"a".to_vec())));
+        machine.set_eof_behavior(EofBehavior::MinusOne);
+        assert_eq!(engine.eval(&program), Ok(0));
+        assert_eq!(engine.machine().cells(), &[b'a', 255, 255]);
+
+        let machine = engine.machine_mut();
+        machine.set_input_bytes(b"a".to_vec());
+        machine.set_eof_behavior(EofBehavior::Error);
+        let error = engine.eval(&program).unwrap_err();
+        assert_eq!(error.value, InterpreterErrorKind::InputExhausted);
+        assert_eq!(error.location.col, 3);
+    }
+    assert_eq!("error".parse(), Ok(EofBehavior::Error));
+}
+
+#[test]
+fn test_output() {
+    use crate::lexer::Lexer;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = SharedBuffer::default();
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_output(Box::new(buffer.clone()));
+    assert_eq!(interpreter.eval(&Lexer::lex("-.+.")), Ok(0));
+    assert_eq!(*buffer.0.lock().unwrap(), vec![255, 0]);
+
+    let buffer = SharedBuffer::default();
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_output(Box::new(buffer.clone()));
+    interpreter.set_output_encoding(OutputEncoding::Utf8);
+    assert_eq!(interpreter.eval(&Lexer::lex("-.+.")), Ok(0));
+    assert_eq!(*buffer.0.lock().unwrap(), "\u{ff}\0".as_bytes());
+    assert_eq!(interpreter.stats().bytes_written, 3);
+}
+
+#[test]
+fn test_binary_io() {
+    use crate::lexer::Lexer;
+    use crate::spec::Capture;
+
+    // every byte, line ends included, goes through unchanged
+    let bytes: Vec<u8> = (0..=255).rev().collect();
+    let output = Capture::default();
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_input(Box::new(io::Cursor::new(bytes.clone())));
+    interpreter.set_output(Box::new(output.clone()));
+    assert_eq!(interpreter.eval(&Lexer::lex(&",.".repeat(256))), Ok(0));
+    assert_eq!(*output.0.lock().unwrap(), bytes);
+}
+
+#[test]
+fn test_stats() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_output(Box::new(io::sink()));
+    interpreter.set_input_bytes(b"ab".to_vec());
+    assert_eq!(interpreter.eval(&Lexer::lex(",>>,.<<")), Ok(0));
+    assert_eq!(
+        interpreter.stats(),
+        Stats {
+            steps: 7,
+            cycles: 7,
+            peak_tape: 3,
+            max_pointer: 2,
+            tape_growths: 1,
+            max_depth: 0,
+            bytes_read: 2,
+            bytes_written: 1,
+        }
+    );
+}
+
+#[test]
+fn test_input_stream() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_input_stream("a\nb".to_string());
+    interpreter.set_eof_behavior(EofBehavior::MinusOne);
+    assert_eq!(interpreter.eval(&Lexer::lex(",>,>,>,")), Ok(0));
+    assert_eq!(interpreter.cells(), &[b'a', b'\n', b'b', 255]);
+
+    interpreter.set_input_stream("x".to_string());
+    interpreter.set_eof_behavior(EofBehavior::Error);
+    let e = interpreter.eval(&Lexer::lex(",,")).unwrap_err();
+    assert_eq!(
+        (e.value, e.location.col),
+        (InterpreterErrorKind::InputExhausted, 2)
+    );
+}
+
+#[test]
+fn test_input_reader() {
+    use crate::engine::Engine;
+    use crate::lexer::Lexer;
+    use crate::optimized::OptimizedInterpreter;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_input(Box::new(io::Cursor::new(b"a\nb".to_vec())));
+    interpreter.set_eof_behavior(EofBehavior::Zero);
+    assert_eq!(interpreter.eval(&Lexer::lex(",>,>,>,")), Ok(0));
+    assert_eq!(interpreter.cells(), &[b'a', b'\n', b'b', 0]);
+    // an endless input, read only as far as `,` goes
+    interpreter.set_input_iter((1..).map(|n: u32| n as u8));
+    assert_eq!(interpreter.eval(&Lexer::lex(",>,>,")), Ok(0));
+    assert_eq!(interpreter.cells(), &[1, 2, 3]);
+    let mut next: u8 = 10;
+    interpreter.set_input_fn(move || {
+        next = next.saturating_sub(5);
+        Some(next).filter(|&n| n > 0)
+    });
+    assert_eq!(interpreter.eval(&Lexer::lex(",>,>,")), Ok(0));
+    assert_eq!(interpreter.cells(), &[5, 0, 0]);
+
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stdin was closed",
+            ))
+        }
+    }
+    for engine in [
+        &mut interpreter as &mut dyn Engine,
+        &mut OptimizedInterpreter::new(),
+    ] {
+        engine.machine_mut().set_input(Box::new(Broken));
+        let e = engine.eval(&Lexer::lex("+\n>,")).unwrap_err();
+        assert_eq!(
+            e.value,
+            InterpreterErrorKind::InputError(io::ErrorKind::BrokenPipe)
+        );
+        assert_eq!(e.location.to_string(), "2:2");
+        assert_eq!(e.value.to_string(), "could not read the input: broken pipe");
+        assert_eq!(
+            engine.machine().error_message(e.value),
+            "could not read the input: stdin was closed"
+        );
+    }
+    assert_eq!(
+        interpreter.error_message(InterpreterErrorKind::PointerError),
+        "pointer moved below cell 0"
+    );
+}
+
+#[test]
+fn test_step_limit() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    interpreter.set_step_limit(Some(3));
+    assert_eq!(interpreter.eval(&Lexer::lex("+++")), Ok(0));
+    let result = interpreter.eval(&Lexer::lex("+[]"));
+    assert_eq!(
+        result.map_err(|e| (e.value, e.location.col)),
+        Err((InterpreterErrorKind::StepLimitExceeded, 2))
+    );
+    assert_eq!(interpreter.steps(), 3);
+}
+
+#[test]
+fn test_tape_size() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    for &kind in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let mut interpreter = kind.create();
+        interpreter.machine_mut().set_tape_size(Some(3));
+        assert_eq!(interpreter.eval(&Lexer::lex(">>+")), Ok(0));
+        let result = interpreter.eval(&Lexer::lex(">>+>"));
+        assert_eq!(
+            result.map_err(|e| (e.value, e.location.col)),
+            Err((InterpreterErrorKind::PointerError, 4)),
+            "{:?}",
+            kind
+        );
+        assert_eq!(interpreter.machine().cells().len(), 3);
+        assert_eq!(interpreter.machine().pointer(), 2);
+    }
+}
+
+#[test]
+fn test_host_call() {
+    use crate::lexer::Lexer;
+    let mut interpreter = SimpleInterpreter::new();
+    // stores the sum of the two cells before the pointer
+    interpreter.set_host_call(Box::new(|context: &mut HostContext| {
+        let sum = context.cell(-2).wrapping_add(context.cell(-1));
+        context.set_cell(0, sum);
+        context.set_cell(2, context.pointer() as u8);
+    }));
+    assert_eq!(interpreter.eval(&Lexer::lex_with_calls("++>+++>%")), Ok(0));
+    assert_eq!(interpreter.cells, vec![2, 3, 5, 0, 2]);
+    assert_eq!(interpreter.eval(&Lexer::lex("+%")), Ok(0));
+    assert_eq!(interpreter.cells, vec![1]);
+
+    let mut optimized = crate::engine::EngineKind::Optimized.create();
+    optimized
+        .machine_mut()
+        .set_host_call(Box::new(|context: &mut HostContext| {
+            context.set_cell(0, 42)
+        }));
+    assert_eq!(optimized.eval(&Lexer::lex_with_calls("+[%[-]]")), Ok(0));
+    assert_eq!(optimized.machine().cells(), &[0]);
+    assert_eq!(optimized.eval(&Lexer::lex_with_calls("+>%")), Ok(0));
+    assert_eq!(optimized.machine().cells(), &[1, 42]);
+}
+
+#[test]
+fn test_tapes() {
+    use crate::engine::EngineKind;
+    use crate::lexer::{Extensions, Lexer};
+    let tapes = Extensions {
+        tapes: true,
+        ..Extensions::default()
+    };
+    // doubles 3 on the second tape and copies the result back
+    let program = Lexer::lex_with("+++^~[->++<]>^~>", tapes);
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        assert_eq!(engine.eval(&program), Ok(0));
+        assert_eq!(engine.machine().cells(), &[6, 0]);
+        // a new run starts with both tapes cleared
+        assert_eq!(engine.eval(&Lexer::lex_with("~", tapes)), Ok(0));
+        assert_eq!(engine.machine().cells(), &[0]);
+    }
+    // standard programs are not affected
+    let mut interpreter = SimpleInterpreter::new();
+    assert_eq!(interpreter.eval(&Lexer::lex("+~^")), Ok(0));
+    assert_eq!(interpreter.cells, vec![1]);
+}
+
+#[test]
+fn test_extended() {
+    use crate::engine::EngineKind;
+    use crate::lexer::{Extensions, Lexer};
+    use crate::spec::Capture;
+    let extended = Extensions {
+        extended: true,
+        ..Extensions::default()
+    };
+    // 'A' from 4 * 16 + 1, kept in storage over a cleared cell, then `@`
+    // ends the program before the last `.`
+    let program = Lexer::lex_with("4+$[-]>!.<2+.@.", extended);
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let output = Capture::default();
+        let mut engine = kind.create();
+        engine.machine_mut().set_output(Box::new(output.clone()));
+        assert_eq!(engine.eval(&program), Ok(0));
+        assert_eq!(*output.0.lock().unwrap(), b"A!");
+        assert_eq!(engine.machine().cells(), &[33, 65]);
+    }
+    let printed: String = program.iter().map(|t| t.value.to_string()).collect();
+    assert_eq!(printed, "4+$[-]>!.<2+.@.");
+    // comments otherwise
+    assert_eq!(Lexer::lex("@$!0F").len(), 0);
+}
+
+#[test]
+fn test_loop_detection() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        engine.machine_mut().set_step_limit(Some(100_000));
+        engine.machine_mut().set_eof_behavior(EofBehavior::Zero);
+        engine.machine_mut().enable_loop_detection();
+        let error = engine.eval(&Lexer::lex("++[]")).unwrap_err();
+        assert_eq!(error.value, InterpreterErrorKind::NonTerminating);
+        assert_eq!(error.location.col, 4);
+        // the second cell wraps around after 256 rounds
+        let error = engine.eval(&Lexer::lex("+[>+<]")).unwrap_err();
+        assert_eq!(error.value, InterpreterErrorKind::NonTerminating);
+        assert!(engine.machine().steps() < 2000);
+        // reading more input is progress
+        engine.machine_mut().set_input_bytes(b"abc".to_vec());
+        assert_eq!(engine.eval(&Lexer::lex(",[>+<,]")), Ok(0));
+        assert_eq!(engine.eval(&Lexer::lex("++[-]")), Ok(0));
+    }
+}
+
+#[test]
+fn test_warnings() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        let machine = engine.machine_mut();
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine.enable_warnings();
+        machine.set_input_bytes(b"a".to_vec());
+        // a loop reading past the end of the input three times
+        assert_eq!(engine.eval(&Lexer::lex("->+++[<,>-]")), Ok(0));
+        let warnings: Vec<(WarningKind, usize)> = engine
+            .machine()
+            .warnings()
+            .unwrap()
+            .iter()
+            .map(|w| (w.value, w.location.col))
+            .collect();
+        assert_eq!(
+            warnings,
+            [(WarningKind::CellWrapped, 1), (WarningKind::ReadPastEnd, 8)]
+        );
+        // the optimized engine warns at the loop
+        assert_eq!(engine.eval(&Lexer::lex("+++[>-<-]")), Ok(0));
+        let warnings = engine.machine().warnings().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].value, WarningKind::CellWrapped);
+        assert_eq!(engine.eval(&Lexer::lex("+")), Ok(0));
+        assert_eq!(engine.machine().warnings(), Some(&[][..]));
+    }
+}
+
+#[test]
+fn test_bracket_mode() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+    assert_eq!("permissive".parse(), Ok(BracketMode::Permissive));
+    for kind in [EngineKind::Simple, EngineKind::Optimized] {
+        let mut engine = kind.create();
+        let program = Lexer::lex("+]+[>+<-][>+");
+        assert!(engine.eval(&program).is_err());
+        engine
+            .machine_mut()
+            .set_bracket_mode(BracketMode::Permissive);
+        // the `]` does nothing, and the last `[` is skipped to the end
+        assert_eq!(engine.eval(&program), Ok(0));
+        assert_eq!(engine.machine().cells(), &[0, 2]);
+        // on a cell that is not zero, it goes on to the end
+        assert_eq!(engine.eval(&Lexer::lex("+[>+[-]+")), Ok(0));
+        assert_eq!(engine.machine().cells(), &[1, 1]);
+    }
+}
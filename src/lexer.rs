@@ -1,10 +1,67 @@
+use crate::json::Json;
+use std::fmt;
+use std::thread;
+
+// The size from which Lexer::lex_parallel splits the source, in bytes.
+pub const PARALLEL_LEXING_THRESHOLD: usize = 1 << 20;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Location {
     pub line: usize,
     pub col: usize,
+    // index of the source file when several files are lexed together
+    pub file: usize,
+    // the bytes of the construct in its file, from `offset` to `end`,
+    // excluded; one byte for a token
+    pub offset: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+impl Location {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("line", self.line.into()),
+            ("col", self.col.into()),
+            ("file", self.file.into()),
+            ("offset", self.offset.into()),
+            ("end", self.end.into()),
+        ])
+    }
+    // `file`, `offset` and `end` may be left out for 0.
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        let field = |name| json.get(name).and_then(Json::as_u64).map(|n| n as usize);
+        match (field("line"), field("col")) {
+            (Some(line), Some(col)) => Ok(Self {
+                line,
+                col,
+                file: field("file").unwrap_or(0),
+                offset: field("offset").unwrap_or(0),
+                end: field("end").unwrap_or(0),
+            }),
+            _ => Err("a location needs `line` and `col`".to_string()),
+        }
+    }
+    // The span from this location to the end of `last`, e.g. from a `[` to
+    // its `]` to underline a whole loop.
+    pub fn to(self, last: Location) -> Location {
+        Location {
+            end: last.end,
+            ..self
+        }
+    }
+    // The bytes of the source covered, if the source is the one lexed.
+    pub fn text<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source.get(self.offset..self.end)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Annotation<T> {
     pub value: T,
     pub location: Location,
@@ -25,6 +82,69 @@ pub enum TokenKind {
     Input,
     JumpForward,
     JumpBackward,
+    // `%`, only recognized by Lexer::lex_with_calls
+    Call,
+    // `~` and `^`, only recognized with Extensions::tapes: switch to the
+    // other tape, and copy the current cell to the current cell of the other
+    // tape
+    SwitchTape,
+    Transfer,
+    // Extended Brainfuck Type I, only recognized with Extensions::extended:
+    // `@` ends the program, `$` copies the cell to the storage cell and `!`
+    // copies the storage cell back, and a hex digit `0` to `F` sets the cell
+    // to 16 times its value
+    End,
+    Store,
+    Retrieve,
+    Literal(u8),
+}
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.byte() as char)
+    }
+}
+impl TokenKind {
+    // The character of the command in a source.
+    pub fn byte(self) -> u8 {
+        match self {
+            TokenKind::IncrementPointer => b'>',
+            TokenKind::DecrementPointer => b'<',
+            TokenKind::Increment => b'+',
+            TokenKind::Decrement => b'-',
+            TokenKind::Output => b'.',
+            TokenKind::Input => b',',
+            TokenKind::JumpForward => b'[',
+            TokenKind::JumpBackward => b']',
+            TokenKind::Call => b'%',
+            TokenKind::SwitchTape => b'~',
+            TokenKind::Transfer => b'^',
+            TokenKind::End => b'@',
+            TokenKind::Store => b'$',
+            TokenKind::Retrieve => b'!',
+            TokenKind::Literal(digit) => b"0123456789ABCDEF"[usize::from(digit & 0xf)],
+        }
+    }
+    // The inverse of Display.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '>' => Some(TokenKind::IncrementPointer),
+            '<' => Some(TokenKind::DecrementPointer),
+            '+' => Some(TokenKind::Increment),
+            '-' => Some(TokenKind::Decrement),
+            '.' => Some(TokenKind::Output),
+            ',' => Some(TokenKind::Input),
+            '[' => Some(TokenKind::JumpForward),
+            ']' => Some(TokenKind::JumpBackward),
+            '%' => Some(TokenKind::Call),
+            '~' => Some(TokenKind::SwitchTape),
+            '^' => Some(TokenKind::Transfer),
+            '@' => Some(TokenKind::End),
+            '$' => Some(TokenKind::Store),
+            '!' => Some(TokenKind::Retrieve),
+            '0'..='9' | 'A'..='F' => c.to_digit(16).map(|d| TokenKind::Literal(d as u8)),
+            _ => None,
+        }
+    }
 }
 pub type Token = Annotation<TokenKind>;
 impl Token {
@@ -52,39 +172,217 @@ impl Token {
     pub fn jump_backward(location: Location) -> Self {
         Self::new(TokenKind::JumpBackward, location)
     }
+    pub fn call(location: Location) -> Self {
+        Self::new(TokenKind::Call, location)
+    }
+    pub fn switch_tape(location: Location) -> Self {
+        Self::new(TokenKind::SwitchTape, location)
+    }
+    pub fn transfer(location: Location) -> Self {
+        Self::new(TokenKind::Transfer, location)
+    }
+}
+
+impl Token {
+    // {"kind": "+", "location": {"line": 1, "col": 1, "file": 0, "offset": 0, "end": 1}}
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("kind", self.value.to_string().into()),
+            ("location", self.location.to_json()),
+        ])
+    }
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        let kind = json
+            .get("kind")
+            .and_then(Json::as_str)
+            .and_then(|kind| {
+                let mut chars = kind.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => TokenKind::from_char(c),
+                    _ => None,
+                }
+            })
+            .ok_or_else(|| "a token needs a command as its `kind`".to_string())?;
+        let location = json
+            .get("location")
+            .ok_or_else(|| "a token needs a `location`".to_string())
+            .and_then(Location::from_json)?;
+        Ok(Self::new(kind, location))
+    }
 }
 
 pub type Program = Vec<Token>;
 
+// A hash of the commands alone, so programs differing only in comments,
+// whitespace or locations share it. It is FNV-1a and stays the same across
+// builds and platforms, unlike std's hashers, so it can key stored caches.
+pub trait Fingerprint {
+    fn fingerprint(&self) -> u64;
+}
+
+impl Fingerprint for [Token] {
+    fn fingerprint(&self) -> u64 {
+        self.iter().fold(0xcbf2_9ce4_8422_2325, |hash, token| {
+            (hash ^ u64::from(token.value.byte())).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+}
+
+// Programs as JSON arrays of tokens, for caching them or sending them to
+// other tools. This is the crate's own JSON, not serde.
+pub fn program_to_json(program: &Program) -> Json {
+    Json::Array(program.iter().map(Token::to_json).collect())
+}
+
+pub fn program_from_json(json: &Json) -> Result<Program, String> {
+    json.as_array()
+        .ok_or_else(|| "a program must be an array of tokens".to_string())?
+        .iter()
+        .map(Token::from_json)
+        .collect()
+}
+
+// Commands beyond the eight of brainfuck, each read only when asked for.
+// Otherwise their characters are comments, as in any brainfuck program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extensions {
+    // `%`
+    pub calls: bool,
+    // `~` and `^`, for a second tape
+    pub tapes: bool,
+    // `@`, `$`, `!` and `0` to `F` of Extended Brainfuck Type I
+    pub extended: bool,
+}
+
 pub struct Lexer;
 impl Lexer {
     pub fn lex(input: &str) -> Program {
-        let mut tokens = Vec::new();
+        Self::lex_file(input, 0)
+    }
+    // Lexes the files one after another as a single program.
+    pub fn lex_files<S: AsRef<str>>(inputs: &[S]) -> Program {
+        Self::lex_files_with(inputs, Extensions::default())
+    }
+    pub fn lex_files_with<S: AsRef<str>>(inputs: &[S], extensions: Extensions) -> Program {
+        inputs
+            .iter()
+            .enumerate()
+            .flat_map(|(file, input)| Self::lex_source(input.as_ref(), file, extensions))
+            .collect()
+    }
+    pub fn lex_file(input: &str, file: usize) -> Program {
+        Self::lex_source(input, file, Extensions::default())
+    }
+    // Also reads `%` as a call to the host; see SimpleInterpreter::set_host_call.
+    pub fn lex_with_calls(input: &str) -> Program {
+        Self::lex_with(
+            input,
+            Extensions {
+                calls: true,
+                ..Extensions::default()
+            },
+        )
+    }
+    pub fn lex_with(input: &str, extensions: Extensions) -> Program {
+        Self::lex_source(input, 0, extensions)
+    }
+    // Lexes a large source on `threads` std threads (not rayon), each taking
+    // a run of whole lines, into the same tokens as lex_with. Smaller sources are lexed on
+    // the calling thread, where it is faster.
+    pub fn lex_parallel(input: &str, extensions: Extensions, threads: usize) -> Program {
+        if input.len() < PARALLEL_LEXING_THRESHOLD {
+            return Self::lex_with(input, extensions);
+        }
+        Self::lex_chunks(input, extensions, threads)
+    }
+    fn lex_chunks(input: &str, extensions: Extensions, threads: usize) -> Program {
+        let target = input.len().div_ceil(threads.max(1));
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < input.len() {
+            let from = (start + target).min(input.len());
+            let end = match input.as_bytes()[from..].iter().position(|&c| c == b'\n') {
+                Some(newline) => from + newline + 1,
+                None => input.len(),
+            };
+            chunks.push((start, &input[start..end]));
+            start = end;
+        }
+        let lexed: Vec<Program> = thread::scope(|scope| {
+            let workers: Vec<_> = chunks
+                .iter()
+                .map(|&(_, chunk)| scope.spawn(move || Self::lex_source(chunk, 0, extensions)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        // each chunk was lexed as if it began the source
+        let mut program = Vec::with_capacity(lexed.iter().map(Vec::len).sum());
+        let mut lines = 0;
+        for ((start, chunk), tokens) in chunks.into_iter().zip(lexed) {
+            program.extend(tokens.into_iter().map(|mut token| {
+                token.location.line += lines;
+                token.location.offset += start;
+                token.location.end += start;
+                token
+            }));
+            lines += chunk.bytes().filter(|&c| c == b'\n').count();
+        }
+        program
+    }
+    fn lex_source(input: &str, file: usize, extensions: Extensions) -> Program {
+        Self::scan(input, file, |c| match c {
+            b'>' => Some(TokenKind::IncrementPointer),
+            b'<' => Some(TokenKind::DecrementPointer),
+            b'+' => Some(TokenKind::Increment),
+            b'-' => Some(TokenKind::Decrement),
+            b'.' => Some(TokenKind::Output),
+            b',' => Some(TokenKind::Input),
+            b'[' => Some(TokenKind::JumpForward),
+            b']' => Some(TokenKind::JumpBackward),
+            b'%' if extensions.calls => Some(TokenKind::Call),
+            b'~' if extensions.tapes => Some(TokenKind::SwitchTape),
+            b'^' if extensions.tapes => Some(TokenKind::Transfer),
+            b'@' if extensions.extended => Some(TokenKind::End),
+            b'$' if extensions.extended => Some(TokenKind::Store),
+            b'!' if extensions.extended => Some(TokenKind::Retrieve),
+            b'0'..=b'9' | b'A'..=b'F' if extensions.extended => TokenKind::from_char(c as char),
+            _ => None,
+        })
+    }
+    // Picks out the bytes `command` turns into commands, with their location.
+    // Everything else is a comment. Shared with languages other than
+    // brainfuck, such as the 2D one of the grid module. `command` is called
+    // twice per byte: a first pass counts the commands so that the tokens are
+    // allocated once.
+    pub fn scan<T>(
+        input: &str,
+        file: usize,
+        mut command: impl FnMut(u8) -> Option<T>,
+    ) -> Vec<Annotation<T>> {
+        let count = input.bytes().filter(|&c| command(c).is_some()).count();
+        let mut commands = Vec::with_capacity(count);
         let mut line = 1;
         let mut col = 1;
-
-        for c in input.as_bytes() {
-            if let Some(command) = match c {
-                b'>' => Some(Token::increment_pointer(Location { line, col })),
-                b'<' => Some(Token::decrement_pointer(Location { line, col })),
-                b'+' => Some(Token::increment(Location { line, col })),
-                b'-' => Some(Token::decrement(Location { line, col })),
-                b'.' => Some(Token::output(Location { line, col })),
-                b',' => Some(Token::input(Location { line, col })),
-                b'[' => Some(Token::jump_forward(Location { line, col })),
-                b']' => Some(Token::jump_backward(Location { line, col })),
-                b'\n' => {
-                    line += 1;
-                    col = 0;
-                    None
-                }
-                _ => None,
-            } {
-                tokens.push(command);
+        for (offset, &c) in input.as_bytes().iter().enumerate() {
+            if c == b'\n' {
+                line += 1;
+                col = 0;
+            } else if let Some(value) = command(c) {
+                let location = Location {
+                    line,
+                    col,
+                    file,
+                    offset,
+                    end: offset + 1,
+                };
+                commands.push(Annotation::new(value, location));
             }
             col += 1;
         }
-        tokens
+        commands
     }
 }
 
@@ -94,16 +392,234 @@ fn test_lexer() {
     assert_eq!(
         Lexer::lex(p),
         vec![
-            Token::increment(Location { line: 1, col: 1 }),
-            Token::jump_forward(Location { line: 1, col: 2 }),
-            Token::output(Location { line: 1, col: 3 }),
-            Token::increment(Location { line: 1, col: 4 }),
-            Token::jump_backward(Location { line: 1, col: 5 }),
-            Token::increment_pointer(Location { line: 2, col: 1 }),
-            Token::input(Location { line: 2, col: 2 }),
-            Token::decrement(Location { line: 2, col: 3 }),
-            Token::output(Location { line: 2, col: 4 }),
-            Token::decrement_pointer(Location { line: 2, col: 5 }),
+            Token::increment(Location {
+                line: 1,
+                col: 1,
+                file: 0,
+                offset: 0,
+                end: 1
+            }),
+            Token::jump_forward(Location {
+                line: 1,
+                col: 2,
+                file: 0,
+                offset: 1,
+                end: 2
+            }),
+            Token::output(Location {
+                line: 1,
+                col: 3,
+                file: 0,
+                offset: 2,
+                end: 3
+            }),
+            Token::increment(Location {
+                line: 1,
+                col: 4,
+                file: 0,
+                offset: 3,
+                end: 4
+            }),
+            Token::jump_backward(Location {
+                line: 1,
+                col: 5,
+                file: 0,
+                offset: 4,
+                end: 5
+            }),
+            Token::increment_pointer(Location {
+                line: 2,
+                col: 1,
+                file: 0,
+                offset: 6,
+                end: 7
+            }),
+            Token::input(Location {
+                line: 2,
+                col: 2,
+                file: 0,
+                offset: 7,
+                end: 8
+            }),
+            Token::decrement(Location {
+                line: 2,
+                col: 3,
+                file: 0,
+                offset: 8,
+                end: 9
+            }),
+            Token::output(Location {
+                line: 2,
+                col: 4,
+                file: 0,
+                offset: 9,
+                end: 10
+            }),
+            Token::decrement_pointer(Location {
+                line: 2,
+                col: 5,
+                file: 0,
+                offset: 10,
+                end: 11
+            }),
         ]
     );
 }
+
+#[test]
+fn test_lex_files() {
+    let program = Lexer::lex_files(&["+\n-", "."]);
+    assert_eq!(
+        program,
+        vec![
+            Token::increment(Location {
+                line: 1,
+                col: 1,
+                file: 0,
+                offset: 0,
+                end: 1
+            }),
+            Token::decrement(Location {
+                line: 2,
+                col: 1,
+                file: 0,
+                offset: 2,
+                end: 3
+            }),
+            Token::output(Location {
+                line: 1,
+                col: 1,
+                file: 1,
+                offset: 0,
+                end: 1
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_location_span() {
+    let source = "+[\n-] comment";
+    let program = Lexer::lex(source);
+    assert_eq!(program[2].location.text(source), Some("-"));
+    let whole = program[1].location.to(program[3].location);
+    assert_eq!((whole.line, whole.col), (1, 2));
+    assert_eq!(whole.text(source), Some("[\n-]"));
+    let ook = crate::dialect::Dialect::Ook.parse("Ook. Ook.").unwrap();
+    assert_eq!((ook[0].location.offset, ook[0].location.end), (0, 9));
+}
+
+#[test]
+fn test_lex_parallel() {
+    let source = "+[->+<]\n>.\n\n,[.,] comment\n".repeat(50);
+    let extensions = Extensions::default();
+    for threads in 1..8 {
+        assert_eq!(
+            Lexer::lex_chunks(&source, extensions, threads),
+            Lexer::lex(&source)
+        );
+    }
+    assert_eq!(Lexer::lex_chunks("", extensions, 4), vec![]);
+    assert_eq!(Lexer::lex_parallel("+", extensions, 4), Lexer::lex("+"));
+}
+
+#[test]
+fn test_token_kind_display() {
+    let p = "><+-.,[]";
+    let printed: String = Lexer::lex(p).iter().map(|t| t.value.to_string()).collect();
+    assert_eq!(printed, p);
+}
+
+#[test]
+fn test_lex_with_calls() {
+    assert_eq!(
+        Lexer::lex("+%"),
+        vec![Token::increment(Location {
+            line: 1,
+            col: 1,
+            file: 0,
+            offset: 0,
+            end: 1
+        })]
+    );
+    let program = Lexer::lex_with_calls("+%");
+    assert_eq!(
+        program[1],
+        Token::call(Location {
+            line: 1,
+            col: 2,
+            file: 0,
+            offset: 1,
+            end: 2
+        })
+    );
+    assert_eq!(program[1].value.to_string(), "%");
+
+    let tapes = Extensions {
+        tapes: true,
+        ..Extensions::default()
+    };
+    assert_eq!(Lexer::lex("+~^").len(), 1);
+    let program = Lexer::lex_with("+~^%", tapes);
+    let kinds: Vec<TokenKind> = program.iter().map(|t| t.value).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Increment,
+            TokenKind::SwitchTape,
+            TokenKind::Transfer
+        ]
+    );
+}
+
+#[test]
+fn test_program_json() {
+    let program = Lexer::lex_files(&["+[\n-]", ".%"]);
+    let json = program_to_json(&program);
+    assert_eq!(
+        json.as_array().unwrap()[2].to_string(),
+        r#"{"kind":"-","location":{"line":2,"col":1,"file":0,"offset":3,"end":4}}"#
+    );
+    let text = json.to_string();
+    assert_eq!(program_from_json(&Json::parse(&text).unwrap()), Ok(program));
+
+    let token = Json::parse(r#"[{"kind":"%","location":{"line":1,"col":2}}]"#).unwrap();
+    assert_eq!(
+        program_from_json(&token),
+        Ok(vec![Token::call(Location {
+            line: 1,
+            col: 2,
+            file: 0,
+            offset: 0,
+            end: 0
+        })])
+    );
+    let bad = Json::parse(r#"[{"kind":"x","location":{"line":1,"col":1}}]"#).unwrap();
+    assert!(program_from_json(&bad).is_err());
+    assert!(program_from_json(&Json::Null).is_err());
+}
+
+#[test]
+fn test_fingerprint() {
+    let program = Lexer::lex("+[->+<] add");
+    assert_eq!(
+        program.fingerprint(),
+        Lexer::lex("+ [-\n>+<]").fingerprint()
+    );
+    assert_ne!(program.fingerprint(), Lexer::lex("+[->+<].").fingerprint());
+    assert_eq!(Lexer::lex("").fingerprint(), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(Lexer::lex("+").fingerprint(), 0xaf63_a64c_8601_90ca);
+    let extended = |source: &str| {
+        let extensions = Extensions {
+            extended: true,
+            ..Extensions::default()
+        };
+        Lexer::lex_with(source, extensions).fingerprint()
+    };
+    assert_ne!(extended("A@"), extended("B@"));
+    // the hex digit itself
+    assert_eq!(
+        extended("A"),
+        (0xcbf2_9ce4_8422_2325 ^ u64::from(b'A')).wrapping_mul(0x0000_0100_0000_01b3)
+    );
+}
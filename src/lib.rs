@@ -1,2 +1,58 @@
+pub mod analysis;
+pub mod asynchronous;
+pub mod batch;
+pub mod bench;
+pub mod boolfuck;
+pub mod clock;
+pub mod compiled;
+pub mod config;
+pub mod corpus;
+pub mod dialect;
+pub mod difftest;
+pub mod digest;
+pub mod dump;
+pub mod engine;
+pub mod equivalence;
+pub mod escape;
+pub mod explain;
+pub mod ffi;
+pub mod formatter;
+pub mod fuzz;
+pub mod grid;
+pub mod html;
+pub mod input;
 pub mod interpreter;
+pub mod json;
 pub mod lexer;
+pub mod line_editor;
+pub mod live;
+pub mod lsp;
+pub mod metrics;
+pub mod mutation;
+pub mod optimized;
+pub mod optimizer;
+pub mod pipe;
+pub mod playback;
+pub mod playground;
+pub mod prelude;
+pub mod random;
+pub mod repl;
+pub mod resumable;
+pub mod rpc;
+mod run;
+pub mod scheduler;
+pub mod server;
+pub mod signal;
+pub mod snapshot;
+pub mod socket;
+pub mod spec;
+pub mod stats;
+pub mod synthesis;
+pub mod tape;
+pub mod tee;
+pub mod terminal;
+
+// Lexes and runs a program in one call, with the defaults of the interpreter.
+//
+//     let output = brainfuck::run(",.,.", b"hi")?;
+pub use run::{run, Error};
@@ -0,0 +1,187 @@
+use crate::terminal::RawMode;
+use std::io::{self, Read, Write};
+
+// Lines to remember for the up arrow.
+const HISTORY_SIZE: usize = 500;
+
+fn read_byte<R: Read>(keys: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match keys.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Reads lines from the keys typed on a terminal: the left and right arrows,
+// Home and End or Ctrl-A and Ctrl-E move in the line, Backspace and Delete
+// remove a character, Ctrl-U the start of the line, and the up and down
+// arrows go through the lines entered before. A small editor of the crate's
+// own, not rustyline.
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    history: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    // Like read_line, on the terminal, which is in raw mode while the line
+    // is edited.
+    pub fn read_terminal_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let _raw = RawMode::enable(false)?;
+        self.read_line(prompt, &mut io::stdin().lock(), &mut io::stdout().lock())
+    }
+
+    // The next line typed, without its end, drawing it on `screen` as it is
+    // edited. `keys` must not echo. None on Ctrl-D in an empty line or at
+    // the end of the keys.
+    pub fn read_line<R: Read, W: Write>(
+        &mut self,
+        prompt: &str,
+        keys: &mut R,
+        screen: &mut W,
+    ) -> io::Result<Option<String>> {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // the line being typed while going through the history
+        let mut draft = Vec::new();
+        let mut entry = self.history.len();
+        write!(screen, "{}", prompt)?;
+        screen.flush()?;
+        loop {
+            let byte = match read_byte(keys)? {
+                Some(byte) => byte,
+                None if line.is_empty() => return Ok(None),
+                None => b'\n',
+            };
+            match byte {
+                b'\r' | b'\n' => break,
+                // Ctrl-D
+                0x04 if line.is_empty() => {
+                    writeln!(screen)?;
+                    return Ok(None);
+                }
+                0x04 if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                // Backspace
+                0x7f | 0x08 if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                // Ctrl-A and Ctrl-E
+                0x01 => cursor = 0,
+                0x05 => cursor = line.len(),
+                // Ctrl-U
+                0x15 => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                0x1b => {
+                    let mut key = read_byte(keys)?;
+                    if key == Some(b'[') || key == Some(b'O') {
+                        key = read_byte(keys)?;
+                    }
+                    match key {
+                        Some(b'C') => cursor = (cursor + 1).min(line.len()),
+                        Some(b'D') => cursor = cursor.saturating_sub(1),
+                        Some(b'H') => cursor = 0,
+                        Some(b'F') => cursor = line.len(),
+                        // Delete is "\x1b[3~"
+                        Some(b'3') => {
+                            let tilde = read_byte(keys)? == Some(b'~');
+                            if tilde && cursor < line.len() {
+                                line.remove(cursor);
+                            }
+                        }
+                        Some(b'A') if entry > 0 => {
+                            if entry == self.history.len() {
+                                draft = line;
+                            }
+                            entry -= 1;
+                            line = self.history[entry].chars().collect();
+                            cursor = line.len();
+                        }
+                        Some(b'B') if entry < self.history.len() => {
+                            entry += 1;
+                            line = match self.history.get(entry) {
+                                Some(previous) => previous.chars().collect(),
+                                None => std::mem::take(&mut draft),
+                            };
+                            cursor = line.len();
+                        }
+                        _ => {}
+                    }
+                }
+                byte if byte < 0x20 => {}
+                byte => {
+                    // the rest of a UTF-8 character
+                    let mut bytes = vec![byte];
+                    let length = match byte {
+                        0xc0..=0xdf => 2,
+                        0xe0..=0xef => 3,
+                        0xf0..=0xf7 => 4,
+                        _ => 1,
+                    };
+                    while bytes.len() < length {
+                        match read_byte(keys)? {
+                            Some(next) => bytes.push(next),
+                            None => break,
+                        }
+                    }
+                    for c in String::from_utf8_lossy(&bytes).chars() {
+                        line.insert(cursor, c);
+                        cursor += 1;
+                    }
+                }
+            }
+            // redraw the whole line and put the cursor back
+            let text: String = line.iter().collect();
+            write!(screen, "\r{}{}\x1b[K", prompt, text)?;
+            if cursor < line.len() {
+                write!(screen, "\x1b[{}D", line.len() - cursor)?;
+            }
+            screen.flush()?;
+        }
+        writeln!(screen)?;
+        let line: String = line.into_iter().collect();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        Ok(Some(line))
+    }
+}
+
+#[test]
+fn test_line_editor() {
+    let mut editor = LineEditor::new();
+    let mut read = |keys: &[u8]| {
+        let mut screen = Vec::new();
+        editor.read_line("> ", &mut &keys[..], &mut screen).unwrap()
+    };
+    // left arrow, then insert; Backspace
+    assert_eq!(read(b"+-\x1b[D>\n"), Some("+>-".to_string()));
+    assert_eq!(read(b"..x\x7f\n"), Some("..".to_string()));
+    // Home, Delete, End
+    assert_eq!(read(b"ab\x01\x1b[3~\x05c\r"), Some("bc".to_string()));
+    // the previous line but one, then back to the draft
+    assert_eq!(read(b"\x1b[A\x1b[A\n"), Some("..".to_string()));
+    assert_eq!(read(b"[\x1b[A\x1b[B\n"), Some("[".to_string()));
+    assert_eq!(read("é\x15+\n".as_bytes()), Some("+".to_string()));
+    assert_eq!(read(b"\x04"), None);
+    assert_eq!(read(b""), None);
+    assert_eq!(editor.history(), ["+>-", "..", "bc", "..", "[", "+"]);
+}
@@ -0,0 +1,207 @@
+use crate::interpreter::{InterpreterError, SimpleInterpreter};
+use crate::lexer::{Program, TokenKind};
+use crate::snapshot::Snapshot;
+use crate::spec::Capture;
+
+// Steps between checkpoints at first. Once there are MAX_CHECKPOINTS, every
+// other one is dropped and the gap doubles, so that long runs keep few.
+const CHECKPOINT_INTERVAL: u64 = 10_000;
+const MAX_CHECKPOINTS: usize = 64;
+
+struct Checkpoint {
+    // the furthest command the run had got to, or jumped to; the checkpoint
+    // holds while the program is unchanged up to there
+    reach: usize,
+    cursor: usize,
+    tape: Snapshot,
+    steps: u64,
+    cycles: u64,
+    max_pointer: usize,
+    tape_growths: u64,
+    depth: usize,
+    max_depth: usize,
+    bytes_read: u64,
+    bytes_written: u64,
+    output: usize,
+}
+
+// Runs a program again each time it is edited, as in watch mode, going on
+// from the last state of the previous run that the edit cannot have changed
+// rather than from the start: a run that never got past the first changed
+// command is not repeated up to there. Each run reads the same input. The
+// second tape and the storage cell of the extensions are not restored, so
+// programs using them must not be run this way.
+pub struct LiveRun {
+    machine: SimpleInterpreter,
+    output: Capture,
+    input: Vec<u8>,
+    // the commands of the last run
+    commands: Vec<TokenKind>,
+    checkpoints: Vec<Checkpoint>,
+    // steps between checkpoints at first, and now
+    interval: u64,
+    gap: u64,
+    skipped: u64,
+}
+
+impl LiveRun {
+    pub fn new(input: Vec<u8>) -> Self {
+        let output = Capture::default();
+        let mut machine = SimpleInterpreter::new();
+        machine.set_output(Box::new(output.clone()));
+        Self {
+            machine,
+            output,
+            input,
+            commands: Vec::new(),
+            checkpoints: Vec::new(),
+            interval: CHECKPOINT_INTERVAL,
+            gap: CHECKPOINT_INTERVAL,
+            skipped: 0,
+        }
+    }
+
+    // For the step limit, the EOF behavior and such. The input and the
+    // output are the run's own.
+    pub fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        &mut self.machine
+    }
+
+    // Everything the last run wrote, including what was kept from earlier.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.0.lock().unwrap().clone()
+    }
+
+    // The steps of the last run taken over from an earlier one.
+    pub fn skipped_steps(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        let commands: Vec<TokenKind> = program.iter().map(|t| t.value).collect();
+        let unchanged = commands
+            .iter()
+            .zip(&self.commands)
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.commands = commands;
+        let kept = self
+            .checkpoints
+            .iter()
+            .take_while(|c| c.reach < unchanged)
+            .count();
+        self.checkpoints.truncate(kept);
+        if let Err(e) = self.machine.load(program) {
+            self.checkpoints.clear();
+            return Err(e);
+        }
+
+        let mut output = self.output.0.lock().unwrap();
+        let mut reach = 0;
+        match self.checkpoints.last() {
+            Some(checkpoint) => {
+                let m = &mut self.machine;
+                m.restore(&checkpoint.tape);
+                m.program_cursor = checkpoint.cursor;
+                m.steps = checkpoint.steps;
+                m.cycles = checkpoint.cycles;
+                m.max_pointer = checkpoint.max_pointer;
+                m.tape_growths = checkpoint.tape_growths;
+                m.depth = checkpoint.depth;
+                m.max_depth = checkpoint.max_depth;
+                m.bytes_read = checkpoint.bytes_read;
+                m.bytes_written = checkpoint.bytes_written;
+                m.set_input_bytes(self.input[checkpoint.bytes_read as usize..].to_vec());
+                output.truncate(checkpoint.output);
+                reach = checkpoint.reach;
+                self.skipped = checkpoint.steps;
+            }
+            None => {
+                self.machine.set_input_bytes(self.input.clone());
+                output.clear();
+                self.gap = self.interval;
+                self.skipped = 0;
+            }
+        }
+        drop(output);
+
+        let result = loop {
+            let cursor = self.machine.program_cursor;
+            match self.machine.step() {
+                Ok(true) => {}
+                Ok(false) => break Ok(0),
+                Err(e) => break Err(e),
+            }
+            // a jump forward depends on where the loop ends
+            reach = reach
+                .max(cursor)
+                .max(self.machine.program_cursor.saturating_sub(1));
+            if self.machine.steps.is_multiple_of(self.gap) {
+                self.checkpoint(reach);
+            }
+        };
+        // going on from the end runs commands added after it
+        if result.is_ok() {
+            self.checkpoint(reach);
+        }
+        let last = program.last().map(|t| t.location);
+        self.machine.finish(result, last)
+    }
+
+    fn checkpoint(&mut self, reach: usize) {
+        let m = &self.machine;
+        self.checkpoints.push(Checkpoint {
+            reach,
+            cursor: m.program_cursor,
+            tape: m.snapshot(),
+            steps: m.steps,
+            cycles: m.cycles,
+            max_pointer: m.max_pointer,
+            tape_growths: m.tape_growths,
+            depth: m.depth,
+            max_depth: m.max_depth,
+            bytes_read: m.bytes_read,
+            bytes_written: m.bytes_written,
+            output: self.output.0.lock().unwrap().len(),
+        });
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            let mut index = 0;
+            self.checkpoints.retain(|_| {
+                index += 1;
+                index % 2 == 0
+            });
+            self.gap *= 2;
+        }
+    }
+}
+
+#[test]
+fn test_live_run() {
+    use crate::lexer::Lexer;
+    let fresh = |source: &str, input: &[u8]| crate::playground::run(source, input).unwrap();
+    let mut live = LiveRun::new(b"ab".to_vec());
+    live.interval = 1;
+    let edits = [
+        ("++++[>++++<-]>.", 0),
+        // an edit after the loop does not run it again
+        ("++++[>++++<-]>+.", 41),
+        // nor does adding commands at the end
+        ("++++[>++++<-]>+.,.,.", 43),
+        // an edit in the loop goes back to before it first got there
+        ("++++[>+++<-]>+.,.,.", 9),
+        ("-++++[>+++<-]>+.,.,.", 0),
+    ];
+    for &(source, skipped) in edits.iter() {
+        assert_eq!(live.run(&Lexer::lex(source)), Ok(0));
+        assert_eq!(live.output(), fresh(source, b"ab"), "{}", source);
+        assert_eq!(live.skipped_steps(), skipped, "{}", source);
+    }
+    // the input goes on from where the last run had read to
+    live.machine_mut()
+        .set_eof_behavior(crate::interpreter::EofBehavior::Zero);
+    let source = "-++++[>+++<-]>+.,.,.>,.";
+    assert_eq!(live.run(&Lexer::lex(source)), Ok(0));
+    assert_eq!(live.output(), fresh(source, b"ab"));
+    assert!(live.skipped_steps() > 0);
+    assert!(live.run(&Lexer::lex("[")).is_err());
+}
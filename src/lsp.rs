@@ -0,0 +1,348 @@
+use crate::analysis;
+use crate::formatter;
+use crate::json::Json;
+use crate::lexer::{Lexer, Location, Program, TokenKind};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+pub struct LanguageServer {
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            exited: false,
+        }
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        while !self.exited {
+            let body = match read_message(&mut reader)? {
+                Some(body) => body,
+                None => break,
+            };
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            for outgoing in self.handle(&message) {
+                write_message(&mut writer, &outgoing)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the messages (responses and notifications) to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let id = message.get("id").cloned();
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let mut outgoing = vec![];
+        let result = match method {
+            "initialize" => Ok(Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        ("textDocumentSync", Json::from(1usize)),
+                        ("hoverProvider", Json::from(true)),
+                        ("documentFormattingProvider", Json::from(true)),
+                        ("definitionProvider", Json::from(true)),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![("name", Json::from("brainfuck"))]),
+                ),
+            ])),
+            "shutdown" => Ok(Json::Null),
+            "exit" => {
+                self.exited = true;
+                return outgoing;
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|d| d.get("uri")).and_then(Json::as_str);
+                let text = document.and_then(|d| d.get("text")).and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    outgoing.push(self.diagnostics(uri));
+                }
+                return outgoing;
+            }
+            "textDocument/didChange" => {
+                let uri = document_uri(&params);
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    outgoing.push(self.diagnostics(uri));
+                }
+                return outgoing;
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = document_uri(&params) {
+                    self.documents.remove(uri);
+                    outgoing.push(publish_diagnostics(uri, vec![]));
+                }
+                return outgoing;
+            }
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/formatting" => self.formatting(&params),
+            "textDocument/definition" => self.definition(&params),
+            _ => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+        };
+        // notifications never get a response
+        if let Some(id) = id {
+            let response = match result {
+                Ok(result) => Json::object(vec![
+                    ("jsonrpc", Json::from("2.0")),
+                    ("id", id),
+                    ("result", result),
+                ]),
+                Err((code, message)) => Json::object(vec![
+                    ("jsonrpc", Json::from("2.0")),
+                    ("id", id),
+                    (
+                        "error",
+                        Json::object(vec![
+                            ("code", Json::Number(f64::from(code))),
+                            ("message", Json::from(message)),
+                        ]),
+                    ),
+                ]),
+            };
+            outgoing.push(response);
+        }
+        outgoing
+    }
+
+    fn diagnostics(&self, uri: &str) -> Json {
+        let text = self.documents.get(uri).map(String::as_str).unwrap_or("");
+        let program = Lexer::lex(text);
+        let diagnostics = analysis::unmatched_brackets(&program)
+            .into_iter()
+            .map(|e| {
+                Json::object(vec![
+                    ("range", token_range(text, e.location)),
+                    ("severity", Json::from(1usize)),
+                    ("source", Json::from("brainfuck")),
+                    ("message", Json::from(e.value.to_string())),
+                ])
+            })
+            .collect();
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a String), (i32, String)> {
+        let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing uri".to_string()))?;
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or((INVALID_PARAMS, format!("unknown document: {}", uri)))?;
+        Ok((uri, text))
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, (i32, String)> {
+        let (_, text) = self.document(params)?;
+        let program = Lexer::lex(text);
+        let index = match token_at(&program, params) {
+            Some(index) => index,
+            None => return Ok(Json::Null),
+        };
+        let depths = analysis::nesting_depths(&program);
+        let mut range = program[index].location;
+        let mut lines = vec![format!("Loop nesting depth: {}", depths[index])];
+        if let Ok(jump_table) = analysis::match_brackets(&program) {
+            if let Some(&other) = jump_table.get(&index) {
+                let (start, end) = if index < other {
+                    (index, other)
+                } else {
+                    (other, index)
+                };
+                let kind = analysis::classify_loop(&program, start, end);
+                lines.push(format!("Loop: {}", kind.describe()));
+                // the whole loop
+                range = program[start].location.to(program[end].location);
+            }
+        }
+        Ok(Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![
+                    ("kind", Json::from("plaintext")),
+                    ("value", Json::from(lines.join("\n"))),
+                ]),
+            ),
+            ("range", token_range(text, range)),
+        ]))
+    }
+
+    fn formatting(&self, params: &Json) -> Result<Json, (i32, String)> {
+        let (_, text) = self.document(params)?;
+        let formatted = formatter::format(text);
+        if formatted == *text {
+            return Ok(Json::Array(vec![]));
+        }
+        let end_line = text.matches('\n').count();
+        let end_character = text.rsplit('\n').next().map(|l| l.len()).unwrap_or(0);
+        Ok(Json::Array(vec![Json::object(vec![
+            (
+                "range",
+                Json::object(vec![
+                    ("start", position(0, 0)),
+                    ("end", position(end_line, end_character)),
+                ]),
+            ),
+            ("newText", Json::from(formatted)),
+        ])]))
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, (i32, String)> {
+        let (uri, text) = self.document(params)?;
+        let program = Lexer::lex(text);
+        let index = match token_at(&program, params) {
+            Some(index) => index,
+            None => return Ok(Json::Null),
+        };
+        match program[index].value {
+            TokenKind::JumpForward | TokenKind::JumpBackward => {}
+            _ => return Ok(Json::Null),
+        }
+        let jump_table = match analysis::match_brackets(&program) {
+            Ok(jump_table) => jump_table,
+            Err(_) => return Ok(Json::Null),
+        };
+        Ok(match jump_table.get(&index) {
+            Some(&other) => Json::object(vec![
+                ("uri", Json::from(uri)),
+                ("range", token_range(text, program[other].location)),
+            ]),
+            None => Json::Null,
+        })
+    }
+}
+
+fn document_uri(params: &Json) -> Option<&str> {
+    params
+        .get("textDocument")
+        .and_then(|d| d.get("uri"))
+        .and_then(Json::as_str)
+}
+
+fn token_at(program: &Program, params: &Json) -> Option<usize> {
+    let position = params.get("position")?;
+    let line = position.get("line").and_then(Json::as_u64)? as usize + 1;
+    let col = position.get("character").and_then(Json::as_u64)? as usize + 1;
+    program
+        .iter()
+        .position(|t| t.location.line == line && t.location.col == col)
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("line", Json::from(line)),
+        ("character", Json::from(character)),
+    ])
+}
+
+// The span of the location, which may run over several lines of `text`.
+fn token_range(text: &str, location: Location) -> Json {
+    let covered = &text.as_bytes()[location.offset..location.end];
+    let lines = covered.iter().filter(|&&c| c == b'\n').count();
+    let character = match covered.iter().rposition(|&c| c == b'\n') {
+        Some(newline) => covered.len() - newline - 1,
+        None => location.col - 1 + covered.len(),
+    };
+    Json::object(vec![
+        ("start", position(location.line - 1, location.col - 1)),
+        ("end", position(location.line - 1 + lines, character)),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", Json::from(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+pub(crate) fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = content_length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[test]
+fn test_language_server() {
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.bf","text":"+[-]\n]"}}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.bf"},"contentChanges":[{"text":"+[-]"}]}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///a.bf"},"position":{"line":0,"character":1}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.bf"},"position":{"line":0,"character":3}}}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let mut input = String::new();
+    for m in messages.iter() {
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{}", m.len(), m));
+    }
+    let mut output = Vec::new();
+    LanguageServer::new()
+        .run(input.as_bytes(), &mut output)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(r#""hoverProvider":true"#));
+    assert!(output.contains(r#""message":"unmatched ']'""#));
+    assert!(output
+        .contains(r#""range":{"start":{"line":0,"character":3},"end":{"line":0,"character":4}}"#));
+    // hovering a bracket covers the loop
+    assert!(output
+        .contains(r#""range":{"start":{"line":0,"character":1},"end":{"line":0,"character":4}}"#));
+}
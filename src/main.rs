@@ -1,18 +1,96 @@
 extern crate getopts;
 
-use brainfuck::interpreter::SimpleInterpreter;
-use brainfuck::lexer::Lexer;
-use getopts::Options;
+use brainfuck::analysis;
+use brainfuck::bench;
+use brainfuck::boolfuck::{self, BoolfuckInterpreter};
+use brainfuck::config::{self, Config};
+use brainfuck::corpus;
+use brainfuck::dialect::{Dialect, SourceMap};
+use brainfuck::difftest;
+use brainfuck::digest::HashingOutput;
+use brainfuck::dump::{self, TapeFormat};
+use brainfuck::engine::EngineKind;
+use brainfuck::escape;
+use brainfuck::explain;
+use brainfuck::fuzz::{self, Finding, Generator};
+use brainfuck::grid::{self, GridInterpreter};
+use brainfuck::html;
+use brainfuck::input::{BytesInput, RandomInput, StdinInput, TimeoutFallback};
+use brainfuck::interpreter::{
+    BracketMode, Buffering, EofBehavior, InterpreterError, InterpreterErrorKind, OutputEncoding,
+    SimpleInterpreter, Stats,
+};
+use brainfuck::json::Json;
+use brainfuck::lexer::{Extensions, Lexer, Program, TokenKind};
+use brainfuck::live::LiveRun;
+use brainfuck::lsp::LanguageServer;
+use brainfuck::metrics::Metrics;
+use brainfuck::optimizer;
+use brainfuck::pipe;
+use brainfuck::playback::{Cast, Playback};
+use brainfuck::random::Rng;
+use brainfuck::repl::Repl;
+use brainfuck::rpc::DebugServer;
+use brainfuck::server;
+use brainfuck::signal;
+use brainfuck::socket;
+use brainfuck::spec::{self, TestCase, TestResult};
+use brainfuck::stats::ProgramStats;
+use brainfuck::tape::{Tape, TapeKind};
+use brainfuck::terminal::{self, RawMode};
+use getopts::{Matches, Options};
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
 use std::process;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!(
         "Brainfuck interpreter written in Rust.
-Usage: {} source.bf [options]
-    FILE: program read from script file",
+Usage: {0} source.bf... [options] [-- INPUT...]
+       {0} -e PROGRAM [options]
+       {0} repl
+       {0} pipe a.bf b.bf... [--engine ENGINE] [--eof EOF] [-i FILE]
+       {0} difftest source.bf [--engines A,B] [--eof EOF] [-i FILE]
+       {0} fuzz [--count N] [--steps N] [--length N] [--loops PERCENT] [--io PERCENT]
+                [--seed N] [--out DIR]
+       {0} test [PATH...] [--engine ENGINE]
+       {0} translate FILE [--from DIALECT] [--to DIALECT] [-o FILE]
+       {0} bench source.bf [-n RUNS] [--engine ENGINE] [-i FILE]
+       {0} stats FILE [--format text|json]
+       {0} serve [--host HOST] [-p PORT] [--steps N] [--max-output BYTES] [--max-depth N]
+       {0} rpc [--tcp ADDRESS]
+       {0} listen source.bf [-p PORT] [--steps N] [--idle SECS] [--max-connections N]
+       {0} corpus [--engine ENGINE]
+       {0} grid source.bf [--eof EOF] [-i FILE]
+       {0} boolfuck source.bool [-i FILE]
+    FILE: program read from script files, or from stdin if FILE is -.
+          Several files are run as one program.
+    INPUT: words given to ',' joined with spaces and followed by a newline
+    repl: run brainfuck interactively on a persistent tape
+    pipe: run the programs together, feeding the output of each one to the next
+    difftest: run a program on two engines and report the first difference
+    fuzz: run random programs on both engines and save the ones that misbehave
+    test: run the .bf files under PATH and compare their output with the
+          companion .in/.out files or their front matter
+    translate: rewrite a program in another dialect (bf, ook), or as Boolfuck (bool)
+    bench: run a program repeatedly without output and report timings
+    stats: count the commands and loops of a program without running it
+    serve: run programs posted to an HTTP API, for a playground
+    rpc: debug a program over JSON-RPC on stdin/stdout or TCP
+    listen: run the program for every TCP connection, with ',' and '.' on the socket
+    corpus: run the bundled classic programs and compare their output with the
+            expected one, on both engines unless --engine is given
+    grid: run 2D brainfuck, where '^' and 'v' move to the row above or below
+    boolfuck: run Boolfuck, brainfuck on a tape of bits",
         program
     );
     print!("{}", opts.usage(&brief));
@@ -22,44 +100,2529 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
 
-    let mut opts = Options::new();
-    opts.optopt("i", "input", "set input file name", "NAME");
-    opts.optopt("o", "output", "set output file name", "NAME");
-    opts.optflag("h", "help", "print this help menu");
+    if args.get(1).map(String::as_str) == Some("repl") {
+        let stdin = std::io::stdin();
+        let mut repl = Repl::new();
+        let result = if stdin.is_terminal() && std::io::stdout().is_terminal() {
+            repl.run_terminal()
+        } else {
+            repl.run(stdin.lock(), std::io::stdout())
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("pipe") {
+        run_pipe(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("difftest") {
+        run_difftest(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("fuzz") {
+        run_fuzz(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("test") {
+        run_tests(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("translate") {
+        run_translate(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("stats") {
+        run_stats(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("serve") {
+        run_serve(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("rpc") {
+        run_rpc(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("listen") {
+        run_listen(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("bench") {
+        run_bench(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("corpus") {
+        run_corpus(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("grid") {
+        run_grid(&program, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("boolfuck") {
+        run_boolfuck(&program, &args[2..]);
+        return;
+    }
 
+    let opts = run_opts();
+    let (args, input_args) = split_input_args(args);
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => panic!("{}", f),
     };
     if matches.opt_present("h") {
         print_usage(&program, opts);
         return;
     }
+    if matches.opt_present("lsp") {
+        let stdin = std::io::stdin();
+        if let Err(e) = LanguageServer::new().run(stdin.lock(), std::io::stdout()) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut options = run_options(&matches, input_args);
+
+    if !matches.opt_present("e") {
+        let scripts = matches.free.clone();
+        if scripts.is_empty() {
+            print_usage(&program, opts);
+            process::exit(64);
+        }
+
+        if matches.opt_present("watch") {
+            if scripts.iter().any(|s| s == "-") {
+                eprintln!("Error: --watch needs source files");
+                process::exit(64);
+            }
+            if matches.opt_present("live") {
+                let script = match scripts.as_slice() {
+                    [script] => script,
+                    _ => {
+                        eprintln!("Error: --live runs a single source file");
+                        process::exit(64);
+                    }
+                };
+                watch_live(script, &options);
+            }
+            watch(&scripts, &options);
+        }
+        if matches.opt_present("live") {
+            eprintln!("Error: --live needs --watch");
+            process::exit(64);
+        }
+    }
+
+    let sources = read_sources(&matches, &mut std::io::stdin(), &mut options);
+    exit_with(
+        run_script(&sources, &options),
+        matches.opt_present("exit-code-from-cell"),
+    );
+}
 
-    let _output = matches.opt_str("o");
-    let input = matches.opt_str("i");
-    let script = if !matches.free.is_empty() {
-        matches.free[0].clone()
+fn run_opts() -> Options {
+    let mut opts = Options::new();
+    opts.optopt("e", "eval", "run the given program text", "PROGRAM");
+    opts.optopt("i", "input", "set input file name", "NAME");
+    opts.optopt(
+        "",
+        "input-string",
+        "give the text to ',' with \\n, \\t, \\xHH... escapes",
+        "TEXT",
+    );
+    opts.optopt(
+        "",
+        "random-input",
+        "give ',' pseudo-random bytes from the seed, LENGTH of them if given",
+        "SEED[:LENGTH]",
+    );
+    opts.optopt(
+        "",
+        "input-timeout",
+        "make ',' wait at most MS milliseconds, then store the EOF value or fail",
+        "MS[:eof|error]",
+    );
+    opts.optflag(
+        "",
+        "then-stdin",
+        "once the input of -i, --input-string or --random-input has ended, read the console",
+    );
+    opts.optopt(
+        "",
+        "prompt",
+        "on a terminal, write TEXT to stderr whenever ',' waits for the user",
+        "TEXT",
+    );
+    opts.optopt("o", "output", "set output file name", "NAME");
+    opts.optmulti(
+        "",
+        "tee",
+        "also write the output to FILE, e.g. to keep a log of a run watched live",
+        "FILE",
+    );
+    opts.optflagopt(
+        "",
+        "explain",
+        "describe each command run on stderr, only the first N if given",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "explain-delay",
+        "with --explain, wait MS milliseconds after each command",
+        "MS",
+    );
+    opts.optflagopt(
+        "",
+        "play",
+        "animate the run on stderr, FPS frames a second (10 by default)",
+        "FPS",
+    );
+    opts.optopt(
+        "",
+        "play-steps",
+        "with --play or --cast, run N commands between two frames (1 by default)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "cast",
+        "record the frames of --play to FILE as an asciinema cast",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "artifacts",
+        "write the output, an I/O trace, the stats and the tape to DIR, with a manifest",
+        "DIR",
+    );
+    opts.optopt("", "html", "export the program as HTML", "FILE");
+    opts.optflag(
+        "",
+        "profile",
+        "with --html, run the program and overlay execution counts",
+    );
+    opts.optopt(
+        "",
+        "dump-tape-on-exit",
+        "print the final tape and pointer to stderr",
+        "hex|dec|char",
+    );
+    opts.optopt(
+        "",
+        "dump-range",
+        "range of cells printed by --dump-tape-on-exit",
+        "START:END",
+    );
+    opts.optflag(
+        "",
+        "dump-on-interrupt",
+        "on Ctrl-C, print the location, step count and tape around the pointer",
+    );
+    opts.optflag(
+        "",
+        "watch",
+        "re-run the program whenever the source file changes",
+    );
+    opts.optflag(
+        "",
+        "live",
+        "with --watch, go on from the last state an edit leaves unchanged instead of starting over",
+    );
+    opts.optopt(
+        "",
+        "record-input",
+        "save every byte read by ',' to a file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "replay-input",
+        "feed the bytes saved by --record-input to ','",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "eof",
+        "value stored by ',' once the input is exhausted",
+        "newline|zero|minus-one|unchanged|error",
+    );
+    opts.optopt(
+        "",
+        "buffer",
+        "when output is flushed: after every byte, at newlines, or when the buffer \
+         is full (default: line for stdout, full for -o)",
+        "none|line|full",
+    );
+    opts.optopt(
+        "",
+        "output-encoding",
+        "write each cell as a byte, or as a code point in UTF-8 (default: bytes)",
+        "bytes|utf8",
+    );
+    opts.optflag(
+        "",
+        "exit-code-from-cell",
+        "exit with the value of the current cell when the program halts",
+    );
+    opts.optopt(
+        "",
+        "engine",
+        "execution engine, auto picks the fastest one available",
+        "auto|simple|optimized",
+    );
+    opts.optflag("", "dump-tokens", "print the lexed tokens and exit");
+    opts.optflag(
+        "",
+        "dump-ir",
+        "print the instructions of the optimized engine and exit",
+    );
+    opts.optflag(
+        "",
+        "raw",
+        "read ',' one keypress at a time, without waiting for Enter",
+    );
+    opts.optflag("", "no-echo", "with --raw, do not show the typed keys");
+    opts.optflag(
+        "",
+        "filter",
+        "stream stdin to ',' byte by byte and buffer the output, for use in pipes",
+    );
+    opts.optflag(
+        "",
+        "tapes",
+        "read '~' as switching to a second tape and '^' as copying the cell to it",
+    );
+    opts.optflag(
+        "",
+        "extended",
+        "read '@', '$', '!' and '0' to 'F' as in Extended Brainfuck Type I",
+    );
+    opts.optopt(
+        "",
+        "tape-file",
+        "run on the tape stored in the file, memory-mapped, and keep it there for the next run",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "tape-size",
+        "the most cells the tape can have; moving right of the last one is a pointer error",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "tape-memory",
+        "where the cells live: on the heap, or in an anonymous memory map for huge tapes, whole or a page at a time",
+        "heap|mapped|paged",
+    );
+    opts.optopt(
+        "",
+        "source-map",
+        "report locations in the file a translated program came from",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "brackets",
+        "what unmatched brackets do: stop the program, or jump to the end and nothing",
+        "strict|permissive",
+    );
+    opts.optopt(
+        "",
+        "max-depth",
+        "refuse to run programs with loops nested deeper than this",
+        "N",
+    );
+    opts.optflag(
+        "W",
+        "warnings",
+        "report cells wrapping around and reads past the end of the input",
+    );
+    opts.optflag(
+        "",
+        "hash-output",
+        "print the SHA-256 of the output instead of the output",
+    );
+    opts.optflag(
+        "",
+        "detect-loops",
+        "stop with an error when a loop comes back to a state it has been in",
+    );
+    opts.optflag(
+        "",
+        "binary",
+        "read stdin byte by byte and write '.' as bytes, without translating line ends",
+    );
+    opts.optflag(
+        "v",
+        "verbose",
+        "print a summary of the run to stderr when it ends",
+    );
+    opts.optflag("", "lsp", "run as a language server over stdin/stdout");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+// Everything after `--` is the input of the program.
+fn split_input_args(args: Vec<String>) -> (Vec<String>, Option<Vec<String>>) {
+    match args.iter().position(|a| a == "--") {
+        Some(i) => (args[..i].to_vec(), Some(args[i + 1..].to_vec())),
+        None => (args, None),
+    }
+}
+
+// Checks the options of a run and merges them with bf.toml.
+fn run_options(matches: &Matches, input_args: Option<Vec<String>>) -> RunOptions {
+    let dump_format = matches.opt_str("dump-tape-on-exit").map(|f| {
+        f.parse::<TapeFormat>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        })
+    });
+    let dump_range = match matches.opt_str("dump-range") {
+        Some(r) => dump::parse_range(&r).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => (None, None),
+    };
+    let filter = matches.opt_present("filter");
+    let input_string = matches.opt_str("input-string").map(|s| {
+        escape::unescape(&s).unwrap_or_else(|e| {
+            eprintln!("Error: --input-string: {}", e);
+            process::exit(64);
+        })
+    });
+    let random_input = matches.opt_str("random-input").map(|s| {
+        let mut parts = s.splitn(2, ':').map(str::parse::<u64>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(seed)), None) => (seed, None),
+            (Some(Ok(seed)), Some(Ok(length))) => (seed, Some(length)),
+            _ => {
+                eprintln!("Error: --random-input must be SEED or SEED:LENGTH");
+                process::exit(64);
+            }
+        }
+    });
+    let input_timeout = matches.opt_str("input-timeout").map(|s| {
+        let mut parts = s.splitn(2, ':');
+        let millis = parts.next().and_then(|ms| ms.parse().ok());
+        let fallback = match parts.next() {
+            None | Some("eof") => Some(TimeoutFallback::Eof),
+            Some("error") => Some(TimeoutFallback::Error),
+            Some(_) => None,
+        };
+        match (millis, fallback) {
+            (Some(millis), Some(fallback)) => (Duration::from_millis(millis), fallback),
+            _ => {
+                eprintln!("Error: --input-timeout must be MS, MS:eof or MS:error");
+                process::exit(64);
+            }
+        }
+    });
+    if let Err(e) = check_filter(matches, input_args.is_some()) {
+        eprintln!("Error: {}", e);
+        process::exit(64);
+    }
+    if matches.opt_present("no-echo") && !matches.opt_present("raw") {
+        eprintln!("Error: --no-echo needs --raw");
+        process::exit(64);
+    }
+    let buffering = matches.opt_str("buffer").map(|b| {
+        b.parse::<Buffering>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        })
+    });
+    let output_encoding = matches.opt_str("output-encoding").map(|e| {
+        e.parse::<OutputEncoding>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        })
+    });
+    if matches.opt_present("hash-output") && matches.opt_present("o") {
+        eprintln!("Error: --hash-output cannot be combined with -o");
+        process::exit(64);
+    }
+    if matches.opt_present("binary") && output_encoding == Some(OutputEncoding::Utf8) {
+        eprintln!("Error: --binary writes bytes, not UTF-8");
+        process::exit(64);
+    }
+    let first_script = matches
+        .free
+        .first()
+        .filter(|s| *s != "-" && !matches.opt_present("e"))
+        .map(Path::new);
+    let config = match config::find(first_script) {
+        Some(path) => Config::load(&path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => Config::default(),
+    };
+    let bracket_mode = match matches.opt_str("brackets") {
+        Some(b) => b.parse::<BracketMode>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => config.bracket_mode.unwrap_or_default(),
+    };
+    let tape_kind = match matches.opt_str("tape-memory") {
+        Some(kind) => kind.parse::<TapeKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => config.tape_kind.unwrap_or_default(),
+    };
+    let tape_size = match matches.opt_str("tape-size") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(cells) if cells > 0 => Some(cells),
+            _ => {
+                eprintln!("Error: --tape-size must be a positive number");
+                process::exit(64);
+            }
+        },
+        None => config.tape_size,
+    };
+    // --binary writes bytes whatever bf.toml says
+    let output_encoding = output_encoding.or(if matches.opt_present("binary") {
+        None
     } else {
-        print_usage(&program, opts);
-        return process::exit(64);
+        config.output_encoding
+    });
+    let eof_behavior = match matches.opt_str("eof") {
+        Some(e) => e.parse::<EofBehavior>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        // most filters stop at a zero byte
+        None => config.eof_behavior.unwrap_or(if filter {
+            EofBehavior::Zero
+        } else {
+            EofBehavior::default()
+        }),
+    };
+    let nesting_limit = matches.opt_str("max-depth").map(|n| {
+        n.parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("Error: --max-depth must be a number");
+            process::exit(64);
+        })
+    });
+    let input_sources = [
+        matches.opt_present("i"),
+        input_args.is_some(),
+        input_string.is_some(),
+        random_input.is_some(),
+    ];
+    if matches.opt_present("then-stdin") && !input_sources.contains(&true) {
+        eprintln!("Error: --then-stdin follows -i, --input-string, --random-input or --");
+        process::exit(64);
+    }
+    if input_sources.iter().filter(|&&given| given).count() > 1 {
+        eprintln!(
+            "Error: give the input with only one of -i, --input-string, --random-input or --"
+        );
+        process::exit(64);
+    }
+    let mut engine = match matches.opt_str("engine") {
+        Some(e) => e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => config.engine.unwrap_or_default(),
+    };
+    let number = |option: &str| {
+        matches.opt_str(option).map(|n| {
+            n.parse::<u64>().unwrap_or_else(|_| {
+                eprintln!("Error: --{} must be a number", option);
+                process::exit(64);
+            })
+        })
+    };
+    let explain_steps = number("explain");
+    let explain_delay = number("explain-delay").map(Duration::from_millis);
+    if explain_delay.is_some() && !matches.opt_present("explain") {
+        eprintln!("Error: --explain-delay needs --explain");
+        process::exit(64);
+    }
+    let play_steps = number("play-steps");
+    let playing = matches.opt_present("play") || matches.opt_present("cast");
+    if play_steps.is_some() && !playing {
+        eprintln!("Error: --play-steps needs --play or --cast");
+        process::exit(64);
+    }
+    if playing && matches.opt_present("explain") {
+        eprintln!("Error: --play and --cast cannot be used with --explain");
+        process::exit(64);
+    }
+    let play = if playing {
+        let defaults = Playback::default();
+        let fps = number("play").map_or(defaults.fps, |fps| fps.clamp(1, 1000) as u32);
+        Some(Playback {
+            fps,
+            steps_per_frame: play_steps.unwrap_or(defaults.steps_per_frame),
+            ..defaults
+        })
+    } else {
+        None
+    };
+    if matches.opt_present("explain") || play.is_some() {
+        // a command at a time, which only the simple engine runs
+        match engine {
+            EngineKind::Auto | EngineKind::Simple => engine = EngineKind::Simple,
+            _ => {
+                eprintln!("Error: --explain, --play and --cast need the simple engine");
+                process::exit(64);
+            }
+        }
+    }
+    if matches.opt_present("html") && matches.opt_present("profile") {
+        // execution counts are per source instruction, which only the simple engine keeps
+        match engine {
+            EngineKind::Auto | EngineKind::Simple => engine = EngineKind::Simple,
+            _ => {
+                eprintln!("Error: --profile needs the simple engine");
+                process::exit(64);
+            }
+        }
+    }
+    RunOptions {
+        input: matches.opt_str("i"),
+        input_args: input_args
+            .map(|words| format!("{}\n", words.join(" ")).into_bytes())
+            .or(input_string),
+        random_input,
+        input_timeout,
+        output: matches.opt_str("o"),
+        tee: matches.opt_strs("tee"),
+        artifacts: matches.opt_str("artifacts"),
+        explain: matches.opt_present("explain"),
+        explain_steps,
+        explain_delay,
+        play,
+        play_live: matches.opt_present("play"),
+        cast: matches.opt_str("cast"),
+        engine,
+        eof_behavior,
+        output_encoding: output_encoding.unwrap_or_default(),
+        buffering: buffering.or(config.buffering).or(if filter {
+            Some(Buffering::Full)
+        } else {
+            None
+        }),
+        record_input: matches.opt_str("record-input"),
+        replay_input: matches.opt_str("replay-input"),
+        html_path: matches.opt_str("html"),
+        profile: matches.opt_present("profile"),
+        dump_tokens: matches.opt_present("dump-tokens"),
+        dump_ir: matches.opt_present("dump-ir"),
+        dump_format,
+        dump_range,
+        dump_on_interrupt: matches.opt_present("dump-on-interrupt"),
+        verbose: matches.opt_present("v"),
+        raw_input: matches.opt_present("raw"),
+        filter,
+        binary: matches.opt_present("binary"),
+        tapes: matches.opt_present("tapes"),
+        extended: matches.opt_present("extended"),
+        detect_loops: matches.opt_present("detect-loops"),
+        hash_output: matches.opt_present("hash-output"),
+        warnings: matches.opt_present("W") || config.warnings == Some(true),
+        bracket_mode,
+        nesting_limit,
+        tape_file: matches.opt_str("tape-file"),
+        tape_kind,
+        tape_size,
+        source_map: matches.opt_str("source-map"),
+        then_stdin: matches.opt_present("then-stdin"),
+        prompt: matches.opt_str("prompt"),
+        echo: !matches.opt_present("no-echo"),
+        source_from_stdin: false,
+    }
+}
+
+// --filter hands all of stdin to `,`, so the input cannot come from
+// elsewhere, nor the program from stdin.
+fn check_filter(matches: &Matches, input_args: bool) -> Result<(), String> {
+    if !matches.opt_present("filter") {
+        return Ok(());
+    }
+    let other_input = ["i", "input-string", "random-input"]
+        .iter()
+        .any(|option| matches.opt_present(option));
+    if other_input || input_args {
+        return Err("--filter reads the input from stdin".to_string());
+    }
+    if !matches.opt_present("e") && matches.free.iter().any(|script| script == "-") {
+        return Err("--filter cannot read the program from stdin".to_string());
+    }
+    Ok(())
+}
+
+// The sources to run: the text of -e, or the scripts, with "-" read from
+// `stdin`.
+fn read_sources(matches: &Matches, stdin: &mut dyn Read, options: &mut RunOptions) -> Vec<Source> {
+    if let Some(text) = matches.opt_str("e") {
+        return vec![Source {
+            name: "-e".to_string(),
+            text,
+        }];
+    }
+    let mut sources = Vec::with_capacity(matches.free.len());
+    for script in &matches.free {
+        let mut p = String::new();
+        if script == "-" {
+            stdin
+                .read_to_string(&mut p)
+                .expect("something went wrong reading stdin");
+            // stdin is taken by the source, so ',' only gets -i or the EOF value
+            options.source_from_stdin = true;
+        } else {
+            let mut f = File::open(script).expect("file not found");
+            f.read_to_string(&mut p)
+                .expect("something went wrong reading the file");
+        }
+        sources.push(Source {
+            name: script.clone(),
+            text: p,
+        });
+    }
+    sources
+}
+
+fn run_grid(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "eof",
+        "value stored by ',' at the end of the input (default: newline)",
+        "newline|zero|minus-one|unchanged|error",
+    );
+    opts.optopt("i", "input", "give the bytes of the file to ','", "FILE");
+    let usage = format!("Usage: {} grid source.bf [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let mut grid = GridInterpreter::new();
+    let machine = grid.machine_mut();
+    if let Some(eof) = matches.opt_str("eof") {
+        machine.set_eof_behavior(eof.parse().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }));
+    }
+    if let Some(path) = matches.opt_str("i") {
+        machine.set_input_bytes(fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        }));
+    }
+    machine.set_interrupt_flag(signal::install_interrupt_handler());
+    if let Err(e) = grid.eval(&grid::lex(&source)) {
+        let _ = std::io::stdout().flush();
+        let message = grid.machine().error_message(e.value);
+        println!("Error: {} at {}:{}", message, script, e.location);
+        process::exit(1);
+    }
+}
+
+fn run_boolfuck(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("i", "input", "give the bytes of the file to ','", "FILE");
+    let usage = format!("Usage: {} boolfuck source.bool [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let mut interpreter = BoolfuckInterpreter::new();
+    if let Some(path) = matches.opt_str("i") {
+        let bytes = fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        });
+        interpreter.set_input_source(Box::new(BytesInput::new(bytes)));
+    }
+    if let Err(e) = interpreter.eval(&boolfuck::lex(&source)) {
+        let _ = std::io::stdout().flush();
+        println!("Error: {} at {}:{}", e.value, script, e.location);
+        process::exit(1);
+    }
+}
+
+fn run_bench(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("n", "runs", "number of runs (default 10)", "RUNS");
+    opts.optopt("", "engine", "execution engine", "auto|simple|optimized");
+    opts.optopt("i", "input", "give the bytes of the file to ','", "FILE");
+    let usage = format!("Usage: {} bench source.bf [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let runs = match matches.opt_str("n").map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n,
+        Some(_) => {
+            eprintln!("Error: RUNS must be a positive number");
+            process::exit(64);
+        }
+        None => 10,
+    };
+    let engine = match matches.opt_str("engine") {
+        Some(e) => e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EngineKind::Auto,
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let input = match matches.opt_str("i") {
+        Some(path) => fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        }),
+        None => Vec::new(),
+    };
+
+    match bench::bench(&Lexer::lex(&source), engine, &input, runs) {
+        Ok(result) => {
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            println!("{} ({} engine, {} runs)", script, result.engine, runs);
+            println!(
+                "min {:.3} ms, median {:.3} ms, max {:.3} ms",
+                ms(result.min()),
+                ms(result.median()),
+                ms(result.max())
+            );
+            println!(
+                "{} instructions, {:.0} instructions/s",
+                result.steps,
+                result.steps_per_second()
+            );
+        }
+        Err(e) => {
+            println!("Error: {} at {}:{}", e.value, script, e.location);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_translate(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "from",
+        "dialect of FILE (default: from the extension, else bf)",
+        "bf|ook",
+    );
+    opts.optopt(
+        "",
+        "to",
+        "dialect to write (default: from the extension of -o)",
+        "bf|ook|bool",
+    );
+    opts.optopt("o", "output", "write to the file instead of stdout", "FILE");
+    opts.optopt(
+        "",
+        "source-map",
+        "write where each command came from, for run --source-map",
+        "FILE",
+    );
+    let usage = format!("Usage: {} translate FILE [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let dialect = |option: &str, path: Option<&String>| -> Option<Dialect> {
+        match matches.opt_str(option) {
+            Some(d) => Some(d.parse::<Dialect>().unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(64);
+            })),
+            None => path.and_then(|p| Dialect::from_path(Path::new(p))),
+        }
+    };
+    let from = dialect("from", Some(script)).unwrap_or(Dialect::Brainfuck);
+    let output = matches.opt_str("o");
+    let to = dialect("to", output.as_ref()).unwrap_or_else(|| {
+        eprintln!("Error: give the dialect to write with --to");
+        process::exit(64);
+    });
+
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let translated = match matches.opt_str("source-map") {
+        Some(map_path) => {
+            if to == Dialect::Boolfuck {
+                eprintln!("Error: a Boolfuck translation has no source map");
+                process::exit(64);
+            }
+            let (translated, mut map) = from.translate(&source, to).unwrap_or_else(|e| {
+                println!("Error: {} in {}", e, script);
+                process::exit(1);
+            });
+            map.source = script.clone();
+            if let Err(e) = fs::write(&map_path, map.to_json().to_string()) {
+                eprintln!("Error: could not write {}: {}", map_path, e);
+                process::exit(73);
+            }
+            translated
+        }
+        None => {
+            let parsed = from.parse(&source).unwrap_or_else(|e| {
+                println!("Error: {} in {}", e, script);
+                process::exit(1);
+            });
+            to.render(&parsed)
+        }
+    };
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, translated) {
+                eprintln!("Error: could not write {}: {}", path, e);
+                process::exit(73);
+            }
+        }
+        None => {
+            print!("{}", translated);
+            if to == Dialect::Brainfuck && !translated.is_empty() {
+                println!();
+            }
+        }
+    }
+}
+
+fn run_listen(program: &str, args: &[String]) {
+    let defaults = socket::Limits::default();
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "host",
+        "address to listen on (default 127.0.0.1)",
+        "HOST",
+    );
+    opts.optopt("p", "port", "port to listen on (default 4000)", "PORT");
+    opts.optopt("", "engine", "execution engine", "auto|simple|optimized");
+    opts.optopt(
+        "",
+        "steps",
+        "step limit per connection, 0 for none (default 100000000)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "idle",
+        &format!(
+            "seconds of client silence before ',' gets EOF (default {})",
+            defaults.idle.as_secs()
+        ),
+        "SECS",
+    );
+    opts.optopt(
+        "",
+        "max-connections",
+        &format!(
+            "connections served at once (default {})",
+            defaults.connections
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "metrics-port",
+        "also serve Prometheus metrics at /metrics on this port",
+        "PORT",
+    );
+    let usage = format!("Usage: {} listen source.bf [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let number = |option: &str, default: u64| match matches.opt_str(option) {
+        Some(n) => n.parse::<u64>().unwrap_or_else(|_| {
+            eprintln!("Error: --{} must be a number", option);
+            process::exit(64);
+        }),
+        None => default,
+    };
+    let steps = number("steps", defaults.steps.unwrap_or(0));
+    let limits = socket::Limits {
+        steps: Some(steps).filter(|&s| s > 0),
+        idle: Duration::from_secs(number("idle", defaults.idle.as_secs())),
+        connections: number("max-connections", defaults.connections as u64) as usize,
+    };
+    let engine = match matches.opt_str("engine") {
+        Some(e) => e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EngineKind::Auto,
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let parsed = Lexer::lex(&source);
+    if let Err(e) = analysis::match_brackets(&parsed) {
+        println!("Error: {} at {}:{}", e.value, script, e.location);
+        process::exit(1);
+    }
+    let address = format!(
+        "{}:{}",
+        matches.opt_str("host").as_deref().unwrap_or("127.0.0.1"),
+        number("port", 4000)
+    );
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("Error: could not listen on {}: {}", address, e);
+        process::exit(1);
+    });
+    let metrics = Arc::new(Metrics::default());
+    if let Some(port) = matches.opt_str("metrics-port") {
+        let address = format!(
+            "{}:{}",
+            matches.opt_str("host").as_deref().unwrap_or("127.0.0.1"),
+            port
+        );
+        let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+            eprintln!("Error: could not listen on {}: {}", address, e);
+            process::exit(1);
+        });
+        eprintln!("Metrics on http://{}/metrics", address);
+        let metrics = metrics.clone();
+        thread::spawn(move || server::serve_metrics(listener, metrics));
+    }
+    eprintln!("Serving {} on {}", script, address);
+    socket::serve(listener, parsed, engine, limits, metrics);
+}
+
+fn run_rpc(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "tcp",
+        "listen on the address instead of using stdin/stdout",
+        "ADDRESS",
+    );
+    let usage = format!("Usage: {} rpc [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) if m.free.is_empty() => m,
+        Ok(_) => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let address = match matches.opt_str("tcp") {
+        Some(address) => address,
+        None => {
+            let stdin = std::io::stdin();
+            if let Err(e) = DebugServer::new().run(stdin.lock(), std::io::stdout()) {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+    };
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("Error: could not listen on {}: {}", address, e);
+        process::exit(1);
+    });
+    eprintln!("Listening on {}", address);
+    // each connection debugs its own program
+    for stream in listener.incoming().flatten() {
+        thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                let _ = DebugServer::new().run(std::io::BufReader::new(reader), stream);
+            }
+        });
+    }
+}
+
+fn run_serve(program: &str, args: &[String]) {
+    let defaults = server::Limits::default();
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "host",
+        "address to listen on (default 127.0.0.1)",
+        "HOST",
+    );
+    opts.optopt("p", "port", "port to listen on (default 8080)", "PORT");
+    opts.optopt(
+        "",
+        "steps",
+        &format!("step limit per run (default {})", defaults.steps),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-output",
+        &format!("output limit per run (default {})", defaults.output),
+        "BYTES",
+    );
+    opts.optopt(
+        "",
+        "max-depth",
+        &format!("loop nesting limit (default {})", defaults.depth),
+        "N",
+    );
+    let usage = format!("Usage: {} serve [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) if m.free.is_empty() => m,
+        Ok(_) => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let number = |option: &str, default: u64| match matches.opt_str(option) {
+        Some(n) => n.parse::<u64>().unwrap_or_else(|_| {
+            eprintln!("Error: --{} must be a number", option);
+            process::exit(64);
+        }),
+        None => default,
+    };
+    let limits = server::Limits {
+        steps: number("steps", defaults.steps),
+        output: number("max-output", defaults.output as u64) as usize,
+        depth: number("max-depth", defaults.depth as u64) as usize,
+        ..defaults
+    };
+    let port = number("port", 8080);
+    let host = matches
+        .opt_str("host")
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    let address = format!("{}:{}", host, port);
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("Error: could not listen on {}: {}", address, e);
+        process::exit(1);
+    });
+    eprintln!("Listening on http://{}/run", address);
+    server::serve(listener, limits);
+}
+
+fn run_stats(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "format", "report format (default: text)", "text|json");
+    let usage = format!("Usage: {} stats FILE [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let json = match matches.opt_str("format").as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => {
+            eprintln!("Error: unknown format: {}", format);
+            process::exit(64);
+        }
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let dialect = Dialect::from_path(Path::new(script)).unwrap_or(Dialect::Brainfuck);
+    let parsed = dialect.parse(&source).unwrap_or_else(|e| {
+        println!("Error: {} in {}", e, script);
+        process::exit(1);
+    });
+    let stats = ProgramStats::new(&source, &parsed);
+    if json {
+        println!("{}", stats.to_json());
+    } else {
+        println!("{}", stats);
+    }
+}
+
+fn run_tests(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "engine", "execution engine", "auto|simple|optimized");
+    let usage = format!("Usage: {} test [PATH...] [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
     };
+    let engine = match matches.opt_str("engine") {
+        Some(e) => e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EngineKind::Auto,
+    };
+    let roots = if matches.free.is_empty() {
+        vec![".".to_string()]
+    } else {
+        matches.free.clone()
+    };
+    let mut paths = Vec::new();
+    for root in roots.iter() {
+        match spec::discover(Path::new(root)) {
+            Ok(found) => paths.extend(found),
+            Err(e) => {
+                eprintln!("Error: could not read {}: {}", root, e);
+                process::exit(66);
+            }
+        }
+    }
+
+    let (mut passed, mut failed) = (0, 0);
+    for path in paths.iter() {
+        let result = match TestCase::load(path) {
+            Ok(Some(case)) => Ok(case.run(engine)),
+            Ok(None) => continue,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(TestResult::Passed) => {
+                passed += 1;
+                println!("test {} ... ok", path.display());
+            }
+            Ok(TestResult::Failed { message, .. }) | Err(message) => {
+                failed += 1;
+                println!("test {} ... FAILED\n  {}", path.display(), message);
+            }
+        }
+    }
+    println!("\ntest result: {} passed; {} failed", passed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
 
-    let mut p = String::new();
-    let mut f = File::open(script).expect("file not found");
-    f.read_to_string(&mut p)
-        .expect("something went wrong reading the file");
+fn run_corpus(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "engine", "execution engine", "auto|simple|optimized");
+    let usage = format!("Usage: {} corpus [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) if m.free.is_empty() => m,
+        Ok(_) => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let engines = match matches.opt_str("engine") {
+        Some(e) => vec![e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        })],
+        None => vec![EngineKind::Simple, EngineKind::Optimized],
+    };
 
-    let program = Lexer::lex(&p);
-    let mut interpreter = SimpleInterpreter::new();
-    if let Some(input_path) = input {
-        let mut input_file = File::open(input_path).expect("File was not opened");
-        let mut buf = String::new();
-        let _ = input_file.read_to_string(&mut buf);
-        interpreter.set_input_stream(buf);
+    let (mut passed, mut failed) = (0, 0);
+    for engine in engines {
+        for (name, result) in corpus::check(engine) {
+            match result {
+                TestResult::Passed => {
+                    passed += 1;
+                    println!("corpus {} ({}) ... ok", name, engine);
+                }
+                TestResult::Failed { message, .. } => {
+                    failed += 1;
+                    println!("corpus {} ({}) ... FAILED\n  {}", name, engine, message);
+                }
+            }
+        }
+    }
+    println!("\ncorpus result: {} passed; {} failed", passed, failed);
+    if failed > 0 {
+        process::exit(1);
     }
+}
+
+fn run_fuzz(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "count", "number of programs (default 1000)", "N");
+    opts.optopt("", "steps", "step limit of each run (default 10000)", "N");
+    opts.optopt("", "length", "maximum program length (default 100)", "N");
+    opts.optopt(
+        "",
+        "loops",
+        "chance in percent of opening a loop at each command (default 10)",
+        "PERCENT",
+    );
+    opts.optopt(
+        "",
+        "io",
+        "chance in percent of a command being '.' or ',' (default 33)",
+        "PERCENT",
+    );
+    opts.optopt(
+        "",
+        "seed",
+        "seed of the generator (default: the clock)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "out",
+        "directory for the reproducers (default fuzz-findings)",
+        "DIR",
+    );
+    let usage = format!("Usage: {} fuzz [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) if m.free.is_empty() => m,
+        Ok(_) => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let number = |name: &str, default: u64| -> u64 {
+        match matches.opt_str(name).map(|n| n.parse::<u64>()) {
+            Some(Ok(n)) => n,
+            Some(Err(_)) => {
+                eprintln!("Error: --{} must be a number", name);
+                process::exit(64);
+            }
+            None => default,
+        }
+    };
+    let count = number("count", 1000);
+    let steps = number("steps", 10000);
+    let generator = Generator {
+        max_length: number("length", 100) as usize,
+        loops: number("loops", 10),
+        io: number("io", 33),
+        ..Generator::default()
+    };
+    let seed = matches
+        .opt_str("seed")
+        .map(|_| number("seed", 0))
+        .unwrap_or_else(|| Rng::from_time().next_u64());
+    let out = matches
+        .opt_str("out")
+        .unwrap_or_else(|| "fuzz-findings".to_string());
 
-    if let Err(e) = interpreter.eval(&program) {
-        println!("Error: {:?}", e);
+    println!("seed: {}", seed);
+    let mut rng = Rng::new(seed);
+    // panics are reported with the reproducers instead
+    std::panic::set_hook(Box::new(|_| {}));
+    let (mut passed, mut limited, mut panics, mut divergences) = (0, 0, 0, 0);
+    for i in 0..count {
+        let source = generator.generate(&mut rng);
+        let input: Vec<u8> = (0..16).map(|_| rng.byte()).collect();
+        let (kind, message) = match fuzz::check(&source, &input, steps) {
+            Finding::Passed => {
+                passed += 1;
+                continue;
+            }
+            Finding::StepLimitExceeded => {
+                limited += 1;
+                continue;
+            }
+            Finding::Panic(message) => {
+                panics += 1;
+                ("panic", message)
+            }
+            Finding::Divergence(message) => {
+                divergences += 1;
+                ("divergence", message)
+            }
+        };
+        let path = format!("{}/{}-{}-{}", out, kind, seed, i);
+        println!("{}: {}.bf\n{}", kind, path, message);
+        let saved = fs::create_dir_all(&out)
+            .and_then(|_| fs::write(format!("{}.bf", path), &source))
+            .and_then(|_| fs::write(format!("{}.input", path), &input))
+            .and_then(|_| fs::write(format!("{}.txt", path), format!("{}\n", message)));
+        if let Err(e) = saved {
+            eprintln!("Error: could not save {}: {}", path, e);
+            process::exit(73);
+        }
+    }
+    let _ = std::panic::take_hook();
+    println!(
+        "{} programs: {} passed, {} hit the step limit, {} panics, {} divergences",
+        count, passed, limited, panics, divergences
+    );
+    if panics + divergences > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_difftest(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "engines",
+        "the two engines to compare (default simple,optimized)",
+        "A,B",
+    );
+    opts.optopt(
+        "",
+        "eof",
+        "value stored by ',' once the input is exhausted",
+        "newline|zero|minus-one|unchanged|error",
+    );
+    opts.optopt("i", "input", "give the bytes of the file to ','", "FILE");
+    let usage = format!("Usage: {} difftest source.bf [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let script = match matches.free.as_slice() {
+        [script] => script,
+        _ => {
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    let engines = matches
+        .opt_str("engines")
+        .unwrap_or_else(|| "simple,optimized".to_string());
+    let engines: Vec<EngineKind> = engines
+        .split(',')
+        .map(|e| {
+            e.trim().parse::<EngineKind>().unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(64);
+            })
+        })
+        .collect();
+    if engines.len() != 2 {
+        eprintln!("Error: --engines takes two engines");
+        process::exit(64);
+    }
+    let eof_behavior = match matches.opt_str("eof") {
+        Some(e) => e.parse::<EofBehavior>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EofBehavior::default(),
+    };
+    let source = fs::read_to_string(script).unwrap_or_else(|e| {
+        eprintln!("Error: could not read {}: {}", script, e);
+        process::exit(66);
+    });
+    let input = match matches.opt_str("i") {
+        Some(path) => fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        }),
+        None => Vec::new(),
+    };
+
+    let (left, right, divergence) = difftest::difftest(
+        &Lexer::lex(&source),
+        (engines[0], engines[1]),
+        &input,
+        eof_behavior,
+    );
+    let (left_name, right_name) = (left.engine.to_string(), right.engine.to_string());
+    match divergence {
+        None => println!(
+            "{}: {} and {} agree ({} I/O events, {} and {} steps)",
+            script,
+            left_name,
+            right_name,
+            left.events.len(),
+            left.steps,
+            right.steps
+        ),
+        Some(ref divergence) => {
+            println!("{}: {} and {} diverge", script, left_name, right_name);
+            println!(
+                "{}",
+                difftest::Report {
+                    divergence,
+                    names: (&left_name, &right_name),
+                }
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn run_pipe(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "engine", "execution engine", "auto|simple|optimized");
+    opts.optopt(
+        "",
+        "eof",
+        "value stored by ',' once the input is exhausted",
+        "newline|zero|minus-one|unchanged|error",
+    );
+    opts.optopt("i", "input", "feed the file to the first program", "FILE");
+    let usage = format!("Usage: {} pipe a.bf b.bf... [options]", program);
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprint!("{}", opts.usage(&usage));
+            process::exit(64);
+        }
+    };
+    if matches.free.is_empty() {
+        eprint!("{}", opts.usage(&usage));
+        process::exit(64);
+    }
+    let engine = match matches.opt_str("engine") {
+        Some(e) => e.parse::<EngineKind>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EngineKind::Auto,
+    };
+    let eof_behavior = match matches.opt_str("eof") {
+        Some(e) => e.parse::<EofBehavior>().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(64);
+        }),
+        None => EofBehavior::default(),
+    };
+    let input: Box<dyn Read + Send> = match matches.opt_str("i") {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Error: could not read {}: {}", path, e);
+                process::exit(66);
+            }
+        },
+        None => terminal::stdin_input(),
+    };
+    let programs: Vec<Program> = matches
+        .free
+        .iter()
+        .map(|script| match fs::read_to_string(script) {
+            Ok(source) => Lexer::lex(&source),
+            Err(e) => {
+                eprintln!("Error: could not read {}: {}", script, e);
+                process::exit(66);
+            }
+        })
+        .collect();
+
+    let results = pipe::pipe(
+        &programs,
+        engine,
+        eof_behavior,
+        input,
+        Box::new(std::io::stdout()),
+        signal::install_interrupt_handler(),
+    );
+    let mut outcome = Outcome::Finished(0);
+    for (script, result) in matches.free.iter().zip(results) {
+        match result {
+            Err(e) if e.value == InterpreterErrorKind::Interrupted => {
+                outcome = Outcome::Interrupted;
+            }
+            Err(e) => {
+                println!("Error: {} at {}:{}", e.value, script, e.location);
+                if let Outcome::Finished(_) = outcome {
+                    outcome = Outcome::Failed;
+                }
+            }
+            Ok(_) => {}
+        }
+    }
+    match outcome {
+        Outcome::Finished(_) => {}
+        Outcome::Failed => process::exit(1),
+        Outcome::Interrupted => process::exit(130),
+    }
+}
+
+struct Source {
+    name: String,
+    text: String,
+}
+
+#[derive(Default)]
+struct RunOptions {
+    input: Option<String>,
+    // from the words after `--` or --input-string
+    input_args: Option<Vec<u8>>,
+    // seed and length
+    random_input: Option<(u64, Option<u64>)>,
+    input_timeout: Option<(Duration, TimeoutFallback)>,
+    output: Option<String>,
+    tee: Vec<String>,
+    artifacts: Option<String>,
+    explain: bool,
+    // the commands to describe, all if None
+    explain_steps: Option<u64>,
+    explain_delay: Option<Duration>,
+    // set by --cast too
+    play: Option<Playback>,
+    play_live: bool,
+    cast: Option<String>,
+    engine: EngineKind,
+    eof_behavior: EofBehavior,
+    output_encoding: OutputEncoding,
+    buffering: Option<Buffering>,
+    record_input: Option<String>,
+    replay_input: Option<String>,
+    html_path: Option<String>,
+    profile: bool,
+    dump_tokens: bool,
+    dump_ir: bool,
+    dump_format: Option<TapeFormat>,
+    dump_range: (Option<usize>, Option<usize>),
+    dump_on_interrupt: bool,
+    verbose: bool,
+    raw_input: bool,
+    filter: bool,
+    binary: bool,
+    tapes: bool,
+    extended: bool,
+    detect_loops: bool,
+    hash_output: bool,
+    warnings: bool,
+    bracket_mode: BracketMode,
+    nesting_limit: Option<usize>,
+    tape_file: Option<String>,
+    tape_kind: TapeKind,
+    tape_size: Option<usize>,
+    source_map: Option<String>,
+    then_stdin: bool,
+    prompt: Option<String>,
+    echo: bool,
+    source_from_stdin: bool,
+}
+
+enum Outcome {
+    // holds the value of the current cell when the program halted
+    Finished(u8),
+    Failed,
+    Interrupted,
+}
+
+// The status to exit with, None for 0 without --exit-code-from-cell.
+fn exit_code(outcome: Outcome, exit_code_from_cell: bool) -> Option<i32> {
+    match outcome {
+        Outcome::Finished(cell) if exit_code_from_cell => Some(i32::from(cell)),
+        Outcome::Finished(_) => None,
+        Outcome::Failed => Some(1),
+        Outcome::Interrupted => Some(130),
+    }
+}
+
+fn exit_with(outcome: Outcome, exit_code_from_cell: bool) {
+    if let Some(code) = exit_code(outcome, exit_code_from_cell) {
+        process::exit(code);
+    }
+}
+
+// Runs the concatenation of the sources.
+fn run_script(sources: &[Source], options: &RunOptions) -> Outcome {
+    let texts: Vec<&str> = sources.iter().map(|s| s.text.as_str()).collect();
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    if let (Some(html_path), false) = (&options.html_path, options.profile) {
+        write_html(html_path, &texts.join("\n"), &names.join(", "), None);
+        return Outcome::Finished(0);
+    }
+
+    let extensions = Extensions {
+        tapes: options.tapes,
+        extended: options.extended,
+        ..Extensions::default()
+    };
+    let mut program = match texts[..] {
+        // large generated programs are lexed on every core
+        [text] => {
+            let threads = thread::available_parallelism().map_or(1, |n| n.get());
+            Lexer::lex_parallel(text, extensions, threads)
+        }
+        _ => Lexer::lex_files_with(&texts, extensions),
+    };
+    let source_map = options.source_map.as_ref().map(|path| {
+        let map = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        });
+        Json::parse(&map)
+            .map_err(|e| e.to_string())
+            .and_then(|json| SourceMap::from_json(&json))
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {} in {}", e, path);
+                process::exit(64);
+            })
+    });
+    let mut names = names;
+    if let Some(ref map) = source_map {
+        // the map is of the first file
+        map.apply(&mut program, 0);
+        names[0] = &map.source;
     }
+    if options.dump_tokens {
+        for token in &program {
+            println!(
+                "{}:{}\t{}",
+                names[token.location.file], token.location, token.value
+            );
+        }
+        return Outcome::Finished(0);
+    }
+    if options.dump_ir {
+        return match optimizer::compile(&program) {
+            Ok(code) => {
+                for (index, instruction) in code.iter().enumerate() {
+                    let location = instruction.location;
+                    println!(
+                        "{:5}  {}:{}\t{}",
+                        index, names[location.file], location, instruction.value
+                    );
+                }
+                Outcome::Finished(0)
+            }
+            Err(e) => {
+                println!(
+                    "Error: {} at {}:{}",
+                    e.value, names[e.location.file], e.location
+                );
+                Outcome::Failed
+            }
+        };
+    }
+    let mut instance = options.engine.create();
+    let interpreter = instance.machine_mut();
+    if options.html_path.is_some() {
+        interpreter.enable_profiling();
+    }
+    interpreter.set_eof_behavior(options.eof_behavior);
+    interpreter.set_tape_kind(options.tape_kind);
+    interpreter.set_tape_size(options.tape_size);
+    interpreter.set_bracket_mode(options.bracket_mode);
+    interpreter.set_nesting_limit(options.nesting_limit);
+    if options.detect_loops {
+        interpreter.enable_loop_detection();
+    }
+    if options.warnings {
+        interpreter.enable_warnings();
+    }
+    if let Some(ref output_path) = options.output {
+        match File::create(output_path) {
+            Ok(file) => {
+                let buffering = options.buffering.unwrap_or(Buffering::Full);
+                interpreter.set_output(buffering.wrap(file))
+            }
+            Err(e) => {
+                eprintln!("Error: could not create {}: {}", output_path, e);
+                process::exit(73);
+            }
+        }
+    } else if let Some(buffering) = options.buffering {
+        interpreter.set_output(buffering.wrap(std::io::stdout()));
+    } else if options.explain {
+        // each byte shows up next to the command that wrote it
+        interpreter.set_output(Buffering::Unbuffered.wrap(std::io::stdout()));
+    } else if options.play_live && std::io::stdout().is_terminal() {
+        // it would be drawn over by the frames; it is written after them
+        interpreter.set_output(Box::new(std::io::sink()));
+    }
+    let hashed = if options.hash_output {
+        let output = HashingOutput::default();
+        interpreter.set_output(Box::new(output.clone()));
+        Some(output)
+    } else {
+        None
+    };
+    for path in &options.tee {
+        match File::create(path) {
+            Ok(file) => interpreter.add_output(Buffering::Full.wrap(file)),
+            Err(e) => {
+                eprintln!("Error: could not create {}: {}", path, e);
+                process::exit(73);
+            }
+        }
+    }
+    if let Some(ref dir) = options.artifacts {
+        let files = fs::create_dir_all(dir).and_then(|_| {
+            let output = File::create(Path::new(dir).join("output.bin"))?;
+            let trace = File::create(Path::new(dir).join("trace.jsonl"))?;
+            Ok((output, trace))
+        });
+        match files {
+            Ok((output, trace)) => {
+                interpreter.add_output(Buffering::Full.wrap(output));
+                interpreter.set_step_trace(Buffering::Full.wrap(trace));
+            }
+            Err(e) => {
+                eprintln!("Error: could not write to {}: {}", dir, e);
+                process::exit(73);
+            }
+        }
+        interpreter.enable_io_trace();
+    }
+    interpreter.set_output_encoding(options.output_encoding);
+    if let Some(ref input_path) = options.input {
+        match fs::read(input_path) {
+            Ok(bytes) => interpreter.set_input_bytes(bytes),
+            Err(e) => {
+                eprintln!("Error: could not read {}: {}", input_path, e);
+                process::exit(66);
+            }
+        }
+    }
+    if let Some(ref bytes) = options.input_args {
+        interpreter.set_input_bytes(bytes.clone());
+    }
+    if let Some((seed, length)) = options.random_input {
+        interpreter.set_input_source(Box::new(RandomInput::new(seed, length)));
+    }
+    let input_from_stdin =
+        options.input.is_none() && options.input_args.is_none() && options.random_input.is_none();
+    if options.source_from_stdin && input_from_stdin {
+        interpreter.set_input_bytes(vec![]);
+    }
+    if options.then_stdin {
+        interpreter.chain_input(Box::new(StdinInput::default()));
+    }
+    // restores the terminal when run_script returns
+    let mut _raw_mode = None;
+    let stream_stdin = options.raw_input || options.filter || options.binary;
+    if stream_stdin && input_from_stdin && options.replay_input.is_none() {
+        interpreter.set_input(if options.binary {
+            Box::new(std::io::stdin())
+        } else {
+            terminal::stdin_input()
+        });
+        if options.raw_input {
+            _raw_mode = RawMode::enable(options.echo).ok();
+        }
+    }
+    if let Some(ref replay_path) = options.replay_input {
+        let bytes = fs::read(replay_path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", replay_path, e);
+            process::exit(66);
+        });
+        interpreter.set_input_bytes(bytes);
+    }
+    if let Some(ref prompt) = options.prompt {
+        if std::io::stdin().is_terminal() {
+            interpreter.set_input_prompt(prompt);
+        }
+    }
+    if let Some((timeout, fallback)) = options.input_timeout {
+        interpreter.set_input_timeout(timeout, fallback);
+    }
+    if options.record_input.is_some() {
+        interpreter.enable_input_recording();
+    }
+
+    interpreter.set_interrupt_flag(signal::install_interrupt_handler());
+
+    // a missing file is an empty tape
+    let tape = options.tape_file.as_ref().map(|path| {
+        Tape::open_file(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Error: could not open {}: {}", path, e);
+            process::exit(66);
+        })
+    });
+
+    let started = Instant::now();
+    let result = instance.compile(&program).and_then(|_| {
+        if let Some((tape, pointer)) = tape {
+            instance.machine_mut().set_tape(tape, pointer);
+        }
+        if options.explain {
+            explain(instance.machine_mut(), options, &names)?;
+        }
+        if let Some(playback) = options.play {
+            play(instance.machine_mut(), &playback, options)?;
+        }
+        instance.run()
+    });
+    let elapsed = started.elapsed();
+    let interpreter = instance.machine();
+    if options.verbose {
+        let _ = std::io::stdout().flush();
+        eprint!("{}", summary(instance.kind(), elapsed, interpreter.stats()));
+    }
+    if let Some(ref dir) = options.artifacts {
+        let run = Run {
+            names: &names,
+            engine: instance.kind(),
+            elapsed,
+            result: &result,
+        };
+        if let Err(e) = write_artifacts(Path::new(dir), &run, interpreter) {
+            eprintln!("Error: could not write to {}: {}", dir, e);
+            process::exit(73);
+        }
+    }
+    if let (Some(record_path), Some(record)) = (&options.record_input, interpreter.recorded_input())
+    {
+        if let Err(e) = fs::write(record_path, record) {
+            eprintln!("Error: could not write {}: {}", record_path, e);
+            process::exit(73);
+        }
+    }
+    for warning in interpreter.warnings().unwrap_or_default() {
+        let name = names.get(warning.location.file).copied().unwrap_or("?");
+        eprintln!(
+            "Warning: {} at {}:{}",
+            warning.value, name, warning.location
+        );
+    }
+    if let Err(ref e) = result {
+        let name = names.get(e.location.file).copied().unwrap_or("?");
+        if e.value == InterpreterErrorKind::Interrupted {
+            let _ = std::io::stdout().flush();
+            eprintln!(
+                "\nInterrupted at {}:{} after {} steps",
+                name,
+                e.location,
+                interpreter.steps()
+            );
+            if options.dump_on_interrupt {
+                let pointer = interpreter.pointer();
+                eprint!(
+                    "{}",
+                    dump::dump_tape(
+                        interpreter.cells(),
+                        pointer,
+                        options.dump_format.unwrap_or(TapeFormat::Hex),
+                        (Some(pointer.saturating_sub(8)), Some(pointer + 8)),
+                    )
+                );
+            }
+            return Outcome::Interrupted;
+        }
+        let message = interpreter.error_message(e.value);
+        println!("Error: {} at {}:{}", message, name, e.location);
+    }
+    if let Some(output) = hashed {
+        println!("{}", output.digest());
+    }
+    let outcome = match result {
+        Ok(_) => Outcome::Finished(interpreter.cells()[interpreter.pointer()]),
+        Err(_) => Outcome::Failed,
+    };
+    if let Some(format) = options.dump_format {
+        eprint!(
+            "{}",
+            dump::dump_tape(
+                interpreter.cells(),
+                interpreter.pointer(),
+                format,
+                options.dump_range
+            )
+        );
+    }
+    if let Some(ref html_path) = options.html_path {
+        write_html(
+            html_path,
+            &texts.join("\n"),
+            &names.join(", "),
+            interpreter.profile(),
+        );
+    }
+    outcome
+}
+
+fn summary(engine: EngineKind, elapsed: Duration, stats: Stats) -> String {
+    [
+        "\n--- summary".to_string(),
+        format!("engine:       {}", engine),
+        format!("wall time:    {:.3} ms", elapsed.as_secs_f64() * 1000.0),
+        format!("instructions: {}", stats.steps),
+        format!("cycles:       {}", stats.cycles),
+        format!("peak tape:    {} cells", stats.peak_tape),
+        format!("max pointer:  {}", stats.max_pointer),
+        format!("tape growths: {}", stats.tape_growths),
+        format!("max depth:    {}", stats.max_depth),
+        format!("input:        {} bytes", stats.bytes_read),
+        format!("output:       {} bytes", stats.bytes_written),
+        String::new(),
+    ]
+    .join("\n")
+}
+
+// Describes the commands run on stderr, up to options.explain_steps of
+// them; the caller runs the rest.
+fn explain(
+    machine: &mut SimpleInterpreter,
+    options: &RunOptions,
+    names: &[&str],
+) -> Result<(), InterpreterError> {
+    let mut explained = 0;
+    while options.explain_steps.is_none_or(|steps| explained < steps) {
+        let location = match machine.next_location() {
+            Some(location) => location,
+            None => break,
+        };
+        let command = machine.next_command().unwrap();
+        let text = match explain::explain_step(machine)? {
+            Some(text) => text,
+            None => break,
+        };
+        let name = names.get(location.file).copied().unwrap_or("?");
+        eprintln!("{}:{} {}  {}", name, location, command, text);
+        explained += 1;
+        if let Some(delay) = options.explain_delay {
+            thread::sleep(delay);
+        }
+    }
+    Ok(())
+}
+
+// With --play, shows the frames of `playback` on stderr as the program runs,
+// then the output on stdout if it went nowhere else. With --cast, records
+// them to a file, at once without --play.
+fn play(
+    machine: &mut SimpleInterpreter,
+    playback: &Playback,
+    options: &RunOptions,
+) -> Result<(), InterpreterError> {
+    let started = Instant::now();
+    let mut cast = Cast::new();
+    let result = playback.play(machine, |time, frame| {
+        if options.cast.is_some() {
+            cast.push(time, frame);
+        }
+        if options.play_live {
+            if let Some(wait) = time.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+            // from the top of a cleared screen
+            eprint!("\x1b[H\x1b[2J{}", frame);
+        }
+    });
+    // a run ending in an error is worth a cast too
+    if let Some(ref path) = options.cast {
+        if let Err(e) = fs::write(path, cast.to_cast()) {
+            eprintln!("Error: could not write {}: {}", path, e);
+            process::exit(73);
+        }
+    }
+    let written = result?;
+    if options.play_live
+        && options.output.is_none()
+        && options.buffering.is_none()
+        && !options.hash_output
+        && std::io::stdout().is_terminal()
+    {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(&written).and_then(|_| stdout.flush());
+    }
+    Ok(())
+}
+
+// What write_artifacts records of a run besides the machine.
+struct Run<'a> {
+    names: &'a [&'a str],
+    engine: EngineKind,
+    elapsed: Duration,
+    result: &'a Result<usize, InterpreterError>,
+}
+
+// Writes a run to `dir` to be archived or attached to a bug report:
+// output.bin and the step trace in trace.jsonl, both written during the run,
+// the `,` and `.` of the run in io.jsonl, the summary of --verbose in
+// stats.txt, the tape in tape.txt and manifest.json, which names them and
+// tells how the run ended.
+fn write_artifacts(dir: &Path, run: &Run, machine: &SimpleInterpreter) -> std::io::Result<()> {
+    let mut io = String::new();
+    for event in machine.io_trace().unwrap_or_default() {
+        let command = match event.command {
+            TokenKind::Input => "input",
+            _ => "output",
+        };
+        let event = Json::object(vec![
+            ("step", event.step.into()),
+            ("command", command.into()),
+            ("byte", u64::from(event.byte).into()),
+            ("pointer", event.pointer.into()),
+            ("location", event.location.to_json()),
+        ]);
+        io.push_str(&format!("{}\n", event));
+    }
+    fs::write(dir.join("io.jsonl"), io)?;
+    let stats = machine.stats();
+    fs::write(
+        dir.join("stats.txt"),
+        summary(run.engine, run.elapsed, stats).trim_start(),
+    )?;
+    let tape = dump::dump_tape(
+        machine.cells(),
+        machine.pointer(),
+        TapeFormat::Hex,
+        (None, None),
+    );
+    fs::write(dir.join("tape.txt"), tape)?;
+    let result = match run.result {
+        Ok(_) => Json::from("ok"),
+        Err(e) => Json::object(vec![
+            ("kind", e.value.name().into()),
+            ("message", machine.error_message(e.value).into()),
+            (
+                "file",
+                run.names
+                    .get(e.location.file)
+                    .copied()
+                    .unwrap_or("?")
+                    .into(),
+            ),
+            ("location", e.location.to_json()),
+        ]),
+    };
+    let manifest = Json::object(vec![
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        (
+            "sources",
+            Json::Array(run.names.iter().map(|&name| name.into()).collect()),
+        ),
+        ("engine", run.engine.to_string().into()),
+        ("result", result),
+        ("steps", stats.steps.into()),
+        ("cycles", stats.cycles.into()),
+        ("output", "output.bin".into()),
+        ("trace", "trace.jsonl".into()),
+        ("io", "io.jsonl".into()),
+        ("stats", "stats.txt".into()),
+        ("tape", "tape.txt".into()),
+    ]);
+    fs::write(dir.join("manifest.json"), format!("{}\n", manifest))
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn modified_times(scripts: &[String]) -> Vec<Option<SystemTime>> {
+    scripts.iter().map(|s| modified_time(s)).collect()
+}
+
+// Reads the scripts as they are now and runs them.
+fn rerun(scripts: &[String], options: &RunOptions) -> Result<Outcome, String> {
+    let sources: Result<Vec<Source>, String> = scripts
+        .iter()
+        .map(|script| {
+            fs::read_to_string(script)
+                .map(|text| Source {
+                    name: script.clone(),
+                    text,
+                })
+                .map_err(|e| format!("could not read {}: {}", script, e))
+        })
+        .collect();
+    Ok(run_script(&sources?, options))
+}
+
+// Re-runs the scripts every time one of them changes, until Ctrl-C.
+fn watch(scripts: &[String], options: &RunOptions) -> ! {
+    loop {
+        let last_modified = modified_times(scripts);
+        match rerun(scripts, options) {
+            Ok(Outcome::Interrupted) => process::exit(130),
+            Ok(_) => {
+                let _ = std::io::stdout().flush();
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+        eprintln!(
+            "\n[watching {} for changes, Ctrl-C to quit]",
+            scripts.join(", ")
+        );
+        let interrupted = signal::install_interrupt_handler();
+        while modified_times(scripts) == last_modified {
+            if interrupted.load(Ordering::SeqCst) {
+                process::exit(130);
+            }
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+}
+
+// Like watch, but each run goes on from a checkpoint of the last one taken
+// before the first edited command, with the whole output printed again.
+// Input comes from -i or --input-string only, the same on every run.
+fn watch_live(script: &str, options: &RunOptions) -> ! {
+    let input = match (&options.input, &options.input_args) {
+        (Some(path), _) => fs::read(path).unwrap_or_else(|e| {
+            eprintln!("Error: could not read {}: {}", path, e);
+            process::exit(66);
+        }),
+        (None, Some(bytes)) => bytes.clone(),
+        (None, None) => Vec::new(),
+    };
+    let mut live = LiveRun::new(input);
+    live.machine_mut().set_eof_behavior(options.eof_behavior);
+    loop {
+        let last_modified = modified_time(script);
+        match fs::read_to_string(script) {
+            Ok(text) => {
+                let result = live.run(&Lexer::lex(&text));
+                let mut stdout = std::io::stdout();
+                let _ = stdout.write_all(&live.output());
+                let _ = stdout.flush();
+                if let Err(e) = result {
+                    println!("Error: {} at {}:{}", e.value, script, e.location);
+                }
+                eprintln!(
+                    "\n[{} steps taken over from the last run]",
+                    live.skipped_steps()
+                );
+            }
+            Err(e) => eprintln!("Error: could not read {}: {}", script, e),
+        }
+        eprintln!("[watching {} for changes, Ctrl-C to quit]", script);
+        let interrupted = signal::install_interrupt_handler();
+        while modified_time(script) == last_modified {
+            if interrupted.load(Ordering::SeqCst) {
+                process::exit(130);
+            }
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+}
+
+fn write_html(path: &str, source: &str, title: &str, counts: Option<&[u64]>) {
+    let mut f = File::create(path).expect("could not create the HTML file");
+    f.write_all(html::to_html(source, title, counts).as_bytes())
+        .expect("something went wrong writing the HTML file");
+}
+
+#[test]
+fn test_exit_code() {
+    let status = |source: &str, from_cell: bool| {
+        let sources = [Source {
+            name: "-e".to_string(),
+            text: source.to_string(),
+        }];
+        exit_code(run_script(&sources, &RunOptions::default()), from_cell)
+    };
+    assert_eq!(status("+++", false), None);
+    assert_eq!(status("+++", true), Some(3));
+    // a failing run cannot pass for a cell left at 0
+    assert_eq!(status("<", false), Some(1));
+    assert_eq!(status("<", true), Some(1));
+}
+
+#[test]
+fn test_artifacts() {
+    let dir = std::env::temp_dir().join(format!("brainfuck_test_artifacts_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let sources = [Source {
+        name: "-e".to_string(),
+        text: "++[>+<-]>.".to_string(),
+    }];
+    let options = RunOptions {
+        artifacts: Some(dir.to_string_lossy().into_owned()),
+        output: Some(dir.with_extension("out").to_string_lossy().into_owned()),
+        engine: EngineKind::Simple,
+        ..RunOptions::default()
+    };
+    assert!(matches!(
+        run_script(&sources, &options),
+        Outcome::Finished(_)
+    ));
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(fs::read(dir.join("output.bin")).unwrap(), [2]);
+    // every command, with the state before it
+    let trace = read("trace.jsonl");
+    let steps: Vec<&str> = trace.lines().collect();
+    assert_eq!(steps.len(), 16);
+    assert_eq!(
+        steps[2],
+        r#"{"step":3,"pointer":0,"cell":2,"location":{"line":1,"col":3,"file":0,"offset":2,"end":3}}"#
+    );
+    assert!(steps[15].starts_with(r#"{"step":16,"pointer":1,"cell":2,"#));
+    assert_eq!(
+        read("io.jsonl"),
+        "{\"step\":16,\"command\":\"output\",\"byte\":2,\"pointer\":1,\
+         \"location\":{\"line\":1,\"col\":10,\"file\":0,\"offset\":9,\"end\":10}}\n"
+    );
+    let stats = read("stats.txt");
+    assert!(stats.contains("instructions: 16"), "{}", stats);
+    assert!(stats.contains("engine:       simple"), "{}", stats);
+    assert_eq!(
+        read("tape.txt"),
+        dump::dump_tape(&[0, 2], 1, TapeFormat::Hex, (None, None))
+    );
+    let manifest = read("manifest.json");
+    assert!(
+        manifest.contains(r#""result":"ok","steps":16"#),
+        "{}",
+        manifest
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// The options of a run from its command line, without the program name.
+#[cfg(test)]
+fn parse_run_options(args: &[&str]) -> (Matches, RunOptions) {
+    let args = std::iter::once("brainfuck")
+        .chain(args.iter().copied())
+        .map(String::from)
+        .collect();
+    let (args, input_args) = split_input_args(args);
+    let matches = run_opts().parse(&args[1..]).unwrap();
+    let options = run_options(&matches, input_args);
+    (matches, options)
+}
+
+#[test]
+fn test_watch() {
+    let dir = std::env::temp_dir().join(format!("brainfuck_test_watch_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("watched.bf").to_string_lossy().into_owned();
+    let output = dir.join("output").to_string_lossy().into_owned();
+    fs::write(&script, "+++.").unwrap();
+    let (matches, options) = parse_run_options(&["--watch", "-o", &output, &script]);
+    assert!(matches.opt_present("watch"));
+    let scripts = matches.free;
+    assert!(matches!(
+        rerun(&scripts, &options),
+        Ok(Outcome::Finished(3))
+    ));
+    assert_eq!(fs::read(&output).unwrap(), [3]);
+
+    // an edit is noticed, and the next run reads it
+    let last_modified = modified_times(&scripts);
+    fs::write(&script, "++.").unwrap();
+    let later = last_modified[0].unwrap() + Duration::from_secs(1);
+    File::options()
+        .write(true)
+        .open(&script)
+        .and_then(|file| file.set_modified(later))
+        .unwrap();
+    assert_ne!(modified_times(&scripts), last_modified);
+    assert!(matches!(
+        rerun(&scripts, &options),
+        Ok(Outcome::Finished(2))
+    ));
+    assert_eq!(fs::read(&output).unwrap(), [2]);
+
+    // a script removed while watching is reported and tried again later
+    fs::remove_file(&script).unwrap();
+    assert_eq!(modified_times(&scripts), [None]);
+    assert!(rerun(&scripts, &options)
+        .err()
+        .unwrap()
+        .starts_with(&format!("could not read {}", script)));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_eval() {
+    let output = std::env::temp_dir().join(format!("brainfuck_test_eval_{}", process::id()));
+    let output = output.to_string_lossy().into_owned();
+    // scripts are not read with -e
+    let (matches, mut options) = parse_run_options(&[
+        "-e",
+        "++++++++[>++++++++<-]>+.",
+        "-o",
+        &output,
+        "missing.bf",
+    ]);
+    let sources = read_sources(&matches, &mut std::io::empty(), &mut options);
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].name, "-e");
+    assert!(matches!(
+        run_script(&sources, &options),
+        Outcome::Finished(65)
+    ));
+    assert_eq!(fs::read(&output).unwrap(), b"A");
+    let _ = fs::remove_file(&output);
+}
+
+#[test]
+fn test_source_from_stdin() {
+    let dir = std::env::temp_dir().join(format!("brainfuck_test_stdin_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input").to_string_lossy().into_owned();
+    let output = dir.join("output").to_string_lossy().into_owned();
+    fs::write(&input, "hi").unwrap();
+    let run = |args: &[&str]| {
+        let (matches, mut options) = parse_run_options(args);
+        let sources = read_sources(&matches, &mut &b",.,."[..], &mut options);
+        assert_eq!(sources[0].name, "-");
+        assert_eq!(sources[0].text, ",.,.");
+        assert!(options.source_from_stdin);
+        run_script(&sources, &options);
+        fs::read(&output).unwrap()
+    };
+    // ',' cannot read the rest of stdin, so it gets the EOF value
+    assert_eq!(run(&["-o", &output, "-"]), b"\n\n");
+    assert_eq!(run(&["-o", &output, "--eof", "zero", "-"]), [0, 0]);
+    assert_eq!(run(&["-o", &output, "-i", &input, "-"]), b"hi");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_input_args() {
+    let output = std::env::temp_dir().join(format!("brainfuck_test_args_{}", process::id()));
+    let output = output.to_string_lossy().into_owned();
+    // options after `--` are input too
+    let (matches, mut options) = parse_run_options(&[
+        "-e", ",[.,]", "-o", &output, "--eof", "zero", "--", "hello", "-v", "world",
+    ]);
+    assert!(!matches.opt_present("v"));
+    assert_eq!(options.input_args, Some(b"hello -v world\n".to_vec()));
+    let sources = read_sources(&matches, &mut std::io::empty(), &mut options);
+    run_script(&sources, &options);
+    assert_eq!(fs::read(&output).unwrap(), b"hello -v world\n");
+    // nothing after `--` is a line of its own
+    let (_, options) = parse_run_options(&["-e", ",[.,]", "--"]);
+    assert_eq!(options.input_args, Some(b"\n".to_vec()));
+    let _ = fs::remove_file(&output);
+}
+
+#[test]
+fn test_buffer() {
+    let path = std::env::temp_dir().join(format!("brainfuck_test_buffer_{}", process::id()));
+    let buffering = |args: &[&str]| parse_run_options(args).1.buffering;
+    assert_eq!(buffering(&["-"]), None);
+    assert_eq!(buffering(&["--buffer", "line", "-"]), Some(Buffering::Line));
+    let unbuffered = buffering(&["--buffer", "none", "-"]).unwrap();
+    assert_eq!(unbuffered, Buffering::Unbuffered);
+    // what the program wrote is in the file before it ends
+    let mut output = unbuffered.wrap(File::create(&path).unwrap());
+    output.write_all(b"a").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"a");
+    // with -o alone, it is kept until the end
+    let mut output = Buffering::Full.wrap(File::create(&path).unwrap());
+    output.write_all(b"a").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"");
+    drop(output);
+    assert_eq!(fs::read(&path).unwrap(), b"a");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_no_echo() {
+    let (_, options) = parse_run_options(&["--raw", "-"]);
+    assert!(options.raw_input && options.echo);
+    let (_, options) = parse_run_options(&["--raw", "--no-echo", "-"]);
+    assert!(options.raw_input && !options.echo);
+}
+
+#[test]
+fn test_filter() {
+    let (_, options) = parse_run_options(&["--filter", "-e", ",[.,]"]);
+    assert!(options.filter);
+    // ends at a zero byte, and writes in blocks
+    assert_eq!(options.eof_behavior, EofBehavior::Zero);
+    assert_eq!(options.buffering, Some(Buffering::Full));
+    let (_, options) = parse_run_options(&["--filter", "--eof", "unchanged", "-e", ",[.,]"]);
+    assert_eq!(options.eof_behavior, EofBehavior::Unchanged);
+    let (_, options) = parse_run_options(&["--filter", "--buffer", "line", "-e", ",[.,]"]);
+    assert_eq!(options.buffering, Some(Buffering::Line));
+    let (_, options) = parse_run_options(&["-e", ",[.,]"]);
+    assert!(!options.filter);
+    assert_eq!(options.eof_behavior, EofBehavior::Newline);
+
+    let check = |args: &[&str]| {
+        let (args, input_args) = split_input_args(args.iter().map(|a| a.to_string()).collect());
+        check_filter(&run_opts().parse(&args).unwrap(), input_args.is_some())
+    };
+    assert_eq!(check(&["--filter", "p.bf"]), Ok(()));
+    // the program would take all of stdin
+    assert!(check(&["--filter", "-"]).is_err());
+    assert_eq!(check(&["--filter", "-e", ",[.,]", "-"]), Ok(()));
+    assert!(check(&["--filter", "-i", "input", "p.bf"]).is_err());
+    assert!(check(&["--filter", "p.bf", "--", "words"]).is_err());
+    assert_eq!(check(&["-i", "input", "-"]), Ok(()));
 }
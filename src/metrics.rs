@@ -0,0 +1,116 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Upper bounds of the run duration histogram, in seconds.
+const BUCKETS: [f64; 6] = [0.001, 0.01, 0.1, 1.0, 10.0, 60.0];
+
+// A limit that stopped a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Output,
+}
+
+// Counters of a service, shared between its connection threads and read by
+// Prometheus from /metrics.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    runs: AtomicU64,
+    steps: AtomicU64,
+    step_limit: AtomicU64,
+    output_limit: AtomicU64,
+    // counts of runs at most as long as each bucket; the last one is +Inf
+    durations: [AtomicU64; BUCKETS.len() + 1],
+    duration_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record(&self, steps: u64, elapsed: Duration, limit: Option<Limit>) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.steps.fetch_add(steps, Ordering::Relaxed);
+        match limit {
+            Some(Limit::Steps) => self.step_limit.fetch_add(1, Ordering::Relaxed),
+            Some(Limit::Output) => self.output_limit.fetch_add(1, Ordering::Relaxed),
+            None => 0,
+        };
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        self.durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn runs(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    // The Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "# HELP brainfuck_runs_total Programs run.\n\
+             # TYPE brainfuck_runs_total counter\n\
+             brainfuck_runs_total {}\n\
+             # HELP brainfuck_steps_total Commands executed.\n\
+             # TYPE brainfuck_steps_total counter\n\
+             brainfuck_steps_total {}\n\
+             # HELP brainfuck_limit_violations_total Runs stopped by a limit.\n\
+             # TYPE brainfuck_limit_violations_total counter\n\
+             brainfuck_limit_violations_total{{limit=\"steps\"}} {}\n\
+             brainfuck_limit_violations_total{{limit=\"output\"}} {}\n\
+             # HELP brainfuck_run_duration_seconds Time taken by a run.\n\
+             # TYPE brainfuck_run_duration_seconds histogram\n",
+            load(&self.runs),
+            load(&self.steps),
+            load(&self.step_limit),
+            load(&self.output_limit),
+        );
+        let mut cumulative = 0;
+        for (i, counter) in self.durations.iter().enumerate() {
+            cumulative += load(counter);
+            let bound = BUCKETS.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(
+                out,
+                "brainfuck_run_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let _ = write!(
+            out,
+            "brainfuck_run_duration_seconds_sum {}\n\
+             brainfuck_run_duration_seconds_count {}\n",
+            load(&self.duration_micros) as f64 / 1e6,
+            cumulative
+        );
+        out
+    }
+}
+
+#[test]
+fn test_metrics() {
+    let metrics = Metrics::default();
+    metrics.record(10, Duration::from_millis(5), None);
+    metrics.record(1000, Duration::from_secs(100), Some(Limit::Steps));
+    assert_eq!(metrics.runs(), 2);
+    let text = metrics.render();
+    for line in &[
+        "brainfuck_runs_total 2",
+        "brainfuck_steps_total 1010",
+        "brainfuck_limit_violations_total{limit=\"steps\"} 1",
+        "brainfuck_limit_violations_total{limit=\"output\"} 0",
+        "brainfuck_run_duration_seconds_bucket{le=\"0.001\"} 0",
+        "brainfuck_run_duration_seconds_bucket{le=\"0.01\"} 1",
+        "brainfuck_run_duration_seconds_bucket{le=\"60\"} 1",
+        "brainfuck_run_duration_seconds_bucket{le=\"+Inf\"} 2",
+        "brainfuck_run_duration_seconds_sum 100.005",
+        "brainfuck_run_duration_seconds_count 2",
+    ] {
+        assert!(text.lines().any(|l| l == *line), "missing {}", line);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::random::Rng;
+
+const COMMANDS: &[u8] = b"+-<>.,";
+
+// A change to a program that keeps its brackets balanced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    // adds a command other than a bracket
+    Insert,
+    // removes a command other than a bracket
+    Delete,
+    // exchanges two commands other than brackets
+    Swap,
+    // puts a balanced run of commands in a loop
+    Wrap,
+    // removes the brackets of a loop, keeping its body
+    Unwrap,
+}
+
+pub const MUTATIONS: [Mutation; 5] = [
+    Mutation::Insert,
+    Mutation::Delete,
+    Mutation::Swap,
+    Mutation::Wrap,
+    Mutation::Unwrap,
+];
+
+impl Mutation {
+    // Applies the mutation at random places of the program, whose brackets
+    // must be balanced. Comments are dropped. None when the program has
+    // nothing to apply it to, e.g. no loop to unwrap.
+    pub fn apply(self, source: &str, rng: &mut Rng) -> Option<String> {
+        let mut program: Vec<u8> = source.bytes().filter(|c| b"+-<>.,[]".contains(c)).collect();
+        let pick = |rng: &mut Rng, positions: &[usize]| {
+            if positions.is_empty() {
+                None
+            } else {
+                Some(positions[rng.below(positions.len() as u64) as usize])
+            }
+        };
+        let plain: Vec<usize> = (0..program.len())
+            .filter(|&i| COMMANDS.contains(&program[i]))
+            .collect();
+        match self {
+            Mutation::Insert => {
+                let at = rng.below(program.len() as u64 + 1) as usize;
+                let command = COMMANDS[rng.below(COMMANDS.len() as u64) as usize];
+                program.insert(at, command);
+            }
+            Mutation::Delete => {
+                program.remove(pick(rng, &plain)?);
+            }
+            Mutation::Swap => {
+                let a = pick(rng, &plain)?;
+                // swapping a command with one like it changes nothing
+                let others: Vec<usize> = plain
+                    .iter()
+                    .copied()
+                    .filter(|&b| program[b] != program[a])
+                    .collect();
+                program.swap(a, pick(rng, &others)?);
+            }
+            Mutation::Wrap => {
+                let start = rng.below(program.len() as u64 + 1) as usize;
+                // the ends of the balanced runs from `start`
+                let mut ends = vec![start];
+                let mut depth = 0;
+                for (i, &c) in program.iter().enumerate().skip(start) {
+                    match c {
+                        b'[' => depth += 1,
+                        b']' if depth == 0 => break,
+                        b']' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        ends.push(i + 1);
+                    }
+                }
+                let end = pick(rng, &ends)?;
+                program.insert(end, b']');
+                program.insert(start, b'[');
+            }
+            Mutation::Unwrap => {
+                let opens: Vec<usize> =
+                    (0..program.len()).filter(|&i| program[i] == b'[').collect();
+                let open = pick(rng, &opens)?;
+                let mut depth = 0;
+                let close = (open..program.len()).find(|&i| {
+                    match program[i] {
+                        b'[' => depth += 1,
+                        b']' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })?;
+                program.remove(close);
+                program.remove(open);
+            }
+        }
+        String::from_utf8(program).ok()
+    }
+}
+
+// Applies one random mutation, for genetic programming or for making
+// variants of a program that an interpreter must cope with. The brackets of
+// `source` must be balanced, and stay so.
+pub fn mutate(source: &str, rng: &mut Rng) -> String {
+    loop {
+        let mutation = MUTATIONS[rng.below(MUTATIONS.len() as u64) as usize];
+        // inserting always applies
+        if let Some(mutated) = mutation.apply(source, rng) {
+            return mutated;
+        }
+    }
+}
+
+#[test]
+fn test_mutation() {
+    let mut rng = Rng::new(3);
+    assert_eq!(Mutation::Delete.apply("[]", &mut rng), None);
+    assert_eq!(Mutation::Unwrap.apply("+-", &mut rng), None);
+    assert_eq!(
+        Mutation::Unwrap.apply("+[-.]", &mut rng),
+        Some("+-.".to_string())
+    );
+    assert_eq!(
+        Mutation::Delete.apply("[.] ok", &mut rng),
+        Some("[]".to_string())
+    );
+    assert_eq!(Mutation::Swap.apply("+[+]", &mut rng), None);
+    assert_eq!(
+        Mutation::Swap.apply("+[.]", &mut rng),
+        Some(".[+]".to_string())
+    );
+    assert_eq!(Mutation::Wrap.apply("", &mut rng), Some("[]".to_string()));
+
+    let balanced = |program: &str| {
+        let mut depth = 0;
+        program.chars().all(|c| {
+            depth += match c {
+                '[' => 1,
+                ']' => -1,
+                _ => 0,
+            };
+            depth >= 0
+        }) && depth == 0
+    };
+    let mut program = "+[->+<]>.".to_string();
+    for _ in 0..500 {
+        program = mutate(&program, &mut rng);
+        assert!(balanced(&program), "{}", program);
+    }
+}
@@ -0,0 +1,388 @@
+use crate::analysis;
+use crate::compiled::{self, CompiledProgram};
+use crate::interpreter::{BracketMode, InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::lexer::{Annotation, Location, Program, TokenKind};
+use crate::optimizer::{self, Code, Cost, Op};
+use std::sync::Arc;
+
+// Runs the code produced by the optimizer. The tape, the pointer and the I/O
+// settings live in an inner SimpleInterpreter so that both engines share them.
+pub struct OptimizedInterpreter {
+    machine: SimpleInterpreter,
+    code: Arc<Code>,
+    // of each instruction, to count cycles
+    costs: Arc<Vec<Cost>>,
+    program: Arc<Program>,
+    // the next instruction
+    pc: usize,
+}
+
+impl Default for OptimizedInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OptimizedInterpreter {
+    pub fn new() -> Self {
+        Self {
+            machine: SimpleInterpreter::new(),
+            code: Arc::new(Vec::new()),
+            costs: Arc::new(Vec::new()),
+            program: Arc::new(Vec::new()),
+            pc: 0,
+        }
+    }
+    pub fn machine(&self) -> &SimpleInterpreter {
+        &self.machine
+    }
+    pub fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        &mut self.machine
+    }
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    pub fn eval(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        self.compile(program)?;
+        self.run()
+    }
+
+    // Prepares the program for run or step on a fresh tape.
+    pub fn compile(&mut self, program: &Program) -> Result<(), InterpreterError> {
+        self.machine.check_nesting(program)?;
+        let jump_table = match self.machine.bracket_mode() {
+            BracketMode::Strict => analysis::match_brackets(program)?,
+            BracketMode::Permissive => analysis::match_brackets_permissive(program),
+        };
+        let (code, costs) = optimizer::compile_with(program, &jump_table);
+        compiled::replace(&mut self.program, program);
+        self.load_code(Arc::new(code), Arc::new(costs));
+        Ok(())
+    }
+
+    // Prepares a program compiled earlier for run or step on a fresh tape,
+    // sharing it.
+    pub fn load_compiled(&mut self, compiled: &CompiledProgram) {
+        self.program = compiled.program.clone();
+        self.load_code(compiled.code.clone(), compiled.costs.clone());
+    }
+
+    // Compiles more tokens onto the end of the code run so far and continues
+    // from where it stopped, keeping the tape and the pointer. The appended
+    // tokens must have balanced brackets. If they fail, the rest of them is
+    // skipped.
+    pub fn eval_append(&mut self, program: &Program) -> Result<usize, InterpreterError> {
+        let (appended, costs) =
+            optimizer::compile_with(program, &analysis::match_brackets(program)?);
+        self.machine.check_nesting(program)?;
+        let offset = self.code.len();
+        let code = Arc::make_mut(&mut self.code);
+        code.extend(appended.into_iter().map(|instruction| Annotation {
+            value: match instruction.value {
+                Op::JumpIfZero(target) => Op::JumpIfZero(target + offset),
+                Op::JumpIfNonZero(target) => Op::JumpIfNonZero(target + offset),
+                op => op,
+            },
+            location: instruction.location,
+        }));
+        Arc::make_mut(&mut self.costs).extend(costs);
+        Arc::make_mut(&mut self.program).extend_from_slice(program);
+        self.pc = offset;
+        let result = self.run();
+        if result.is_err() {
+            self.pc = self.code.len();
+        }
+        result
+    }
+
+    fn load_code(&mut self, code: Arc<Code>, costs: Arc<Vec<Cost>>) {
+        self.code = code;
+        self.costs = costs;
+        self.pc = 0;
+        self.machine.reset_tape();
+    }
+
+    // Runs the compiled code from where it stopped to the end and flushes
+    // the output.
+    pub fn run(&mut self) -> Result<usize, InterpreterError> {
+        let code = &self.code;
+        let costs = &self.costs;
+        let program = &self.program;
+        let m = &mut self.machine;
+        let mut pc = self.pc;
+        let result = run_from(code, costs, program, m, &mut pc);
+        self.pc = pc;
+        let last = self.code.last().map(|i| i.location);
+        self.machine.finish(result, last)
+    }
+
+    // Runs the next instruction. Returns false once the code has ended.
+    pub fn step(&mut self) -> Result<bool, InterpreterError> {
+        if self.pc >= self.code.len() {
+            return Ok(false);
+        }
+        execute(
+            &self.code,
+            &self.costs,
+            &self.program,
+            &mut self.machine,
+            &mut self.pc,
+        )?;
+        Ok(true)
+    }
+}
+
+fn run_from(
+    code: &Code,
+    costs: &[Cost],
+    program: &Program,
+    m: &mut SimpleInterpreter,
+    pc: &mut usize,
+) -> Result<usize, InterpreterError> {
+    while *pc < code.len() {
+        execute(code, costs, program, m, pc)?;
+    }
+    Ok(0)
+}
+
+// Runs the instruction at `pc` and moves `pc` to the next one.
+#[inline(always)]
+fn execute(
+    code: &Code,
+    costs: &[Cost],
+    program: &Program,
+    m: &mut SimpleInterpreter,
+    pc: &mut usize,
+) -> Result<(), InterpreterError> {
+    let instruction = &code[*pc];
+    let location = instruction.location;
+    let cost = costs[*pc];
+    m.count_step(location)?;
+    m.cycles += cost.fixed();
+    // jumps taken or iterations of a replaced loop
+    let mut repeats = 0;
+    match instruction.value {
+        Op::Add(n) => {
+            m.check_wrap(m.cells[m.pointer], signed(n), location);
+            m.cells[m.pointer] = m.cells[m.pointer].wrapping_add(n)
+        }
+        Op::Move(offset) => {
+            if offset < 0 && m.pointer < offset.unsigned_abs() {
+                return Err(replay_to_pointer_error(m, program, location));
+            }
+            m.move_pointer(offset, location)?
+        }
+        Op::Output => m.write_byte(location)?,
+        Op::Input => m.cells[m.pointer] = m.read_byte(location)?,
+        Op::Call => m.call_host(),
+        Op::SwitchTape => m.switch_tape(),
+        Op::Transfer => m.transfer(),
+        Op::End => *pc = code.len() - 1,
+        Op::Store => m.store(),
+        Op::Retrieve => m.retrieve(),
+        Op::Set(n) => m.cells[m.pointer] = n,
+        Op::JumpIfZero(target) => {
+            if m.cells[m.pointer] == 0 {
+                repeats = 1;
+                *pc = target;
+            } else {
+                m.enter_loop();
+            }
+        }
+        Op::JumpIfNonZero(target) => {
+            if m.cells[m.pointer] != 0 {
+                m.check_interrupt(location)?;
+                m.check_loop(*pc, location)?;
+                repeats = 1;
+                *pc = target;
+            } else {
+                m.leave_loop();
+            }
+        }
+        Op::Clear => {
+            repeats = cost.iterations(m.cells[m.pointer]);
+            m.cells[m.pointer] = 0
+        }
+        Op::Scan(step) => {
+            while m.cells[m.pointer] != 0 {
+                m.check_interrupt(location)?;
+                if step < 0 && m.pointer < step.unsigned_abs() {
+                    return Err(replay_to_pointer_error(m, program, location));
+                }
+                m.move_pointer(step, location)?;
+                repeats += 1;
+            }
+        }
+        Op::MultiplyAdd {
+            ref targets,
+            bounds: (min, max),
+        } => {
+            let value = m.cells[m.pointer];
+            repeats = cost.iterations(value);
+            if value != 0 {
+                if m.pointer < min.unsigned_abs() {
+                    return Err(replay_to_pointer_error(m, program, location));
+                }
+                let highest = (m.pointer as isize + max) as usize;
+                if !m.grow_tape(highest + 1) {
+                    return Err(InterpreterError {
+                        value: InterpreterErrorKind::PointerError,
+                        location,
+                    });
+                }
+                m.max_pointer = m.max_pointer.max(highest);
+                for &(offset, factor) in targets.iter() {
+                    let target = (m.pointer as isize + offset) as usize;
+                    m.check_wrap(m.cells[target], i32::from(value) * signed(factor), location);
+                    m.cells[target] = m.cells[target].wrapping_add(value.wrapping_mul(factor));
+                }
+                m.cells[m.pointer] = 0;
+            }
+        }
+    }
+    if let Op::Clear | Op::Scan(_) | Op::MultiplyAdd { .. } = instruction.value {
+        if repeats > 0 {
+            // the loop it replaces
+            m.enter_loop();
+            m.leave_loop();
+        }
+    }
+    m.cycles += cost.extra(repeats);
+    *pc += 1;
+    Ok(())
+}
+
+// What an Add of `n` stands for: `n` times `+`, or 256 - `n` times `-`.
+fn signed(n: u8) -> i32 {
+    i32::from(n as i8)
+}
+
+// A folded instruction carries the location of its first token. When it would
+// move the pointer below cell 0, the tokens are replayed one by one from there,
+// so the error location and the tape match what the simple interpreter leaves.
+fn replay_to_pointer_error(
+    m: &mut SimpleInterpreter,
+    program: &Program,
+    location: Location,
+) -> InterpreterError {
+    let error = InterpreterError {
+        value: InterpreterErrorKind::PointerError,
+        location,
+    };
+    // appended tokens may repeat locations, and the latest ones are running
+    let start = match program.iter().rposition(|t| t.location == location) {
+        Some(start) => start,
+        None => return error,
+    };
+    for token in &program[start..] {
+        match token.value {
+            TokenKind::Increment => m.cells[m.pointer] = m.cells[m.pointer].wrapping_add(1),
+            TokenKind::Decrement => m.cells[m.pointer] = m.cells[m.pointer].wrapping_sub(1),
+            TokenKind::IncrementPointer => {
+                if let Err(e) = m.move_pointer(1, token.location) {
+                    return e;
+                }
+            }
+            TokenKind::DecrementPointer if m.pointer == 0 => {
+                return InterpreterError {
+                    value: InterpreterErrorKind::PointerError,
+                    location: token.location,
+                };
+            }
+            TokenKind::DecrementPointer => m.pointer -= 1,
+            _ => {}
+        }
+    }
+    error
+}
+
+#[test]
+fn test_optimized_interpreter() {
+    use crate::lexer::Lexer;
+    let sources = [
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.",
+        "+++++[->++>>+<<<]>>>>+[<]<<",
+        ">+++[-<+>]<<",
+        "+>>[-]<<<",
+        "+>>+[<<]",
+        ">>+[-<<<+>>>]",
+        "+++[>+++[>+<-]<-]>>[<<+>>-]",
+        "+[-<<>>]+.",
+    ];
+    for source in sources.iter() {
+        let program = Lexer::lex(source);
+        let mut simple = SimpleInterpreter::new();
+        simple.set_output(Box::new(std::io::sink()));
+        let mut optimized = OptimizedInterpreter::new();
+        optimized
+            .machine_mut()
+            .set_output(Box::new(std::io::sink()));
+        assert_eq!(
+            simple.eval(&program),
+            optimized.eval(&program),
+            "{}",
+            source
+        );
+        assert_eq!(
+            simple.snapshot(),
+            optimized.machine().snapshot(),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_cycles() {
+    use crate::interpreter::{EofBehavior, Stats};
+    use crate::lexer::Lexer;
+    use crate::random::Rng;
+
+    let cycles = |source: &str, input: &[u8]| {
+        let program = Lexer::lex(source);
+        let mut simple = SimpleInterpreter::new();
+        let mut optimized = OptimizedInterpreter::new();
+        for m in [&mut simple, optimized.machine_mut()] {
+            m.set_output(Box::new(std::io::sink()));
+            m.set_input_bytes(input.to_vec());
+            m.set_eof_behavior(EofBehavior::Zero);
+            m.set_step_limit(Some(100_000));
+        }
+        // the step limit stops them at different places
+        if simple.eval(&program).is_err() || optimized.eval(&program).is_err() {
+            return None;
+        }
+        // and so is the resource usage but for the tape growths
+        let usage = |stats: Stats| (stats.cycles, stats.max_pointer, stats.max_depth);
+        assert_eq!(
+            usage(simple.stats()),
+            usage(optimized.machine().stats()),
+            "{}",
+            source
+        );
+        Some(simple.cycles())
+    };
+    assert_eq!(cycles("+-", b""), Some(2));
+    // `[` reaches its `]` on 0
+    assert_eq!(cycles("[-]", b""), Some(2));
+    assert_eq!(cycles("++[-]", b""), Some(2 + 2 * 3));
+    assert_eq!(cycles("-[-]", b""), Some(1 + 255 * 3));
+    assert_eq!(cycles("-[+]", b""), Some(1 + 3));
+    for source in [
+        ",[->+>++<<]>>[<+>-]<.",
+        ",[>+-<-+-]",
+        "+>+>+>>+<<<<[>]>[>-+<<]",
+        "++[>+++[>+<-]<-]>>[<<+>>-]<<+-",
+        ">,[>,]<[.<]",
+    ] {
+        assert!(cycles(source, b"\x05ab").is_some(), "{}", source);
+    }
+
+    let mut rng = Rng::new(0);
+    let mut source = String::new();
+    for _ in 0..500 {
+        source = crate::mutation::mutate(&source, &mut rng);
+        cycles(&source, b"\x03\xfd");
+    }
+}
@@ -0,0 +1,342 @@
+use crate::analysis::{self, LoopKind};
+use crate::interpreter::InterpreterError;
+use crate::lexer::{Annotation, Program, TokenKind};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Op {
+    Add(u8),
+    Move(isize),
+    Output,
+    Input,
+    Call,
+    SwitchTape,
+    Transfer,
+    // jumps past the end of the code
+    End,
+    Store,
+    Retrieve,
+    Set(u8),
+    // the argument is the index of the matching jump
+    JumpIfZero(usize),
+    JumpIfNonZero(usize),
+    Clear,
+    Scan(isize),
+    // adds cell * factor to the cell at each offset, then clears the cell.
+    // `bounds` are the lowest and highest offsets the original loop body visits.
+    MultiplyAdd {
+        targets: Vec<(isize, u8)>,
+        bounds: (isize, isize),
+    },
+}
+pub type Instruction = Annotation<Op>;
+pub type Code = Vec<Instruction>;
+
+// What an instruction costs in cycles: one per command the simple engine
+// steps through for it, so that every engine counts the same cycles.
+// Commands folded away, like `+-`, are charged to the next instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cost {
+    Fixed(u64),
+    // a jump costs `taken` more when it jumps: to the `]` for `[`, to the
+    // `[` for `]`
+    Jump { fixed: u64, taken: u64 },
+    // a replaced loop of `body` commands costs its two brackets and its body
+    // per iteration, or two when it does not run. `up` is whether its
+    // counter counts up to 256 rather than down to 0.
+    Loop { fixed: u64, body: u64, up: bool },
+}
+
+impl Cost {
+    // Charged before the instruction runs.
+    pub fn fixed(self) -> u64 {
+        match self {
+            Cost::Fixed(fixed) | Cost::Jump { fixed, .. } | Cost::Loop { fixed, .. } => fixed,
+        }
+    }
+
+    // Charged after it ran, having jumped or gone round its loop `n` times.
+    pub fn extra(self, n: u64) -> u64 {
+        match self {
+            Cost::Fixed(_) => 0,
+            Cost::Jump { taken, .. } => n * taken,
+            Cost::Loop { .. } if n == 0 => 2,
+            Cost::Loop { body, .. } => n * (body + 2),
+        }
+    }
+
+    // How many times a clear or multiply loop goes round from `value`.
+    pub fn iterations(self, value: u8) -> u64 {
+        match self {
+            Cost::Loop { up: true, .. } if value != 0 => 256 - u64::from(value),
+            _ => u64::from(value),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Add(n) => write!(f, "add {}", *n as i8),
+            Op::Move(offset) => write!(f, "move {}", offset),
+            Op::Output => write!(f, "output"),
+            Op::Input => write!(f, "input"),
+            Op::Call => write!(f, "call"),
+            Op::SwitchTape => write!(f, "switch"),
+            Op::Transfer => write!(f, "transfer"),
+            Op::End => write!(f, "end"),
+            Op::Store => write!(f, "store"),
+            Op::Retrieve => write!(f, "retrieve"),
+            Op::Set(n) => write!(f, "set {}", n),
+            Op::JumpIfZero(target) => write!(f, "jz {}", target),
+            Op::JumpIfNonZero(target) => write!(f, "jnz {}", target),
+            Op::Clear => write!(f, "clear"),
+            Op::Scan(step) => write!(f, "scan {}", step),
+            Op::MultiplyAdd { targets, .. } => {
+                let targets: Vec<String> = targets
+                    .iter()
+                    .map(|(offset, factor)| format!("[{:+}]*{}", offset, *factor as i8))
+                    .collect();
+                write!(f, "muladd {}", targets.join(" "))
+            }
+        }
+    }
+}
+
+fn visited_bounds(program: &Program, start: usize, end: usize) -> (isize, isize) {
+    let mut offset: isize = 0;
+    let (mut min, mut max) = (0, 0);
+    for token in &program[start + 1..end] {
+        match token.value {
+            TokenKind::IncrementPointer => offset += 1,
+            TokenKind::DecrementPointer => offset -= 1,
+            _ => {}
+        }
+        min = min.min(offset);
+        max = max.max(offset);
+    }
+    (min, max)
+}
+
+// Whether the cell a loop starts on goes up on each iteration.
+fn counts_up(program: &Program, start: usize, end: usize) -> bool {
+    let mut offset: isize = 0;
+    let mut delta = 0;
+    for token in &program[start + 1..end] {
+        match token.value {
+            TokenKind::IncrementPointer => offset += 1,
+            TokenKind::DecrementPointer => offset -= 1,
+            TokenKind::Increment if offset == 0 => delta += 1,
+            TokenKind::Decrement if offset == 0 => delta -= 1,
+            _ => {}
+        }
+    }
+    delta > 0
+}
+
+// Folds runs of `+-` and `<>`, and replaces clear, scan and multiply loops
+// with single instructions. Moves are only folded in one direction so that
+// the pointer errors happen exactly where the simple interpreter reports them.
+pub fn compile(program: &Program) -> Result<Code, InterpreterError> {
+    let jump_table = analysis::match_brackets(program)?;
+    Ok(compile_with(program, &jump_table).0)
+}
+
+// Compiles a program with unmatched brackets for BracketMode::Permissive.
+pub fn compile_permissive(program: &Program) -> Code {
+    compile_with(program, &analysis::match_brackets_permissive(program)).0
+}
+
+// The code and the cost of each of its instructions.
+pub(crate) fn compile_with(
+    program: &Program,
+    jump_table: &HashMap<usize, usize>,
+) -> (Code, Vec<Cost>) {
+    let mut code: Code = Vec::with_capacity(program.len());
+    let mut costs = Vec::with_capacity(program.len());
+    // the commands folded away since the last instruction
+    let mut pending = 0;
+    let mut open_loops = vec![];
+    let mut unmatched = vec![];
+    let mut i = 0;
+    while i < program.len() {
+        let token = &program[i];
+        let location = token.location;
+        let start = i;
+        let op = match token.value {
+            TokenKind::Increment | TokenKind::Decrement => {
+                let mut delta: u8 = 0;
+                while i < program.len() {
+                    match program[i].value {
+                        TokenKind::Increment => delta = delta.wrapping_add(1),
+                        TokenKind::Decrement => delta = delta.wrapping_sub(1),
+                        _ => break,
+                    }
+                    i += 1;
+                }
+                if delta == 0 {
+                    pending += (i - start) as u64;
+                    continue;
+                }
+                code.push(Instruction {
+                    value: Op::Add(delta),
+                    location,
+                });
+                costs.push(Cost::Fixed(pending + (i - start) as u64));
+                pending = 0;
+                continue;
+            }
+            TokenKind::IncrementPointer | TokenKind::DecrementPointer => {
+                let kind = token.value;
+                let mut count: isize = 0;
+                while i < program.len() && program[i].value == kind {
+                    count += 1;
+                    i += 1;
+                }
+                let offset = if kind == TokenKind::IncrementPointer {
+                    count
+                } else {
+                    -count
+                };
+                code.push(Instruction {
+                    value: Op::Move(offset),
+                    location,
+                });
+                costs.push(Cost::Fixed(pending + count as u64));
+                pending = 0;
+                continue;
+            }
+            TokenKind::Output => Op::Output,
+            TokenKind::Input => Op::Input,
+            TokenKind::Call => Op::Call,
+            TokenKind::SwitchTape => Op::SwitchTape,
+            TokenKind::Transfer => Op::Transfer,
+            TokenKind::End => Op::End,
+            TokenKind::Store => Op::Store,
+            TokenKind::Retrieve => Op::Retrieve,
+            TokenKind::Literal(digit) => Op::Set(digit * 16),
+            // unmatched, so it jumps to the end of the code
+            TokenKind::JumpForward if jump_table[&i] == program.len() => {
+                unmatched.push(code.len());
+                costs.push(Cost::Fixed(pending + 1));
+                pending = 0;
+                i += 1;
+                code.push(Instruction {
+                    value: Op::JumpIfZero(0),
+                    location,
+                });
+                continue;
+            }
+            TokenKind::JumpForward => {
+                let end = jump_table[&i];
+                let bounds = visited_bounds(program, i, end);
+                let replacement = match analysis::classify_loop(program, i, end) {
+                    LoopKind::Clear if bounds == (0, 0) => Some(Op::Clear),
+                    // like `[<>-]`, which still needs the visited cells checked
+                    LoopKind::Clear => Some(Op::MultiplyAdd {
+                        targets: vec![],
+                        bounds,
+                    }),
+                    LoopKind::Scan(step) if bounds == (step.min(0), step.max(0)) => {
+                        Some(Op::Scan(step))
+                    }
+                    LoopKind::Multiply(targets) => Some(Op::MultiplyAdd {
+                        targets: targets
+                            .into_iter()
+                            .map(|(offset, factor)| (offset, factor as u8))
+                            .collect(),
+                        bounds,
+                    }),
+                    _ => None,
+                };
+                match replacement {
+                    Some(op) => {
+                        code.push(Instruction {
+                            value: op,
+                            location,
+                        });
+                        costs.push(Cost::Loop {
+                            fixed: pending,
+                            body: (end - i - 1) as u64,
+                            up: counts_up(program, i, end),
+                        });
+                        pending = 0;
+                        i = end + 1;
+                        continue;
+                    }
+                    None => {
+                        open_loops.push(code.len());
+                        // patched when the matching `]` is compiled
+                        Op::JumpIfZero(0)
+                    }
+                }
+            }
+            TokenKind::JumpBackward if !jump_table.contains_key(&i) => {
+                pending += 1;
+                i += 1;
+                continue;
+            }
+            TokenKind::JumpBackward => {
+                let start = open_loops.pop().expect("brackets are matched");
+                code[start].value = Op::JumpIfZero(code.len());
+                Op::JumpIfNonZero(start)
+            }
+        };
+        costs.push(match op {
+            Op::JumpIfZero(_) | Op::JumpIfNonZero(_) => Cost::Jump {
+                fixed: pending + 1,
+                taken: 1,
+            },
+            _ => Cost::Fixed(pending + 1),
+        });
+        pending = 0;
+        code.push(Instruction {
+            value: op,
+            location,
+        });
+        i += 1;
+    }
+    if pending > 0 {
+        // folded away at the very end, with nothing left to charge them to
+        code.push(Instruction {
+            value: Op::Add(0),
+            location: program[program.len() - 1].location,
+        });
+        costs.push(Cost::Fixed(pending));
+    }
+    for start in unmatched {
+        code[start].value = Op::JumpIfZero(code.len());
+    }
+    (code, costs)
+}
+
+#[test]
+fn test_compile() {
+    use crate::lexer::Lexer;
+    let code = compile(&Lexer::lex("+++--[-]>><[->++<<+>]<[<]+[>,.<-]")).unwrap();
+    let ops: Vec<Op> = code.into_iter().map(|i| i.value).collect();
+    assert_eq!(
+        ops,
+        vec![
+            Op::Add(1),
+            Op::Clear,
+            Op::Move(2),
+            Op::Move(-1),
+            Op::MultiplyAdd {
+                targets: vec![(-1, 1), (1, 2)],
+                bounds: (-1, 1)
+            },
+            Op::Move(-1),
+            Op::Scan(-1),
+            Op::Add(1),
+            Op::JumpIfZero(14),
+            Op::Move(1),
+            Op::Input,
+            Op::Output,
+            Op::Move(-1),
+            Op::Add(255),
+            Op::JumpIfNonZero(8),
+        ]
+    );
+}
@@ -0,0 +1,302 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{Buffering, EofBehavior, InterpreterError, InterpreterErrorKind};
+use crate::lexer::Program;
+use crate::spec::Capture;
+use std::io::{self, Read, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+// Chunks in flight between two stages. A stage blocks once the next one is
+// this far behind.
+const PIPE_CAPACITY: usize = 16;
+
+// The sending end of a pipe between two stages.
+pub struct ChannelWriter(SyncSender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the next stage has ended"))?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The receiving end of a pipe. Reading returns EOF once the writer is gone.
+pub struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+pub fn channel() -> (ChannelWriter, ChannelReader) {
+    let (sender, receiver) = mpsc::sync_channel(PIPE_CAPACITY);
+    (
+        ChannelWriter(sender),
+        ChannelReader {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        },
+    )
+}
+
+// Input fed one byte at a time by the host, e.g. with
+// `machine.set_input(Box::new(ReceiverInput(receiver)))`. Reading blocks
+// until a byte arrives, and returns EOF once every sender is dropped.
+pub struct ReceiverInput(pub Receiver<u8>);
+
+impl Read for ReceiverInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.0.recv() {
+            Ok(byte) => buf[0] = byte,
+            Err(_) => return Ok(0),
+        }
+        let mut n = 1;
+        while n < buf.len() {
+            match self.0.try_recv() {
+                Ok(byte) => {
+                    buf[n] = byte;
+                    n += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(n)
+    }
+}
+
+// Output drained one byte at a time by the host. Writing fails once the
+// receiver is dropped.
+pub struct SenderOutput(pub Sender<u8>);
+
+impl Write for SenderOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.0
+                .send(byte)
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the receiver is gone"))?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs the programs concurrently, each on its own thread, with the output of
+// each one streamed to the input of the next. Returns the result of every stage.
+// A stage that fails to write because a later stage has ended is not an error,
+// like a Unix pipeline ignoring SIGPIPE.
+pub fn pipe(
+    programs: &[Program],
+    engine: EngineKind,
+    eof_behavior: EofBehavior,
+    input: Box<dyn Read + Send>,
+    output: Box<dyn Write + Send>,
+    interrupt: Arc<AtomicBool>,
+) -> Vec<Result<usize, InterpreterError>> {
+    // wasm targets cannot spawn threads
+    if cfg!(target_family = "wasm") {
+        return pipe_sequential(programs, engine, eof_behavior, input, output, interrupt);
+    }
+    let last = programs.len().saturating_sub(1);
+    let mut input = Some(input);
+    let mut output = Some(output);
+    let mut handles = Vec::with_capacity(programs.len());
+    for (i, program) in programs.iter().enumerate() {
+        let stage_input = input.take().expect("the input of the stage");
+        let stage_output = if i == last {
+            output.take().expect("the output of the pipeline")
+        } else {
+            let (writer, reader) = channel();
+            input = Some(Box::new(reader));
+            Buffering::Line.wrap(writer)
+        };
+        let program = program.clone();
+        let interrupt = interrupt.clone();
+        handles.push(thread::spawn(move || {
+            run_stage(
+                &program,
+                engine,
+                eof_behavior,
+                stage_input,
+                stage_output,
+                interrupt,
+                i == last,
+            )
+        }));
+    }
+    handles
+        .into_iter()
+        .map(|handle| handle.join().expect("a pipeline stage panicked"))
+        .collect()
+}
+
+// Runs the stages one after another, each reading the whole output of the
+// previous one from memory. A stage only starts once the one before it has
+// ended, so programs that wait for each other do not work.
+pub fn pipe_sequential(
+    programs: &[Program],
+    engine: EngineKind,
+    eof_behavior: EofBehavior,
+    input: Box<dyn Read + Send>,
+    output: Box<dyn Write + Send>,
+    interrupt: Arc<AtomicBool>,
+) -> Vec<Result<usize, InterpreterError>> {
+    let last = programs.len().saturating_sub(1);
+    let mut input = Some(input);
+    let mut output = Some(output);
+    let mut results = Vec::with_capacity(programs.len());
+    for (i, program) in programs.iter().enumerate() {
+        let stage_input = input.take().expect("the input of the stage");
+        if i == last {
+            let stage_output = output.take().expect("the output of the pipeline");
+            results.push(run_stage(
+                program,
+                engine,
+                eof_behavior,
+                stage_input,
+                stage_output,
+                interrupt.clone(),
+                true,
+            ));
+        } else {
+            let captured = Capture::default();
+            results.push(run_stage(
+                program,
+                engine,
+                eof_behavior,
+                stage_input,
+                Box::new(captured.clone()),
+                interrupt.clone(),
+                false,
+            ));
+            let bytes = captured.0.lock().unwrap().clone();
+            input = Some(Box::new(io::Cursor::new(bytes)));
+        }
+    }
+    results
+}
+
+fn run_stage(
+    program: &Program,
+    engine: EngineKind,
+    eof_behavior: EofBehavior,
+    input: Box<dyn Read + Send>,
+    output: Box<dyn Write + Send>,
+    interrupt: Arc<AtomicBool>,
+    last: bool,
+) -> Result<usize, InterpreterError> {
+    let mut interpreter = engine.create();
+    let machine = interpreter.machine_mut();
+    machine.set_eof_behavior(eof_behavior);
+    machine.set_input(input);
+    machine.set_output(output);
+    machine.set_interrupt_flag(interrupt);
+    let result = interpreter.eval(program);
+    match result {
+        Err(ref e) if !last && e.value == InterpreterErrorKind::OutputError => Ok(0),
+        _ => result,
+    }
+}
+
+#[test]
+fn test_pipe() {
+    use crate::lexer::Lexer;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let run = |sources: &[&str]| {
+        let programs: Vec<Program> = sources.iter().map(|s| Lexer::lex(s)).collect();
+        let buffer = SharedBuffer::default();
+        let results = pipe_sequential(
+            &programs,
+            EngineKind::Simple,
+            EofBehavior::Zero,
+            Box::new(io::Cursor::new(b"HAL".to_vec())),
+            Box::new(buffer.clone()),
+            Arc::new(AtomicBool::new(false)),
+        );
+        assert!(results.iter().all(Result::is_ok));
+        let sequential = buffer.0.lock().unwrap().clone();
+        let buffer = SharedBuffer::default();
+        let results = pipe(
+            &programs,
+            EngineKind::Simple,
+            EofBehavior::Zero,
+            Box::new(io::Cursor::new(b"HAL".to_vec())),
+            Box::new(buffer.clone()),
+            Arc::new(AtomicBool::new(false)),
+        );
+        assert!(results.iter().all(Result::is_ok));
+        let output = buffer.0.lock().unwrap().clone();
+        assert_eq!(output, sequential);
+        output
+    };
+    // cat, then add one to each byte
+    assert_eq!(run(&[",[.,]", ",[+.,]"]), b"IBM");
+    // the last stage stops reading after two bytes
+    assert_eq!(run(&[",[.,]", ",[+.,]", ",.,."]), b"IB");
+}
+
+#[test]
+fn test_channel_io() {
+    use crate::interpreter::SimpleInterpreter;
+    use crate::lexer::Lexer;
+
+    let (input, input_receiver) = mpsc::channel();
+    let (output_sender, output) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut machine = SimpleInterpreter::new();
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine.set_input(Box::new(ReceiverInput(input_receiver)));
+        machine.set_output(Box::new(SenderOutput(output_sender)));
+        machine.eval(&Lexer::lex(",[+.,]"))
+    });
+    // each byte comes back before the next one is sent
+    for &byte in b"HAL" {
+        input.send(byte).unwrap();
+        assert_eq!(output.recv(), Ok(byte + 1));
+    }
+    drop(input);
+    assert_eq!(handle.join().unwrap(), Ok(0));
+    assert!(output.recv().is_err());
+}
@@ -0,0 +1,215 @@
+use crate::escape::escape;
+use crate::interpreter::{InterpreterError, SimpleInterpreter};
+use crate::json::Json;
+use crate::spec::Capture;
+use std::time::Duration;
+
+// Commands of the program shown around the current one.
+const PROGRAM_WIDTH: usize = 60;
+
+// Draws a run as it goes, like an animation of the debugger: each frame
+// shows the commands around the current one, a window of the tape around
+// the pointer, and the end of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Playback {
+    pub fps: u32,
+    // commands run between two frames
+    pub steps_per_frame: u64,
+    // cells shown
+    pub cells: usize,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            steps_per_frame: 1,
+            cells: 12,
+        }
+    }
+}
+
+impl Playback {
+    // Runs the rest of the program loaded on `machine`, calling `show` with
+    // each frame and when to show it from the start, the first one before
+    // any command and the last one at the end of the run, even on an error.
+    // Returns what the program wrote, which it also writes to its output.
+    pub fn play<F>(
+        &self,
+        machine: &mut SimpleInterpreter,
+        mut show: F,
+    ) -> Result<Vec<u8>, InterpreterError>
+    where
+        F: FnMut(Duration, &str),
+    {
+        let output = Capture::default();
+        machine.add_output(Box::new(output.clone()));
+        let mut frames = 0;
+        loop {
+            let written = output.0.lock().unwrap().clone();
+            show(
+                Duration::from_secs(frames) / self.fps.max(1),
+                &self.frame(machine, &written),
+            );
+            frames += 1;
+            if machine.next_command().is_none() {
+                break Ok(written);
+            }
+            if let Err(e) =
+                (0..self.steps_per_frame.max(1)).try_for_each(|_| machine.step().map(|_| ()))
+            {
+                let written = output.0.lock().unwrap().clone();
+                show(
+                    Duration::from_secs(frames) / self.fps.max(1),
+                    &self.frame(machine, &written),
+                );
+                break Err(e);
+            }
+        }
+    }
+
+    // The picture of the machine with `written` as its output so far.
+    pub fn frame(&self, machine: &SimpleInterpreter, written: &[u8]) -> String {
+        let mut lines = Vec::new();
+        let cursor = machine.program_cursor;
+        lines.push(match machine.next_location() {
+            Some(location) => format!(
+                "step {}  line {}, col {}",
+                machine.steps(),
+                location.line,
+                location.col
+            ),
+            None => format!("step {}  done", machine.steps()),
+        });
+
+        let start = cursor.saturating_sub(PROGRAM_WIDTH / 2);
+        let end = (start + PROGRAM_WIDTH).min(machine.program.len());
+        let commands: String = machine.program[start..end]
+            .iter()
+            .map(|token| token.value.to_string())
+            .collect();
+        lines.push(commands);
+        lines.push(format!("{}^", " ".repeat(cursor - start)));
+
+        let pointer = machine.pointer();
+        let first = pointer.saturating_sub(self.cells / 2);
+        let (mut values, mut indexes) = (String::new(), String::new());
+        for index in first..first + self.cells {
+            let value = machine.cells().get(index).copied().unwrap_or(0);
+            if index == pointer {
+                values.push_str(&format!("[{:>3}]", value));
+            } else {
+                values.push_str(&format!(" {:>3} ", value));
+            }
+            indexes.push_str(&format!("{:^5}", index));
+        }
+        lines.push(values.trim_end().to_string());
+        lines.push(indexes.trim_end().to_string());
+
+        // the last line written, which ends at the end of the screen
+        let last = written[..written.len().saturating_sub(1)]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(written, |newline| &written[newline + 1..]);
+        let shown = escape(last);
+        let skip = shown.chars().count().saturating_sub(PROGRAM_WIDTH);
+        lines.push(format!(
+            "output: {}",
+            shown.chars().skip(skip).collect::<String>()
+        ));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+// Frames kept as an asciinema cast, version 2, to embed a playback in a
+// page or replay it with `asciinema play`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cast {
+    events: Vec<(Duration, String)>,
+}
+
+impl Cast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a frame of Playback::play to show at `time`.
+    pub fn push(&mut self, time: Duration, frame: &str) {
+        self.events.push((time, frame.to_string()));
+    }
+
+    // The cast file: a header sized for the largest frame, then each frame
+    // drawn on a cleared screen, one JSON line each.
+    pub fn to_cast(&self) -> String {
+        let width = self
+            .events
+            .iter()
+            .flat_map(|(_, frame)| frame.lines())
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = self
+            .events
+            .iter()
+            .map(|(_, frame)| frame.lines().count())
+            .max()
+            .unwrap_or(0);
+        let header = Json::object(vec![
+            ("version", 2usize.into()),
+            ("width", width.max(1).into()),
+            ("height", height.max(1).into()),
+        ]);
+        let mut cast = format!("{}\n", header);
+        for (time, frame) in &self.events {
+            // a terminal needs the carriage returns
+            let text = format!("\x1b[H\x1b[2J{}", frame.replace('\n', "\r\n"));
+            let event = Json::Array(vec![time.as_secs_f64().into(), "o".into(), text.into()]);
+            cast.push_str(&format!("{}\n", event));
+        }
+        cast
+    }
+}
+
+#[test]
+fn test_playback() {
+    use crate::lexer::Lexer;
+    let mut machine = SimpleInterpreter::new();
+    machine.set_output(Box::new(std::io::sink()));
+    machine.load(&Lexer::lex("++[>+++<-]>.")).unwrap();
+    let mut frames = vec![];
+    let playback = Playback {
+        fps: 4,
+        steps_per_frame: 2,
+        cells: 3,
+    };
+    let output = playback
+        .play(&mut machine, |time, frame| {
+            frames.push((time, frame.to_string()))
+        })
+        .unwrap();
+    assert_eq!(output, [6]);
+    // 20 steps, two a frame, and the first frame
+    assert_eq!(frames.len(), 11);
+    assert_eq!(frames[1].0, Duration::from_millis(250));
+    assert_eq!(
+        frames[1].1,
+        "step 2  line 1, col 3\n++[>+++<-]>.\n  ^\n[  2]   0    0\n  0    1    2\noutput: \n"
+    );
+    assert_eq!(
+        frames[10].1,
+        "step 20  done\n++[>+++<-]>.\n            ^\n   0 [  6]   0\n  0    1    2\noutput: \\x06\n"
+    );
+
+    let mut cast = Cast::new();
+    for (time, frame) in &frames[..2] {
+        cast.push(*time, frame);
+    }
+    let cast = cast.to_cast();
+    let lines: Vec<&str> = cast.lines().collect();
+    assert_eq!(lines[0], r#"{"version":2,"width":21,"height":6}"#);
+    assert_eq!(
+        lines[2],
+        r#"[0.25,"o","\u001b[H\u001b[2Jstep 2  line 1, col 3\r\n++[>+++<-]>.\r\n  ^\r\n[  2]   0    0\r\n  0    1    2\r\noutput: \r\n"]"#
+    );
+}
@@ -0,0 +1,157 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::json::Json;
+use crate::lexer::{Lexer, Location};
+use crate::spec::Capture;
+use std::error::Error;
+use std::fmt;
+
+// The surface for embedding the interpreter in a web page: plain strings,
+// bytes and numbers in and out, no terminal or files, and a step limit so a
+// runaway program cannot hang the page. It has no wasm-bindgen exports of
+// its own, as the crate has no wasm-bindgen feature.
+pub const STEP_LIMIT: u64 = 100_000_000;
+
+// An error with the output produced before it, for showing both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaygroundError {
+    pub kind: InterpreterErrorKind,
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+    pub output: Vec<u8>,
+}
+
+impl PlaygroundError {
+    fn new(e: InterpreterError, output: &Capture) -> Self {
+        Self {
+            kind: e.value,
+            message: e.value.to_string(),
+            line: e.location.line,
+            col: e.location.col,
+            output: output.0.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("kind", self.kind_name().into()),
+            ("message", self.message.clone().into()),
+            ("line", self.line.into()),
+            ("col", self.col.into()),
+            (
+                "output",
+                String::from_utf8_lossy(&self.output).into_owned().into(),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for PlaygroundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.line, self.col)
+    }
+}
+
+impl Error for PlaygroundError {}
+
+fn machine(input: &[u8], output: &Capture) -> SimpleInterpreter {
+    let mut machine = SimpleInterpreter::new();
+    machine.set_output(Box::new(output.clone()));
+    machine.set_input_bytes(input.iter().copied());
+    machine.set_eof_behavior(EofBehavior::Zero);
+    machine.set_step_limit(Some(STEP_LIMIT));
+    machine
+}
+
+// Runs a program to the end and returns its output. `,` reads 0 after the input.
+pub fn run(source: &str, input: &[u8]) -> Result<Vec<u8>, PlaygroundError> {
+    let output = Capture::default();
+    let mut interpreter = EngineKind::Auto.create();
+    *interpreter.machine_mut() = machine(input, &output);
+    match interpreter.eval(&Lexer::lex(source)) {
+        Ok(_) => Ok(output.0.lock().unwrap().clone()),
+        Err(e) => Err(PlaygroundError::new(e, &output)),
+    }
+}
+
+// Runs a program one command at a time on the simple engine, for animating
+// the tape.
+pub struct Stepper {
+    machine: SimpleInterpreter,
+    output: Capture,
+}
+
+impl Stepper {
+    // Fails on unmatched brackets.
+    pub fn new(source: &str, input: &[u8]) -> Result<Self, PlaygroundError> {
+        let output = Capture::default();
+        let mut machine = machine(input, &output);
+        machine
+            .load(&Lexer::lex(source))
+            .map_err(|e| PlaygroundError::new(e, &output))?;
+        Ok(Self { machine, output })
+    }
+
+    // Returns false once the program has ended.
+    pub fn step(&mut self) -> Result<bool, PlaygroundError> {
+        self.machine
+            .step()
+            .map_err(|e| PlaygroundError::new(e, &self.output))
+    }
+
+    // Runs at most `count` commands, for advancing several per animation frame.
+    pub fn run(&mut self, count: u64) -> Result<bool, PlaygroundError> {
+        for _ in 0..count {
+            if !self.step()? {
+                return Ok(false);
+            }
+        }
+        Ok(self.location().is_some())
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        self.output.0.lock().unwrap().clone()
+    }
+    pub fn cells(&self) -> &[u8] {
+        self.machine.cells()
+    }
+    pub fn pointer(&self) -> usize {
+        self.machine.pointer()
+    }
+    pub fn steps(&self) -> u64 {
+        self.machine.steps()
+    }
+    // The next command, or None at the end.
+    pub fn location(&self) -> Option<Location> {
+        self.machine.next_location()
+    }
+}
+
+#[test]
+fn test_playground() {
+    assert_eq!(run(",[.,]", b"hi"), Ok(b"hi".to_vec()));
+    let e = run("+.<", b"").unwrap_err();
+    assert_eq!((e.kind_name(), e.line, e.col), ("pointer", 1, 3));
+    assert_eq!(e.output, vec![1]);
+    assert_eq!(e.to_string(), "pointer moved below cell 0 at 1:3");
+    assert_eq!(
+        e.to_json().to_string(),
+        r#"{"kind":"pointer","message":"pointer moved below cell 0","line":1,"col":3,"output":"\u0001"}"#
+    );
+    assert!(Stepper::new("[", b"").is_err());
+
+    let mut stepper = Stepper::new("++>,.", b"a").unwrap();
+    assert_eq!(stepper.step(), Ok(true));
+    assert_eq!((stepper.cells(), stepper.pointer()), (&[1][..], 0));
+    assert_eq!(stepper.location().map(|l| l.col), Some(2));
+    assert_eq!(stepper.run(2), Ok(true));
+    assert_eq!(stepper.run(10), Ok(false));
+    assert_eq!(stepper.output(), b"a");
+    assert_eq!(stepper.steps(), 5);
+    assert_eq!(stepper.location(), None);
+}
@@ -0,0 +1,46 @@
+// The types most programs using the crate need: `use brainfuck::prelude::*;`
+pub use crate::engine::{Engine, EngineKind};
+pub use crate::input::InputSource;
+pub use crate::interpreter::{
+    EofBehavior, InterpreterError, InterpreterErrorKind, SimpleInterpreter,
+};
+pub use crate::lexer::{Fingerprint, Lexer, Location, Program, Token, TokenKind};
+
+#[test]
+fn test_prelude() {
+    use crate::prelude::*;
+
+    // counts down from 3
+    struct Countdown(u8);
+    impl InputSource for Countdown {
+        fn next_byte(&mut self) -> Option<u8> {
+            self.0 = self.0.checked_sub(1)?;
+            Some(self.0 + 1)
+        }
+    }
+
+    let program: Program = Lexer::lex(">,[>,]<[<]>[.>]");
+    for &kind in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let mut engine: Box<dyn Engine> = kind.create();
+        let machine: &mut SimpleInterpreter = engine.machine_mut();
+        machine.set_input_source(Box::new(Countdown(3)));
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine.set_output(Box::new(std::io::sink()));
+        assert_eq!(engine.eval(&program), Ok(0), "{:?}", kind);
+        assert_eq!(engine.machine().cells(), &[0, 3, 2, 1, 0]);
+    }
+
+    let error: InterpreterError = EngineKind::Auto
+        .create()
+        .eval(&Lexer::lex("<"))
+        .unwrap_err();
+    assert_eq!(error.value, InterpreterErrorKind::PointerError);
+    let location: Location = error.location;
+    assert_eq!((location.line, location.col), (1, 1));
+    let first: &Token = &program[0];
+    assert_eq!(first.value, TokenKind::IncrementPointer);
+    assert_eq!(
+        program.fingerprint(),
+        Lexer::lex("> ,[>,] <[<] >[.>]").fingerprint()
+    );
+}
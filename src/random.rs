@@ -0,0 +1,55 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A small xorshift64* generator. The same seed always gives the same numbers,
+// which keeps fuzzing runs reproducible. Not suitable for cryptography.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64, so that close seeds give unrelated states
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // the state must never be zero
+        Self { state: z.max(1) }
+    }
+
+    // Seeds from the clock.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // A number in 0..bound. `bound` must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+#[test]
+fn test_rng() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    let first: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+    assert_eq!(first, (0..4).map(|_| b.next_u64()).collect::<Vec<u64>>());
+    assert_ne!(Rng::new(4).next_u64(), Rng::new(5).next_u64());
+    assert!((0..100).all(|_| a.below(7) < 7));
+}
@@ -0,0 +1,261 @@
+use crate::dump::{self, TapeFormat};
+use crate::interpreter::{InterpreterError, InterpreterErrorKind, SimpleInterpreter};
+use crate::lexer::{Lexer, Program, TokenKind};
+use crate::line_editor::LineEditor;
+use crate::signal;
+use crate::snapshot::Snapshot;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "bf> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HELP: &str = ":dump [hex|dec|char]  show the tape
+:pointer              show the pointer and the current cell
+:reset                clear the tape and the pointer
+:load FILE            run a brainfuck file on the current tape
+:save FILE            save the tape and the pointer
+:restore FILE         restore the tape and the pointer saved by :save
+:help                 show this help
+:quit                 leave the REPL";
+
+// Runs each entered line against the same tape and pointer. Lines with
+// unclosed loops are buffered until the loop is closed.
+pub struct Repl {
+    interpreter: SimpleInterpreter,
+    pending: String,
+    pending_line: usize,
+    line: usize,
+    quit: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Self::fresh_interpreter(),
+            pending: String::new(),
+            pending_line: 1,
+            line: 0,
+            quit: false,
+        }
+    }
+
+    fn fresh_interpreter() -> SimpleInterpreter {
+        let mut interpreter = SimpleInterpreter::new();
+        // nothing is evaluated yet, this only initializes the tape
+        let _ = interpreter.eval(&Program::new());
+        interpreter
+    }
+
+    pub fn interpreter(&self) -> &SimpleInterpreter {
+        &self.interpreter
+    }
+
+    fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        while !self.quit {
+            write!(writer, "{}", self.prompt())?;
+            writer.flush()?;
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                writeln!(writer)?;
+                return Ok(());
+            }
+            if let Some(message) = self.eval_line(&line) {
+                writeln!(writer, "{}", message)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Like run, on the terminal, with the lines edited by a LineEditor.
+    pub fn run_terminal(&mut self) -> io::Result<()> {
+        let mut editor = LineEditor::new();
+        while !self.quit {
+            let line = match editor.read_terminal_line(self.prompt())? {
+                Some(line) => line,
+                None => return Ok(()),
+            };
+            if let Some(message) = self.eval_line(&line) {
+                println!("{}", message);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns a message to show to the user, if any.
+    pub fn eval_line(&mut self, line: &str) -> Option<String> {
+        self.line += 1;
+        if self.pending.is_empty() {
+            if line.trim_start().starts_with(':') {
+                return self.eval_meta_command(line.trim());
+            }
+            self.pending_line = self.line;
+        }
+        self.pending.push_str(line);
+        if !self.pending.ends_with('\n') {
+            self.pending.push('\n');
+        }
+
+        let mut program = Lexer::lex(&self.pending);
+        let mut depth: isize = 0;
+        for token in program.iter() {
+            match token.value {
+                TokenKind::JumpForward => depth += 1,
+                TokenKind::JumpBackward => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth > 0 {
+            return None;
+        }
+        self.pending.clear();
+        for token in program.iter_mut() {
+            token.location.line += self.pending_line - 1;
+        }
+
+        self.eval_program(&program)
+    }
+
+    fn eval_program(&mut self, program: &Program) -> Option<String> {
+        let flag = signal::install_interrupt_handler();
+        self.interpreter.set_interrupt_flag(flag);
+        let result = self.interpreter.eval_append(program);
+        let _ = io::stdout().flush();
+        let printed = program.iter().any(|t| t.value == TokenKind::Output);
+        Self::report(result, printed)
+    }
+
+    fn report(result: Result<usize, InterpreterError>, printed: bool) -> Option<String> {
+        let separator = if printed { "\n" } else { "" };
+        match result {
+            Ok(_) if printed => Some(String::new()),
+            Ok(_) => None,
+            Err(e) if e.value == InterpreterErrorKind::Interrupted => Some(format!(
+                "{}interrupted at line {}, col {}",
+                separator, e.location.line, e.location.col
+            )),
+            Err(e) => Some(format!(
+                "{}Error: {} at line {}, col {}",
+                separator, e.value, e.location.line, e.location.col
+            )),
+        }
+    }
+
+    fn eval_meta_command(&mut self, command: &str) -> Option<String> {
+        let mut parts = command.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("");
+        let argument = parts.next().map(str::trim).unwrap_or("");
+        match name {
+            ":dump" => {
+                let format = if argument.is_empty() {
+                    TapeFormat::Hex
+                } else {
+                    match argument.parse::<TapeFormat>() {
+                        Ok(format) => format,
+                        Err(e) => return Some(format!("Error: {}", e)),
+                    }
+                };
+                let dumped = dump::dump_tape(
+                    self.interpreter.cells(),
+                    self.interpreter.pointer(),
+                    format,
+                    (None, None),
+                );
+                Some(dumped.trim_end().to_string())
+            }
+            ":pointer" => {
+                let pointer = self.interpreter.pointer();
+                Some(format!(
+                    "pointer: {}, cell: {}",
+                    pointer,
+                    self.interpreter.cells()[pointer]
+                ))
+            }
+            ":reset" => {
+                self.interpreter = Self::fresh_interpreter();
+                None
+            }
+            ":load" => {
+                let source = match fs::read_to_string(argument) {
+                    Ok(source) => source,
+                    Err(e) => return Some(format!("Error: could not read {}: {}", argument, e)),
+                };
+                self.eval_program(&Lexer::lex(&source))
+            }
+            ":save" => match self.interpreter.snapshot().save(argument) {
+                Ok(()) => Some(format!("saved to {}", argument)),
+                Err(e) => Some(format!("Error: could not save {}: {}", argument, e)),
+            },
+            ":restore" => match Snapshot::load(argument) {
+                Ok(snapshot) => {
+                    self.interpreter.restore(&snapshot);
+                    None
+                }
+                Err(e) => Some(format!("Error: could not restore {}: {}", argument, e)),
+            },
+            ":help" => Some(HELP.to_string()),
+            ":quit" | ":q" => {
+                self.quit = true;
+                None
+            }
+            _ => Some(format!("unknown command: {} (try :help)", name)),
+        }
+    }
+}
+
+#[test]
+fn test_meta_commands() {
+    let mut repl = Repl::new();
+    repl.eval_line("++>+++");
+    assert_eq!(
+        repl.eval_line(":pointer"),
+        Some("pointer: 1, cell: 3".to_string())
+    );
+    assert_eq!(
+        repl.eval_line(":dump dec"),
+        Some("pointer: 1\n000000:   2 [  3]".to_string())
+    );
+    let path = std::env::temp_dir().join("brainfuck_test_meta_commands.bfstate");
+    let path = path.to_str().unwrap();
+    repl.eval_line(&format!(":save {}", path));
+    repl.eval_line(":reset");
+    assert_eq!(repl.interpreter().cells(), &[0]);
+    repl.eval_line(&format!(":restore {}", path));
+    assert_eq!(repl.interpreter().cells(), &[2, 3]);
+    let _ = fs::remove_file(path);
+    assert!(repl
+        .eval_line(":nope")
+        .unwrap()
+        .starts_with("unknown command"));
+}
+
+#[test]
+fn test_repl() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval_line("+++>"), None);
+    assert_eq!(repl.eval_line("++[-<"), None);
+    assert_eq!(repl.eval_line("+>]"), None);
+    assert_eq!(repl.interpreter().cells(), &[5, 0]);
+    assert_eq!(
+        repl.eval_line("<<"),
+        Some("Error: pointer moved below cell 0 at line 4, col 2".to_string())
+    );
+    assert_eq!(
+        repl.eval_line("]"),
+        Some("Error: unmatched ']' at line 5, col 1".to_string())
+    );
+    assert_eq!(repl.interpreter().pointer(), 0);
+}
@@ -0,0 +1,94 @@
+use crate::interpreter::{InterpreterError, SimpleInterpreter};
+use crate::lexer::{Program, TokenKind};
+use crate::spec::Capture;
+use std::collections::VecDeque;
+
+// Why run_until_input_needed returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    // `,` is next and there is no input for it; provide some, or end the
+    // input, and run again
+    NeedsInput,
+    Finished,
+}
+
+// A program that stops instead of blocking when it reads, so that a GUI
+// event loop or an async server can drive an interactive program on its own
+// thread. Output is kept until taken.
+pub struct Resumable {
+    machine: SimpleInterpreter,
+    output: Capture,
+    input: VecDeque<u8>,
+    input_ended: bool,
+}
+
+impl Resumable {
+    // Fails on unmatched brackets.
+    pub fn new(program: &Program) -> Result<Self, InterpreterError> {
+        let output = Capture::default();
+        let mut machine = SimpleInterpreter::new();
+        machine.set_output(Box::new(output.clone()));
+        machine.load(program)?;
+        Ok(Self {
+            machine,
+            output,
+            input: VecDeque::new(),
+            input_ended: false,
+        })
+    }
+
+    // For the EOF behavior, the step limit, the tape...
+    pub fn machine(&self) -> &SimpleInterpreter {
+        &self.machine
+    }
+    pub fn machine_mut(&mut self) -> &mut SimpleInterpreter {
+        &mut self.machine
+    }
+
+    pub fn provide_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+    // Once the bytes provided are used up, `,` gets the EOF value instead of
+    // stopping.
+    pub fn end_input(&mut self) {
+        self.input_ended = true;
+    }
+
+    // The output written since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.0.lock().unwrap())
+    }
+
+    pub fn run_until_input_needed(&mut self) -> Result<RunState, InterpreterError> {
+        while let Some(command) = self.machine.next_command() {
+            if command == TokenKind::Input {
+                match self.input.pop_front() {
+                    Some(byte) => self.machine.set_input_bytes(Some(byte)),
+                    None if self.input_ended => self.machine.set_input_bytes(None),
+                    None => return Ok(RunState::NeedsInput),
+                }
+            }
+            self.machine.step()?;
+        }
+        Ok(RunState::Finished)
+    }
+}
+
+#[test]
+fn test_resumable() {
+    use crate::interpreter::EofBehavior;
+    use crate::lexer::Lexer;
+
+    let mut program = Resumable::new(&Lexer::lex(">+++++++[<++++++>-]<.,[+.,]")).unwrap();
+    program.machine_mut().set_eof_behavior(EofBehavior::Zero);
+    assert_eq!(program.run_until_input_needed(), Ok(RunState::NeedsInput));
+    assert_eq!(program.take_output(), b"*");
+    program.provide_input(b"HA");
+    assert_eq!(program.run_until_input_needed(), Ok(RunState::NeedsInput));
+    assert_eq!(program.take_output(), b"IB");
+    program.provide_input(b"L");
+    program.end_input();
+    assert_eq!(program.run_until_input_needed(), Ok(RunState::Finished));
+    assert_eq!(program.take_output(), b"M");
+    assert!(Resumable::new(&Lexer::lex("[")).is_err());
+}
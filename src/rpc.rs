@@ -0,0 +1,347 @@
+use crate::interpreter::{EofBehavior, SimpleInterpreter};
+use crate::json::Json;
+use crate::lexer::{Lexer, Location};
+use crate::lsp::{read_message, write_message};
+use crate::snapshot::{Snapshot, TapeDiff};
+use crate::spec::Capture;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+// `continue` stops after this many steps unless told otherwise
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+// Drives one program over JSON-RPC 2.0, framed like the language server
+// with Content-Length headers:
+//
+//     load            {source, input?}        -> state
+//     step            {count?}                -> state
+//     continue        {maxSteps?}             -> state
+//     setBreakpoints  {breakpoints: [{line, col}]}
+//     snapshot                                -> {cells, pointer}
+//     restore         {cells, pointer}        -> state
+//
+// A state tells where the run is, why it stopped, what it wrote and which
+// cells changed since the last state.
+pub struct DebugServer {
+    session: Option<Session>,
+    breakpoints: HashSet<(usize, usize)>,
+}
+
+struct Session {
+    machine: SimpleInterpreter,
+    output: Capture,
+    // set once the program fails; it cannot go on afterwards
+    error: Option<Json>,
+    // the tape when the last state was taken
+    last: Snapshot,
+}
+
+impl Default for DebugServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn invalid_params(message: &str) -> (i32, String) {
+    (INVALID_PARAMS, message.to_string())
+}
+
+fn location_json(location: Location) -> Json {
+    Json::object(vec![
+        ("line", location.line.into()),
+        ("col", location.col.into()),
+    ])
+}
+
+impl Session {
+    fn state(&mut self, reason: &str) -> Json {
+        let snapshot = self.machine.snapshot();
+        let diff = TapeDiff::between(&self.last, &snapshot);
+        self.last = snapshot;
+        let changes = diff
+            .cells
+            .iter()
+            .map(|&(cell, before, after)| {
+                Json::object(vec![
+                    ("cell", cell.into()),
+                    ("before", usize::from(before).into()),
+                    ("after", usize::from(after).into()),
+                ])
+            })
+            .collect();
+        let output = self.output.0.lock().unwrap();
+        Json::object(vec![
+            ("reason", reason.into()),
+            (
+                "location",
+                self.machine
+                    .next_location()
+                    .map_or(Json::Null, location_json),
+            ),
+            ("pointer", self.machine.pointer().into()),
+            ("steps", self.machine.steps().into()),
+            (
+                "output",
+                String::from_utf8_lossy(&output).into_owned().into(),
+            ),
+            ("error", self.error.clone().unwrap_or(Json::Null)),
+            ("changes", Json::Array(changes)),
+        ])
+    }
+
+    // Steps once. Returns the reason to stop, if any.
+    fn step(&mut self) -> Option<&'static str> {
+        if self.error.is_some() {
+            return Some("error");
+        }
+        match self.machine.step() {
+            Ok(true) => None,
+            Ok(false) => Some("end"),
+            Err(e) => {
+                self.error = Some(Json::object(vec![
+                    ("kind", e.value.name().into()),
+                    ("message", e.value.to_string().into()),
+                    ("location", location_json(e.location)),
+                ]));
+                Some("error")
+            }
+        }
+    }
+}
+
+impl DebugServer {
+    pub fn new() -> Self {
+        Self {
+            session: None,
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    // Serves until the reader ends.
+    pub fn run<R: BufRead, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        while let Some(body) = read_message(&mut reader)? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            if let Some(response) = self.handle(&message) {
+                write_message(&mut writer, &response)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the response, or None for a notification.
+    pub fn handle(&mut self, message: &Json) -> Option<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let result = match method {
+            "load" => self.load(&params),
+            "step" => self.step(&params),
+            "continue" => self.resume(&params),
+            "setBreakpoints" => self.set_breakpoints(&params),
+            "snapshot" => self.snapshot(),
+            "restore" => self.restore(&params),
+            _ => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+        };
+        let id = message.get("id").cloned()?;
+        Some(match result {
+            Ok(result) => Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err((code, message)) => Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("id", id),
+                (
+                    "error",
+                    Json::object(vec![
+                        ("code", Json::Number(f64::from(code))),
+                        ("message", Json::from(message)),
+                    ]),
+                ),
+            ]),
+        })
+    }
+
+    fn session(&mut self) -> Result<&mut Session, (i32, String)> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| invalid_params("no program is loaded"))
+    }
+
+    fn load(&mut self, params: &Json) -> Result<Json, (i32, String)> {
+        let source = params
+            .get("source")
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid_params("`source` must be a string"))?;
+        let input = params.get("input").and_then(Json::as_str).unwrap_or("");
+        let output = Capture::default();
+        let mut machine = SimpleInterpreter::new();
+        machine.set_output(Box::new(output.clone()));
+        machine.set_input_bytes(input.bytes());
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine
+            .load(&Lexer::lex(source))
+            .map_err(|e| invalid_params(&format!("{} at {}", e.value, e.location)))?;
+        let last = machine.snapshot();
+        let mut session = Session {
+            machine,
+            output,
+            error: None,
+            last,
+        };
+        let state = session.state("loaded");
+        self.session = Some(session);
+        Ok(state)
+    }
+
+    fn step(&mut self, params: &Json) -> Result<Json, (i32, String)> {
+        let count = params.get("count").and_then(Json::as_u64).unwrap_or(1);
+        let session = self.session()?;
+        for _ in 0..count {
+            if let Some(reason) = session.step() {
+                return Ok(session.state(reason));
+            }
+        }
+        Ok(session.state("step"))
+    }
+
+    // Runs until a breakpoint, the end, an error or `maxSteps` steps.
+    fn resume(&mut self, params: &Json) -> Result<Json, (i32, String)> {
+        let max_steps = params
+            .get("maxSteps")
+            .and_then(Json::as_u64)
+            .unwrap_or(DEFAULT_MAX_STEPS);
+        let breakpoints = self.breakpoints.clone();
+        let session = self.session()?;
+        for _ in 0..max_steps {
+            if let Some(reason) = session.step() {
+                return Ok(session.state(reason));
+            }
+            let at_breakpoint = session
+                .machine
+                .next_location()
+                .is_some_and(|l| breakpoints.contains(&(l.line, l.col)));
+            if at_breakpoint {
+                return Ok(session.state("breakpoint"));
+            }
+        }
+        Ok(session.state("limit"))
+    }
+
+    fn set_breakpoints(&mut self, params: &Json) -> Result<Json, (i32, String)> {
+        let breakpoints = params
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid_params("`breakpoints` must be an array"))?;
+        self.breakpoints = breakpoints
+            .iter()
+            .map(|b| {
+                let line = b.get("line").and_then(Json::as_u64);
+                let col = b.get("col").and_then(Json::as_u64);
+                match (line, col) {
+                    (Some(line), Some(col)) => Ok((line as usize, col as usize)),
+                    _ => Err(invalid_params("a breakpoint needs `line` and `col`")),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Json::Null)
+    }
+
+    fn snapshot(&mut self) -> Result<Json, (i32, String)> {
+        let snapshot = self.session()?.machine.snapshot();
+        Ok(Json::object(vec![
+            (
+                "cells",
+                Json::Array(
+                    snapshot
+                        .cells
+                        .iter()
+                        .map(|&c| Json::from(c as usize))
+                        .collect(),
+                ),
+            ),
+            ("pointer", snapshot.pointer.into()),
+        ]))
+    }
+
+    fn restore(&mut self, params: &Json) -> Result<Json, (i32, String)> {
+        let cells = params
+            .get("cells")
+            .and_then(Json::as_array)
+            .and_then(|cells| {
+                cells
+                    .iter()
+                    .map(|c| c.as_u64().filter(|&c| c < 256).map(|c| c as u8))
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .ok_or_else(|| invalid_params("`cells` must be an array of bytes"))?;
+        let pointer = params
+            .get("pointer")
+            .and_then(Json::as_u64)
+            .map(|p| p as usize)
+            .filter(|&p| p < cells.len())
+            .ok_or_else(|| invalid_params("`pointer` must point into `cells`"))?;
+        let session = self.session()?;
+        session.machine.restore(&Snapshot { cells, pointer });
+        Ok(session.state("restored"))
+    }
+}
+
+#[test]
+fn test_debug_server() {
+    let mut server = DebugServer::new();
+    let mut call = |method: &str, params: &str| {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":{}}}"#,
+            method, params
+        );
+        let response = server.handle(&Json::parse(&request).unwrap()).unwrap();
+        response
+            .get("result")
+            .or_else(|| response.get("error"))
+            .unwrap()
+            .clone()
+    };
+    let reason = |state: &Json| state.get("reason").and_then(Json::as_str).map(String::from);
+
+    assert_eq!(
+        call("step", "{}").get("message"),
+        Some(&Json::from("no program is loaded"))
+    );
+    let state = call("load", r#"{"source":"++\n>,.<<","input":"a"}"#);
+    assert_eq!(reason(&state).as_deref(), Some("loaded"));
+    call("setBreakpoints", r#"{"breakpoints":[{"line":2,"col":4}]}"#);
+    let state = call("continue", "{}");
+    assert_eq!(reason(&state).as_deref(), Some("breakpoint"));
+    assert_eq!(state.get("output"), Some(&Json::from("a")));
+    assert_eq!(
+        state.get("changes").unwrap().to_string(),
+        r#"[{"cell":0,"before":0,"after":2},{"cell":1,"before":0,"after":97}]"#
+    );
+    assert_eq!(
+        call("snapshot", "{}").to_string(),
+        r#"{"cells":[2,97],"pointer":1}"#
+    );
+    call("restore", r#"{"cells":[2,98],"pointer":1}"#);
+    let state = call("step", r#"{"count":2}"#);
+    assert_eq!(reason(&state).as_deref(), Some("error"));
+    assert_eq!(
+        state.get("error").and_then(|e| e.get("kind")),
+        Some(&Json::from("pointer"))
+    );
+
+    call("load", r#"{"source":"+[]"}"#);
+    let state = call("continue", r#"{"maxSteps":10}"#);
+    assert_eq!(reason(&state).as_deref(), Some("limit"));
+    assert_eq!(state.get("steps"), Some(&Json::from(10usize)));
+    assert_eq!(
+        call("frobnicate", "{}").get("code"),
+        Some(&Json::Number(-32601.0))
+    );
+}
@@ -0,0 +1,61 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{InterpreterError, InterpreterErrorKind};
+use crate::lexer::{Lexer, Location};
+use crate::spec::Capture;
+use std::fmt;
+
+// A failed run, with the output written before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub kind: InterpreterErrorKind,
+    pub location: Location,
+    pub output: Vec<u8>,
+}
+
+impl Error {
+    fn new(e: InterpreterError, output: &Capture) -> Self {
+        Self {
+            kind: e.value,
+            location: e.location,
+            output: output.0.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}",
+            self.kind, self.location.line, self.location.col
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Lexes and runs a program with the defaults of the interpreter, and returns
+// its output. There is no step limit; playground::run has one.
+pub fn run(source: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let output = Capture::default();
+    let mut interpreter = EngineKind::Auto.create();
+    let machine = interpreter.machine_mut();
+    machine.set_output(Box::new(output.clone()));
+    machine.set_input_bytes(input.iter().copied());
+    match interpreter.eval(&Lexer::lex(source)) {
+        Ok(_) => Ok(output.0.lock().unwrap().clone()),
+        Err(e) => Err(Error::new(e, &output)),
+    }
+}
+
+#[test]
+fn test_run() {
+    assert_eq!(run(",.,.", b"hi"), Ok(b"hi".to_vec()));
+    // `,` past the input reads a newline, as by default
+    assert_eq!(run(",.", b""), Ok(b"\n".to_vec()));
+    let e = run("+.<", b"").unwrap_err();
+    assert_eq!(e.kind, InterpreterErrorKind::PointerError);
+    assert_eq!((e.location.line, e.location.col), (1, 3));
+    assert_eq!(e.output, [1]);
+    assert_eq!(e.to_string(), "pointer moved below cell 0 at 1:3");
+}
@@ -0,0 +1,202 @@
+use crate::input::InputSource;
+use crate::interpreter::{InterpreterError, SimpleInterpreter};
+use crate::lexer::{Location, Program, TokenKind};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// Commands a program runs before the next one gets its turn.
+pub const DEFAULT_QUANTUM: u64 = 1000;
+
+// The index of a program in its scheduler.
+pub type TaskId = usize;
+
+struct Task {
+    machine: SimpleInterpreter,
+    // of the last command, for reporting a failed flush
+    last: Option<Location>,
+    // set once the program has ended
+    result: Option<Result<(), InterpreterError>>,
+}
+
+// Runs several programs on one thread, taking turns of `quantum` commands
+// each, for simulations of programs talking to each other. Each machine
+// keeps its own I/O; connect two with queue() to route the output of one to
+// the input of the other.
+//
+// A program waiting for input that is not there yet is skipped. When every
+// program left is waiting, they read anyway: an empty queue then reads as
+// the end of the input, and other sources such as stdin block.
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    quantum: u64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUANTUM)
+    }
+}
+
+impl Scheduler {
+    pub fn new(quantum: u64) -> Self {
+        Self {
+            tasks: Vec::new(),
+            quantum: quantum.max(1),
+        }
+    }
+
+    // Loads the program on the machine, which has its I/O set up. Fails on
+    // unmatched brackets.
+    pub fn spawn(
+        &mut self,
+        mut machine: SimpleInterpreter,
+        program: &Program,
+    ) -> Result<TaskId, InterpreterError> {
+        machine.load(program)?;
+        self.tasks.push(Task {
+            machine,
+            last: program.last().map(|t| t.location),
+            result: None,
+        });
+        Ok(self.tasks.len() - 1)
+    }
+
+    pub fn machine(&self, id: TaskId) -> &SimpleInterpreter {
+        &self.tasks[id].machine
+    }
+
+    // None while the program is still running.
+    pub fn result(&self, id: TaskId) -> Option<&Result<(), InterpreterError>> {
+        self.tasks[id].result.as_ref()
+    }
+
+    // Gives every running program one turn. Returns false once all have ended.
+    pub fn run_round(&mut self) -> bool {
+        let mut progressed = false;
+        for task in &mut self.tasks {
+            progressed |= task.run(self.quantum, false);
+        }
+        if !progressed {
+            // everyone is waiting for input
+            for task in &mut self.tasks {
+                task.run(self.quantum, true);
+            }
+        }
+        self.tasks.iter().any(|task| task.result.is_none())
+    }
+
+    pub fn run(&mut self) {
+        while self.run_round() {}
+    }
+}
+
+impl Task {
+    // Returns whether any command ran.
+    fn run(&mut self, quantum: u64, force_input: bool) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+        for count in 0..quantum {
+            let waiting = self.machine.next_command() == Some(TokenKind::Input)
+                && !self.machine.input_ready();
+            if waiting && !(force_input && count == 0) {
+                return count > 0;
+            }
+            match self.machine.step() {
+                Ok(true) => {}
+                Ok(false) => return self.end(Ok(())),
+                Err(e) => return self.end(Err(e)),
+            }
+        }
+        true
+    }
+
+    fn end(&mut self, result: Result<(), InterpreterError>) -> bool {
+        let result = self.machine.finish(result.map(|_| 0), self.last);
+        self.result = Some(result.map(|_| ()));
+        // closes a queue written to, so that its reader sees the end
+        self.machine.set_output(Box::new(io::sink()));
+        true
+    }
+}
+
+// Bytes written by one program for another to read.
+pub fn queue() -> (QueueWriter, QueueReader) {
+    let bytes = Arc::new(Mutex::new(VecDeque::new()));
+    (QueueWriter(bytes.clone()), QueueReader(bytes))
+}
+
+pub struct QueueWriter(Arc<Mutex<VecDeque<u8>>>);
+
+impl Write for QueueWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Ends once it is empty and its writer has been dropped.
+pub struct QueueReader(Arc<Mutex<VecDeque<u8>>>);
+
+impl QueueReader {
+    fn closed(&self) -> bool {
+        Arc::strong_count(&self.0) == 1
+    }
+}
+
+impl InputSource for QueueReader {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.0.lock().unwrap().pop_front()
+    }
+    fn is_ready(&self) -> bool {
+        !self.0.lock().unwrap().is_empty() || self.closed()
+    }
+}
+
+#[test]
+fn test_scheduler() {
+    use crate::interpreter::EofBehavior;
+    use crate::lexer::Lexer;
+    use crate::spec::Capture;
+
+    // a producer piping "HAL" through a consumer that adds one to each byte
+    let (writer, reader) = queue();
+    let mut producer = SimpleInterpreter::new();
+    producer.set_output(Box::new(writer));
+    let output = Capture::default();
+    let mut consumer = SimpleInterpreter::new();
+    consumer.set_input_source(Box::new(reader));
+    consumer.set_eof_behavior(EofBehavior::Zero);
+    consumer.set_output(Box::new(output.clone()));
+
+    let mut scheduler = Scheduler::new(3);
+    let hal = "+".repeat(72) + ".>" + &"+".repeat(65) + ".>" + &"+".repeat(76) + ".";
+    let consumer = scheduler.spawn(consumer, &Lexer::lex(",[+.,]")).unwrap();
+    let producer = scheduler.spawn(producer, &Lexer::lex(&hal)).unwrap();
+    scheduler.run();
+    assert_eq!(scheduler.result(producer), Some(&Ok(())));
+    assert_eq!(scheduler.result(consumer), Some(&Ok(())));
+    assert_eq!(*output.0.lock().unwrap(), b"IBM");
+
+    // two programs waiting on each other read the end of the input
+    let mut scheduler = Scheduler::default();
+    let (a_writer, a_reader) = queue();
+    let (b_writer, b_reader) = queue();
+    for (writer, reader) in [(a_writer, b_reader), (b_writer, a_reader)] {
+        let mut machine = SimpleInterpreter::new();
+        machine.set_output(Box::new(writer));
+        machine.set_input_source(Box::new(reader));
+        machine.set_eof_behavior(EofBehavior::Zero);
+        scheduler.spawn(machine, &Lexer::lex(",+.")).unwrap();
+    }
+    scheduler.run();
+    assert_eq!(scheduler.machine(0).cells(), &[1]);
+    assert_eq!(scheduler.machine(1).cells(), &[2]);
+    assert!(scheduler
+        .spawn(SimpleInterpreter::new(), &Lexer::lex("]"))
+        .is_err());
+}
@@ -0,0 +1,388 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{EofBehavior, InterpreterErrorKind};
+use crate::json::Json;
+use crate::lexer::Lexer;
+use crate::metrics::{Limit, Metrics};
+use crate::spec::Capture;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A small HTTP API for a playground:
+//
+//     POST /run  {"source": "...", "input": "...", "eof": "zero" or "error"}
+//     => 200     {"output": "...", "error": null or {...}, "steps": N}
+//
+//     GET /metrics
+//     => 200     counters in the Prometheus text format
+//
+// The program's own errors are part of a 200 response; other statuses mean
+// the request itself was wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub steps: u64,
+    pub output: usize,
+    // of the request body
+    pub body: usize,
+    // of the loops of a program
+    pub depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: 10_000_000,
+            output: 1 << 20,
+            body: 1 << 20,
+            depth: 1000,
+        }
+    }
+}
+
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Json,
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Error",
+    }
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    Response {
+        status,
+        body: Json::object(vec![("message", message.into())]),
+    }
+}
+
+// Output kept in memory that refuses to grow past a limit.
+struct LimitedCapture {
+    capture: Capture,
+    limit: usize,
+}
+
+impl Write for LimitedCapture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.capture.0.lock().unwrap();
+        if output.len() + buf.len() > self.limit {
+            return Err(io::Error::other("output limit exceeded"));
+        }
+        output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn handle(
+    method: &str,
+    path: &str,
+    body: &[u8],
+    limits: &Limits,
+    metrics: &Metrics,
+) -> Response {
+    if path != "/run" {
+        return error_response(404, "not found");
+    }
+    if method != "POST" {
+        return error_response(405, "use POST");
+    }
+    if body.len() > limits.body {
+        return error_response(413, "request too large");
+    }
+    let request = match std::str::from_utf8(body)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Json::parse(text).map_err(|e| e.to_string()))
+    {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("invalid JSON: {}", e)),
+    };
+    let source = match request.get("source").and_then(Json::as_str) {
+        Some(source) => source,
+        None => return error_response(400, "`source` must be a string"),
+    };
+    let input = match request.get("input") {
+        None | Some(Json::Null) => "",
+        Some(input) => match input.as_str() {
+            Some(input) => input,
+            None => return error_response(400, "`input` must be a string"),
+        },
+    };
+
+    // "error" fails a program reading past the input instead of giving it 0
+    let eof = match request.get("eof").map(Json::as_str) {
+        None | Some(Some("zero")) => EofBehavior::Zero,
+        Some(Some("error")) => EofBehavior::Error,
+        Some(_) => return error_response(400, "`eof` must be \"zero\" or \"error\""),
+    };
+
+    let output = Capture::default();
+    let mut interpreter = EngineKind::Auto.create();
+    let machine = interpreter.machine_mut();
+    machine.set_output(Box::new(LimitedCapture {
+        capture: output.clone(),
+        limit: limits.output,
+    }));
+    machine.set_input_bytes(input.bytes());
+    machine.set_eof_behavior(eof);
+    machine.set_step_limit(Some(limits.steps));
+    machine.set_nesting_limit(Some(limits.depth));
+    let start = Instant::now();
+    let result = interpreter.eval(&Lexer::lex(source));
+    let steps = interpreter.machine().steps();
+    let limit = match result {
+        Err(ref e) if e.value == InterpreterErrorKind::StepLimitExceeded => Some(Limit::Steps),
+        Err(ref e) if e.value == InterpreterErrorKind::OutputError => Some(Limit::Output),
+        _ => None,
+    };
+    metrics.record(steps, start.elapsed(), limit);
+    let error = match result {
+        Ok(_) => Json::Null,
+        Err(e) => {
+            let message = match e.value {
+                InterpreterErrorKind::OutputError => "output limit exceeded".to_string(),
+                kind => kind.to_string(),
+            };
+            Json::object(vec![
+                ("kind", e.value.name().into()),
+                ("message", message.into()),
+                ("line", e.location.line.into()),
+                ("col", e.location.col.into()),
+            ])
+        }
+    };
+    let output = output.0.lock().unwrap();
+    Response {
+        status: 200,
+        body: Json::object(vec![
+            (
+                "output",
+                String::from_utf8_lossy(&output).into_owned().into(),
+            ),
+            ("error", error),
+            ("steps", steps.into()),
+        ]),
+    }
+}
+
+// What goes back over the wire.
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl From<Response> for Reply {
+    fn from(response: Response) -> Self {
+        Self {
+            status: response.status,
+            content_type: "application/json",
+            body: match response.body {
+                Json::Null => String::new(),
+                body => body.to_string(),
+            },
+        }
+    }
+}
+
+fn metrics_reply(metrics: &Metrics) -> Reply {
+    Reply {
+        status: 200,
+        content_type: "text/plain; version=0.0.4",
+        body: metrics.render(),
+    }
+}
+
+// Reads one request. Returns None if the connection closed or sent nonsense.
+fn read_request(
+    stream: &mut BufReader<TcpStream>,
+    limits: &Limits,
+    metrics: &Metrics,
+) -> Option<Reply> {
+    let mut line = String::new();
+    stream.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if stream.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    if method == "OPTIONS" {
+        // CORS preflight of a playground page on another origin
+        return Some(Reply::from(Response {
+            status: 204,
+            body: Json::Null,
+        }));
+    }
+    if method == "GET" && path == "/metrics" {
+        return Some(metrics_reply(metrics));
+    }
+    if length > limits.body {
+        return Some(error_response(413, "request too large").into());
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).ok()?;
+    Some(handle(&method, &path, &body, limits, metrics).into())
+}
+
+fn write_reply(stream: &mut TcpStream, reply: &Reply) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: POST\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{}",
+        reply.status,
+        status_text(reply.status),
+        reply.content_type,
+        reply.body.len(),
+        reply.body
+    )?;
+    stream.flush()
+}
+
+// Serves requests forever, one thread and one request per connection.
+pub fn serve(listener: TcpListener, limits: Limits) {
+    let metrics = Arc::new(Metrics::default());
+    // a failed accept, e.g. out of file descriptors, only loses that connection
+    for mut stream in listener.incoming().flatten() {
+        let metrics = metrics.clone();
+        thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            let mut reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(_) => return,
+            };
+            if let Some(reply) = read_request(&mut reader, &limits, &metrics) {
+                let _ = write_reply(&mut stream, &reply);
+            }
+        });
+    }
+}
+
+// Serves only GET /metrics, for the services that do not speak HTTP.
+pub fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) {
+    let limits = Limits {
+        body: 0,
+        ..Limits::default()
+    };
+    for mut stream in listener.incoming().flatten() {
+        let metrics = metrics.clone();
+        thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            let mut reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(_) => return,
+            };
+            let reply = match read_request(&mut reader, &limits, &metrics) {
+                Some(reply) if reply.status == 200 => reply,
+                Some(_) => error_response(404, "not found").into(),
+                None => return,
+            };
+            let _ = write_reply(&mut stream, &reply);
+        });
+    }
+}
+
+#[test]
+fn test_server() {
+    let limits = Limits {
+        steps: 1000,
+        output: 4,
+        body: 100,
+        depth: 2,
+    };
+    let metrics = Metrics::default();
+    let run = |body: &str| handle("POST", "/run", body.as_bytes(), &limits, &metrics);
+
+    let response = run(r#"{"source": ",[.,]", "input": "hi"}"#);
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.body.to_string(),
+        r#"{"output":"hi","error":null,"steps":8}"#
+    );
+    let response = run(r#"{"source": "+[]"}"#);
+    assert_eq!(
+        response.body.get("error").and_then(|e| e.get("kind")),
+        Some(&Json::from("step_limit_exceeded"))
+    );
+    let response = run(r#"{"source": "+[.]"}"#);
+    let error = response.body.get("error").unwrap();
+    assert_eq!(
+        error.get("message"),
+        Some(&Json::from("output limit exceeded"))
+    );
+    assert_eq!(
+        response.body.get("output"),
+        Some(&Json::from("\u{1}\u{1}\u{1}\u{1}"))
+    );
+
+    let response = run(r#"{"source": ",,", "input": "a", "eof": "error"}"#);
+    assert_eq!(
+        response.body.get("error").and_then(|e| e.get("kind")),
+        Some(&Json::from("input_exhausted"))
+    );
+    let response = run(r#"{"source": "[[]][[[]]]"}"#);
+    assert_eq!(
+        response.body.get("error").and_then(|e| e.get("kind")),
+        Some(&Json::from("nesting_too_deep"))
+    );
+    assert_eq!(run(r#"{"source": "", "eof": "newline"}"#).status, 400);
+
+    assert_eq!(run("{").status, 400);
+    // a body nested too deeply for the parser's stack
+    let nested = format!(r#"{{"source":{}"#, "[".repeat(500_000));
+    let response = handle(
+        "POST",
+        "/run",
+        nested.as_bytes(),
+        &Limits {
+            body: 1 << 20,
+            ..limits
+        },
+        &metrics,
+    );
+    assert_eq!(response.status, 400);
+    assert!(response.body.to_string().contains("nested too deeply"));
+    assert_eq!(run(r#"{"input": ""}"#).status, 400);
+    assert_eq!(
+        run(&format!(r#"{{"source": "{}"}}"#, "+".repeat(100))).status,
+        413
+    );
+    assert_eq!(handle("GET", "/run", b"", &limits, &metrics).status, 405);
+    assert_eq!(handle("POST", "/", b"", &limits, &metrics).status, 404);
+    assert_eq!(metrics.runs(), 5);
+    let text = metrics.render();
+    assert!(text.contains("brainfuck_limit_violations_total{limit=\"steps\"} 1\n"));
+    assert!(text.contains("brainfuck_limit_violations_total{limit=\"output\"} 1\n"));
+}
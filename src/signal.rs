@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn on_interrupt() {
+    if let Some(flag) = INTERRUPTED.get() {
+        flag.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    const SIGINT: i32 = 2;
+    const SIG_DFL: usize = 0;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    extern "C" fn handler(_signum: i32) {
+        super::on_interrupt();
+        // a second Ctrl-C kills the process as usual
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub fn install() {
+        let handler: extern "C" fn(i32) = handler;
+        unsafe {
+            signal(SIGINT, handler as usize);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    const CTRL_C_EVENT: u32 = 0;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    extern "system" fn handler(ctrl_type: u32) -> i32 {
+        if ctrl_type != CTRL_C_EVENT {
+            return 0;
+        }
+        super::on_interrupt();
+        unsafe {
+            SetConsoleCtrlHandler(Some(handler), 0);
+        }
+        1
+    }
+
+    pub fn install() {
+        unsafe {
+            SetConsoleCtrlHandler(Some(handler), 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub fn install() {}
+}
+
+// Installs a Ctrl-C handler and returns the flag it sets, suitable for
+// SimpleInterpreter::set_interrupt_flag. Calling it again returns the same flag.
+pub fn install_interrupt_handler() -> Arc<AtomicBool> {
+    let flag = INTERRUPTED
+        .get_or_init(|| Arc::new(AtomicBool::new(false)))
+        .clone();
+    flag.store(false, Ordering::SeqCst);
+    imp::install();
+    flag
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::fs;
+use std::io;
+
+const HEADER: &str = "bfstate 1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    pub cells: Vec<u8>,
+    pub pointer: usize,
+}
+
+impl Snapshot {
+    pub fn to_text(&self) -> String {
+        let cells: Vec<String> = self.cells.iter().map(|c| format!("{:02x}", c)).collect();
+        format!(
+            "{}\npointer {}\ncells {}\n",
+            HEADER,
+            self.pointer,
+            cells.join(" ")
+        )
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a bfstate file".to_string());
+        }
+        let mut pointer = None;
+        let mut cells = None;
+        for line in lines {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("pointer"), Some(value)) => {
+                    pointer = Some(
+                        value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| format!("invalid pointer: {}", value))?,
+                    );
+                }
+                (Some("cells"), value) => {
+                    let value = value.unwrap_or("");
+                    cells = Some(
+                        value
+                            .split_whitespace()
+                            .map(|c| {
+                                u8::from_str_radix(c, 16)
+                                    .map_err(|_| format!("invalid cell: {}", c))
+                            })
+                            .collect::<Result<Vec<u8>, String>>()?,
+                    );
+                }
+                _ => {}
+            }
+        }
+        let pointer = pointer.ok_or("missing pointer")?;
+        let mut cells = cells.ok_or("missing cells")?;
+        if cells.len() <= pointer {
+            cells.resize(pointer + 1, 0);
+        }
+        Ok(Self { cells, pointer })
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+// What changed from one snapshot to another, e.g. since a breakpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapeDiff {
+    // the cells that changed in order, with their value before and after; a
+    // cell past the end of a tape is 0
+    pub cells: Vec<(usize, u8, u8)>,
+    pub pointer_before: usize,
+    pub pointer_after: usize,
+}
+
+impl TapeDiff {
+    pub fn between(a: &Snapshot, b: &Snapshot) -> Self {
+        let cell = |snapshot: &Snapshot, i| snapshot.cells.get(i).copied().unwrap_or(0);
+        let cells = (0..a.cells.len().max(b.cells.len()))
+            .map(|i| (i, cell(a, i), cell(b, i)))
+            .filter(|(_, before, after)| before != after)
+            .collect();
+        Self {
+            cells,
+            pointer_before: a.pointer,
+            pointer_after: b.pointer,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.pointer_moved() == 0
+    }
+
+    // Cells to the right, negative to the left.
+    pub fn pointer_moved(&self) -> isize {
+        self.pointer_after as isize - self.pointer_before as isize
+    }
+}
+
+// "pointer 0 -> 2, #1 0 -> 97", or "no change"
+impl fmt::Display for TapeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut changes = Vec::new();
+        if self.pointer_moved() != 0 {
+            changes.push(format!(
+                "pointer {} -> {}",
+                self.pointer_before, self.pointer_after
+            ));
+        }
+        for (i, before, after) in &self.cells {
+            changes.push(format!("#{} {} -> {}", i, before, after));
+        }
+        if changes.is_empty() {
+            write!(f, "no change")
+        } else {
+            write!(f, "{}", changes.join(", "))
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_text() {
+    let snapshot = Snapshot {
+        cells: vec![0, 72, 255],
+        pointer: 1,
+    };
+    let text = snapshot.to_text();
+    assert_eq!(text, "bfstate 1\npointer 1\ncells 00 48 ff\n");
+    assert_eq!(Snapshot::from_text(&text), Ok(snapshot));
+    assert!(Snapshot::from_text("pointer 1").is_err());
+}
+
+#[test]
+fn test_tape_diff() {
+    let a = Snapshot {
+        cells: vec![1, 2, 3],
+        pointer: 2,
+    };
+    let b = Snapshot {
+        cells: vec![1, 5, 3, 0, 9],
+        pointer: 0,
+    };
+    let diff = TapeDiff::between(&a, &b);
+    assert_eq!(diff.cells, vec![(1, 2, 5), (4, 0, 9)]);
+    assert_eq!(diff.pointer_moved(), -2);
+    assert_eq!(diff.to_string(), "pointer 2 -> 0, #1 2 -> 5, #4 0 -> 9");
+    // trailing zeros are not a change
+    let c = Snapshot {
+        cells: vec![1, 2, 3, 0],
+        pointer: 2,
+    };
+    assert!(TapeDiff::between(&a, &c).is_empty());
+    assert_eq!(TapeDiff::between(&a, &c).to_string(), "no change");
+}
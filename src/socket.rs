@@ -0,0 +1,122 @@
+use crate::engine::EngineKind;
+use crate::interpreter::{Buffering, EofBehavior, InterpreterError, InterpreterErrorKind};
+use crate::lexer::Program;
+use crate::metrics::{Limit, Metrics};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// What one connection may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub steps: Option<u64>,
+    // a client silent for this long gets EOF
+    pub idle: Duration,
+    // connections served at once; more are closed right away
+    pub connections: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: Some(100_000_000),
+            idle: Duration::from_secs(60),
+            connections: 64,
+        }
+    }
+}
+
+// Runs the program on one connection: `,` reads what the client sends, with
+// EOF once it shuts down its side or stays idle, and `.` writes to it.
+pub fn serve_connection(
+    program: &Program,
+    engine: EngineKind,
+    stream: TcpStream,
+    limits: &Limits,
+    metrics: &Metrics,
+) -> Result<usize, InterpreterError> {
+    let _ = stream.set_read_timeout(Some(limits.idle));
+    let _ = stream.set_nodelay(true);
+    let mut interpreter = engine.create();
+    let machine = interpreter.machine_mut();
+    machine.set_eof_behavior(EofBehavior::Zero);
+    machine.set_step_limit(limits.steps);
+    if let Ok(reader) = stream.try_clone() {
+        machine.set_input(Box::new(reader));
+    } else {
+        machine.set_input_bytes(vec![]);
+    }
+    machine.set_output(Buffering::Line.wrap(stream));
+    let start = Instant::now();
+    let result = interpreter.eval(program);
+    let limit = match result {
+        Err(ref e) if e.value == InterpreterErrorKind::StepLimitExceeded => Some(Limit::Steps),
+        _ => None,
+    };
+    metrics.record(interpreter.machine().steps(), start.elapsed(), limit);
+    result
+}
+
+// Accepts connections forever, each served by a fresh interpreter on its own thread.
+pub fn serve(
+    listener: TcpListener,
+    program: Program,
+    engine: EngineKind,
+    limits: Limits,
+    metrics: Arc<Metrics>,
+) {
+    let program = Arc::new(program);
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming().flatten() {
+        if active.fetch_add(1, Ordering::SeqCst) >= limits.connections {
+            active.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+        let program = program.clone();
+        let active = active.clone();
+        let metrics = metrics.clone();
+        thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if let Err(e) = serve_connection(&program, engine, stream, &limits, &metrics) {
+                eprintln!("{}: {} at {}", peer, e.value, e.location);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+#[test]
+fn test_socket() {
+    use crate::lexer::Lexer;
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let limits = Limits {
+        idle: Duration::from_secs(5),
+        ..Limits::default()
+    };
+    let metrics = Arc::new(Metrics::default());
+    let program = Lexer::lex(",[+.,]");
+    let shared = metrics.clone();
+    thread::spawn(move || serve(listener, program, EngineKind::Auto, limits, shared));
+
+    for _ in 0..2 {
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"HAL").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, b"IBM");
+    }
+    // the count goes up after the connection closes
+    while metrics.runs() < 2 {
+        thread::yield_now();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::engine::EngineKind;
+use crate::escape;
+use crate::interpreter::EofBehavior;
+use crate::lexer::Lexer;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_STEP_LIMIT: u64 = 100_000_000;
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+// A program with the input to give it and the output it must produce.
+//
+// The expectations come from the files next to `name.bf`: `name.in` (optional)
+// and `name.out`, or from a front matter at the top of the program:
+//
+//     ---
+//     input: 3\n
+//     output: 6\n
+//     steps: 1000
+//     ---
+//
+// Values in the front matter are escaped as in escape::unescape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub path: PathBuf,
+    pub source: String,
+    pub input: Vec<u8>,
+    pub expected: Vec<u8>,
+    pub step_limit: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestResult {
+    Passed,
+    Failed { actual: Vec<u8>, message: String },
+}
+
+// Splits the front matter off the source. The front matter must start on the
+// first line, since `-` is a command.
+fn split_front_matter(text: &str) -> Option<(&str, &str)> {
+    let rest = text
+        .strip_prefix(FRONT_MATTER_DELIMITER)?
+        .strip_prefix('\n')
+        .or_else(|| text.strip_prefix("---\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+impl TestCase {
+    // Returns None for a program without expectations, which is not a test.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let mut case = TestCase {
+            path: path.to_path_buf(),
+            source: text.clone(),
+            input: Vec::new(),
+            expected: Vec::new(),
+            step_limit: DEFAULT_STEP_LIMIT,
+        };
+        if let Some((front_matter, source)) = split_front_matter(&text) {
+            // keeps the line numbers of the file in error locations
+            let skipped = &text[..text.len() - source.len()];
+            case.source = "\n".repeat(skipped.matches('\n').count()) + source;
+            let mut has_output = false;
+            for line in front_matter.lines().filter(|l| !l.trim().is_empty()) {
+                let (key, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| format!("{}: expected `key: value`", path.display()))?;
+                let value = value.trim();
+                let invalid = |e: String| format!("{}: {}: {}", path.display(), key, e);
+                match key.trim() {
+                    "input" => case.input = escape::unescape(value).map_err(invalid)?,
+                    "output" => {
+                        case.expected = escape::unescape(value).map_err(invalid)?;
+                        has_output = true;
+                    }
+                    "steps" => {
+                        case.step_limit = value
+                            .parse()
+                            .map_err(|_| invalid(format!("not a number: {}", value)))?
+                    }
+                    key => return Err(format!("{}: unknown key: {}", path.display(), key)),
+                }
+            }
+            if has_output {
+                return Ok(Some(case));
+            }
+        }
+        let read = |extension: &str| match fs::read(path.with_extension(extension)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!(
+                "could not read {}: {}",
+                path.with_extension(extension).display(),
+                e
+            )),
+        };
+        case.expected = match read("out")? {
+            Some(expected) => expected,
+            None => return Ok(None),
+        };
+        case.input = read("in")?.unwrap_or_default();
+        Ok(Some(case))
+    }
+
+    pub fn run(&self, engine: EngineKind) -> TestResult {
+        let output = Capture::default();
+        let mut interpreter = engine.create();
+        let machine = interpreter.machine_mut();
+        machine.set_output(Box::new(output.clone()));
+        machine.set_input_bytes(self.input.clone());
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine.set_step_limit(Some(self.step_limit));
+        let result = interpreter.eval(&Lexer::lex(&self.source));
+        let actual = output.0.lock().unwrap().clone();
+        let message = match result {
+            Err(e) => format!("{} at {}", e.value, e.location),
+            Ok(_) if actual == self.expected => return TestResult::Passed,
+            Ok(_) => {
+                let at = actual
+                    .iter()
+                    .zip(&self.expected)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                format!(
+                    "output differs at byte {}\n  expected: {}\n  actual:   {}",
+                    at,
+                    escape::escape(&self.expected),
+                    escape::escape(&actual)
+                )
+            }
+        };
+        TestResult::Failed { actual, message }
+    }
+}
+
+// Output kept in memory, readable while the interpreter still holds a clone.
+#[derive(Clone, Default)]
+pub(crate) struct Capture(pub(crate) Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Finds the .bf files under `path`, or `path` itself if it is a file, sorted by path.
+pub fn discover(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    if path.is_file() {
+        found.push(path.to_path_buf());
+        return Ok(found);
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.extend(discover(&path)?);
+        } else if path.extension().is_some_and(|e| e == "bf") {
+            found.push(path);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+#[test]
+fn test_spec() {
+    let dir = std::env::temp_dir().join("brainfuck_test_spec");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(
+        dir.join("double.bf"),
+        "---\ninput: \\x03\noutput: \\x06\n---\n,[->++<]>.",
+    )
+    .unwrap();
+    fs::write(dir.join("nested/cat.bf"), ",[.,]").unwrap();
+    fs::write(dir.join("nested/cat.in"), "hi").unwrap();
+    fs::write(dir.join("nested/cat.out"), "ho").unwrap();
+    fs::write(dir.join("loop.bf"), "---\noutput: \nsteps: 100\n---\n+[]").unwrap();
+    fs::write(dir.join("untested.bf"), "+").unwrap();
+
+    let paths = discover(&dir).unwrap();
+    assert_eq!(
+        paths,
+        vec![
+            dir.join("double.bf"),
+            dir.join("loop.bf"),
+            dir.join("nested/cat.bf"),
+            dir.join("untested.bf")
+        ]
+    );
+    assert_eq!(TestCase::load(&paths[3]), Ok(None));
+    let results: Vec<TestResult> = paths[..3]
+        .iter()
+        .map(|p| TestCase::load(p).unwrap().unwrap().run(EngineKind::Auto))
+        .collect();
+    assert_eq!(results[0], TestResult::Passed);
+    assert_eq!(
+        results[1],
+        TestResult::Failed {
+            actual: vec![],
+            message: "step limit exceeded at 5:3".to_string()
+        }
+    );
+    assert_eq!(
+        results[2],
+        TestResult::Failed {
+            actual: b"hi".to_vec(),
+            message: "output differs at byte 1\n  expected: ho\n  actual:   hi".to_string()
+        }
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,167 @@
+use crate::analysis;
+use crate::json::Json;
+use crate::lexer::{Fingerprint, Program, TokenKind};
+use std::fmt;
+
+const COMMANDS: [TokenKind; 8] = [
+    TokenKind::IncrementPointer,
+    TokenKind::DecrementPointer,
+    TokenKind::Increment,
+    TokenKind::Decrement,
+    TokenKind::Output,
+    TokenKind::Input,
+    TokenKind::JumpForward,
+    TokenKind::JumpBackward,
+];
+
+// Static facts about a program, found without running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramStats {
+    // in the order of `><+-.,[]`
+    pub counts: [usize; 8],
+    pub loops: usize,
+    pub max_depth: usize,
+    // bytes of the source, comments included
+    pub source_length: usize,
+    pub minified_length: usize,
+    // cells the pointer can reach, or None when a loop moves the pointer by a
+    // net offset so that it depends on the data
+    pub tape_estimate: Option<usize>,
+    // see Fingerprint
+    pub fingerprint: u64,
+}
+
+impl ProgramStats {
+    pub fn new(source: &str, program: &Program) -> Self {
+        let mut counts = [0; 8];
+        for token in program {
+            if let Some(i) = COMMANDS.iter().position(|&c| c == token.value) {
+                counts[i] += 1;
+            }
+        }
+        Self {
+            counts,
+            loops: counts[6],
+            max_depth: analysis::nesting_depths(program)
+                .into_iter()
+                .max()
+                .unwrap_or(0),
+            source_length: source.len(),
+            minified_length: minify(program).len(),
+            tape_estimate: tape_estimate(program),
+            fingerprint: program.fingerprint(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let counts = COMMANDS
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(c, n)| (c.to_string(), Json::from(*n)))
+            .collect();
+        Json::object(vec![
+            ("counts", Json::Object(counts)),
+            ("loops", self.loops.into()),
+            ("max_depth", self.max_depth.into()),
+            ("source_length", self.source_length.into()),
+            ("minified_length", self.minified_length.into()),
+            (
+                "tape_estimate",
+                self.tape_estimate.map_or(Json::Null, Json::from),
+            ),
+            // as hex, since JSON numbers cannot hold every u64
+            ("fingerprint", format!("{:016x}", self.fingerprint).into()),
+        ])
+    }
+}
+
+impl fmt::Display for ProgramStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts: Vec<String> = COMMANDS
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(c, n)| format!("{} {}", c, n))
+            .collect();
+        writeln!(f, "commands:        {}", counts.join("  "))?;
+        writeln!(f, "loops:           {}", self.loops)?;
+        writeln!(f, "max loop depth:  {}", self.max_depth)?;
+        writeln!(f, "source length:   {}", self.source_length)?;
+        writeln!(f, "minified length: {}", self.minified_length)?;
+        match self.tape_estimate {
+            Some(cells) => writeln!(f, "tape usage:      {} cells", cells)?,
+            None => writeln!(f, "tape usage:      unknown (a loop moves the pointer)")?,
+        }
+        write!(f, "fingerprint:     {:016x}", self.fingerprint)
+    }
+}
+
+// The commands without comments, with adjacent `+-` and `<>` pairs cancelled.
+pub fn minify(program: &Program) -> String {
+    let mut out: Vec<TokenKind> = Vec::with_capacity(program.len());
+    for token in program {
+        let cancels = matches!(
+            (out.last(), token.value),
+            (Some(TokenKind::Increment), TokenKind::Decrement)
+                | (Some(TokenKind::Decrement), TokenKind::Increment)
+                | (
+                    Some(TokenKind::IncrementPointer),
+                    TokenKind::DecrementPointer
+                )
+                | (
+                    Some(TokenKind::DecrementPointer),
+                    TokenKind::IncrementPointer
+                )
+        );
+        if cancels {
+            out.pop();
+        } else {
+            out.push(token.value);
+        }
+    }
+    out.iter().map(|c| c.to_string()).collect()
+}
+
+fn tape_estimate(program: &Program) -> Option<usize> {
+    let mut offset: isize = 0;
+    let mut max: isize = 0;
+    let mut loop_offsets = vec![];
+    for token in program {
+        match token.value {
+            TokenKind::IncrementPointer => offset += 1,
+            TokenKind::DecrementPointer => offset -= 1,
+            TokenKind::JumpForward => loop_offsets.push(offset),
+            // a loop that does not return to where it started
+            TokenKind::JumpBackward if loop_offsets.pop() != Some(offset) => return None,
+            // the other tape has a pointer of its own
+            TokenKind::SwitchTape => return None,
+            _ => {}
+        }
+        max = max.max(offset);
+    }
+    Some(max as usize + 1)
+}
+
+#[test]
+fn test_program_stats() {
+    use crate::lexer::Lexer;
+    let source = "+-+[->>+<<] comment\n>[<]";
+    let stats = ProgramStats::new(source, &Lexer::lex(source));
+    assert_eq!(stats.counts, [3, 3, 3, 2, 0, 0, 2, 2]);
+    assert_eq!(stats.loops, 2);
+    assert_eq!(stats.max_depth, 1);
+    assert_eq!(stats.minified_length, 13);
+    assert_eq!(stats.tape_estimate, None);
+    assert_eq!(
+        stats.to_json().get("counts").and_then(|c| c.get("+")),
+        Some(&Json::Number(3.0))
+    );
+
+    let source = "+[->>+<<]>";
+    let stats = ProgramStats::new(source, &Lexer::lex(source));
+    assert_eq!(stats.tape_estimate, Some(3));
+    assert_eq!(
+        stats.to_json().get("fingerprint").and_then(Json::as_str),
+        Some(format!("{:016x}", Lexer::lex("+[->>+<<]>").fingerprint()).as_str())
+    );
+    assert_eq!(minify(&Lexer::lex("+<>-.")), ".");
+}
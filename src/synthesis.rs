@@ -0,0 +1,147 @@
+use crate::interpreter::EofBehavior;
+use crate::lexer::Lexer;
+use crate::mutation;
+use crate::optimized::OptimizedInterpreter;
+use crate::random::Rng;
+use crate::spec::Capture;
+
+// A genetic search for a program that prints a given output. The fittest
+// half of the population survives each generation, and the other half is
+// made by mutating survivors; copies are dropped to keep the population
+// varied. Programs run on the optimized engine without
+// input, for at most `step_limit` steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Search {
+    // the longest program accepted, in commands
+    pub size: usize,
+    pub population: usize,
+    pub generations: usize,
+    pub step_limit: u64,
+    // the same seed makes the same search
+    pub seed: u64,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            size: 100,
+            population: 100,
+            generations: 500,
+            step_limit: 10000,
+            seed: 0,
+        }
+    }
+}
+
+// How far a program is from the target; the lower the better. A command
+// over the size counts as much as a byte off by one, so that programs too
+// long can lead to shorter ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Fitness {
+    score: u64,
+    length: usize,
+}
+
+impl Search {
+    // A program of at most `size` commands printing `target`, or None if the
+    // search found none.
+    pub fn synthesize(&self, target: &[u8]) -> Option<String> {
+        let mut rng = Rng::new(self.seed);
+        let mut interpreter = OptimizedInterpreter::new();
+        let output = Capture::default();
+        let machine = interpreter.machine_mut();
+        machine.set_output(Box::new(output.clone()));
+        machine.set_eof_behavior(EofBehavior::Zero);
+        machine.set_step_limit(Some(self.step_limit));
+        let mut fitness = |source: &str| {
+            output.0.lock().unwrap().clear();
+            interpreter.machine_mut().set_input_bytes(Vec::new());
+            // a program stopped by the step limit is judged by what it printed
+            let _ = interpreter.eval(&Lexer::lex(source));
+            let printed = output.0.lock().unwrap();
+            let excess = source.len().saturating_sub(self.size) as u64;
+            Fitness {
+                score: distance(&printed, target) + excess,
+                length: source.len(),
+            }
+        };
+
+        // the program printing each byte by counting up or down from the
+        // last one, which may be longer than allowed
+        let mut naive = String::new();
+        let mut cell = 0u8;
+        for &byte in target {
+            let up = byte.wrapping_sub(cell) as usize;
+            if up <= 128 {
+                naive.push_str(&"+".repeat(up));
+            } else {
+                naive.push_str(&"-".repeat(256 - up));
+            }
+            naive.push('.');
+            cell = byte;
+        }
+        let mut population = vec![(fitness(&naive), naive)];
+        while population.len() < self.population.max(2) {
+            let source = mutation::mutate("", &mut rng);
+            population.push((fitness(&source), source));
+        }
+
+        let size = population.len();
+        for _ in 0..=self.generations {
+            // ties go to the same program on every run
+            population.sort();
+            let (best, source) = &population[0];
+            if best.score == 0 {
+                return Some(source.clone());
+            }
+            // copies of a program would crowd out the others
+            population.dedup_by(|a, b| a.1 == b.1);
+            population.truncate(size.div_ceil(2));
+            let survivors = population.len();
+            for i in 0..size - survivors {
+                let mut source = population[i % survivors].1.clone();
+                for _ in 0..=rng.below(3) {
+                    source = mutation::mutate(&source, &mut rng);
+                }
+                population.push((fitness(&source), source));
+            }
+        }
+        None
+    }
+}
+
+fn distance(output: &[u8], target: &[u8]) -> u64 {
+    (0..output.len().max(target.len()))
+        .map(|i| match (output.get(i), target.get(i)) {
+            (Some(&a), Some(&b)) => {
+                let d = a.wrapping_sub(b);
+                d.min(d.wrapping_neg()) as u64
+            }
+            // a missing or extra byte is worse than any wrong one
+            _ => 256,
+        })
+        .sum()
+}
+
+#[test]
+fn test_synthesize() {
+    let run = |source: &str| crate::run(source, b"").unwrap();
+    let search = Search::default();
+    // counting down from 0 is shorter
+    assert_eq!(search.synthesize(&[254, 1]), Some("--.+++.".to_string()));
+    // shorter than counting, as "+++.>.<."
+    let program = Search { size: 8, ..search }.synthesize(&[3, 0, 3]);
+    assert!(
+        program.as_ref().is_some_and(|p| p.len() <= 8),
+        "{:?}",
+        program
+    );
+    assert_eq!(run(&program.unwrap()), [3, 0, 3]);
+    // "+++++." is as short as it gets
+    let search = Search {
+        size: 5,
+        generations: 20,
+        ..search
+    };
+    assert_eq!(search.synthesize(b"\x05"), None);
+}
@@ -0,0 +1,495 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::str::FromStr;
+
+// Cells a paged tape adds at a time: a multiple of the page size of the OS.
+pub const PAGE_SIZE: usize = 1 << 16;
+
+// Where the cells of a machine live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TapeKind {
+    // a vector, reallocated and copied as the tape grows
+    #[default]
+    Heap,
+    // a large anonymous memory map reserved at once, whose pages the OS
+    // only allocates when they are touched: multi-gigabyte tapes need no
+    // contiguous heap allocation and are never copied. Falls back to the
+    // heap where memory maps are not available.
+    Mapped,
+    // address space reserved like Mapped, but with no memory behind it
+    // until the pointer reaches it, a PAGE_SIZE page at a time: growing
+    // costs a system call per page and never copies, memory stays within a
+    // page of the cells reached, and moving past the end faults rather than
+    // writing to cells the tape does not have. Falls back to the heap where
+    // memory maps are not available.
+    Paged,
+}
+
+impl FromStr for TapeKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "heap" => Ok(TapeKind::Heap),
+            "mapped" => Ok(TapeKind::Mapped),
+            "paged" => Ok(TapeKind::Paged),
+            _ => Err(format!("unknown tape memory: {}", s)),
+        }
+    }
+}
+
+// The header of a tape file: the magic, then the pointer and the number
+// of cells, little-endian.
+const MAGIC: &[u8; 8] = b"BFTAPE01";
+const HEADER: usize = 24;
+
+// Bytes reserved for a memory-mapped tape.
+#[cfg(target_pointer_width = "64")]
+const RESERVED: usize = 1 << 40;
+#[cfg(not(target_pointer_width = "64"))]
+const RESERVED: usize = 1 << 30;
+
+// The tape of a machine, which dereferences to its cells. Cells are added
+// with zeros as the pointer moves right.
+pub struct Tape {
+    // the first cell, valid for `len` cells
+    cells: *mut u8,
+    len: usize,
+    // cells of a map from here on were never written and read 0
+    clean: usize,
+    storage: Storage,
+}
+
+enum Storage {
+    Heap(Vec<u8>),
+    Mapped(sys::Map),
+    // the map and how many of its cells can be used
+    Paged(sys::Map, usize),
+    // the map of the whole file, header included, and the size of the file,
+    // which grows along
+    File(sys::Map, File, usize),
+}
+
+// The cells of a map are only reached through the tape owning it.
+unsafe impl Send for Tape {}
+unsafe impl Sync for Tape {}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Self::from(vec![0])
+    }
+}
+
+impl From<Vec<u8>> for Tape {
+    fn from(mut cells: Vec<u8>) -> Self {
+        Self {
+            cells: cells.as_mut_ptr(),
+            len: cells.len(),
+            clean: 0,
+            storage: Storage::Heap(cells),
+        }
+    }
+}
+
+impl Tape {
+    // A tape of one cell.
+    pub fn new(kind: TapeKind) -> Self {
+        match kind {
+            TapeKind::Heap => Self::default(),
+            TapeKind::Mapped => match sys::Map::anonymous(RESERVED) {
+                Ok(map) => Self {
+                    cells: map.base,
+                    len: 1,
+                    clean: 1,
+                    storage: Storage::Mapped(map),
+                },
+                Err(_) => Self::default(),
+            },
+            TapeKind::Paged => match sys::Map::reserve(RESERVED) {
+                Ok(map) => {
+                    let mut tape = Self {
+                        cells: map.base,
+                        len: 0,
+                        clean: 0,
+                        storage: Storage::Paged(map, 0),
+                    };
+                    match tape.grow(1) {
+                        Ok(()) => tape,
+                        Err(_) => Self::default(),
+                    }
+                }
+                Err(_) => Self::default(),
+            },
+        }
+    }
+
+    // Maps the tape stored in a file, creating an empty one if there is
+    // none, and returns it with the pointer saved with it. The cells are
+    // written to the file as the program changes them, so they are kept
+    // even if the run stops on an error or the process is killed; the
+    // pointer is saved at the end of each run.
+    pub fn open_file(path: &Path) -> io::Result<(Self, usize)> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let size = file.metadata()?.len() as usize;
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        if size == 0 {
+            file.set_len(HEADER as u64 + 1)?;
+        } else if size < HEADER {
+            return Err(invalid("not a tape file"));
+        }
+        let map = sys::Map::file(&file, RESERVED)?;
+        let mut tape = Self {
+            cells: unsafe { map.base.add(HEADER) },
+            len: 0,
+            clean: size.max(HEADER + 1) - HEADER,
+            storage: Storage::File(map, file, size.max(HEADER + 1)),
+        };
+        let header = unsafe { std::slice::from_raw_parts_mut(tape.cells.sub(HEADER), HEADER) };
+        if size == 0 {
+            header[..8].copy_from_slice(MAGIC);
+            tape.len = 1;
+            tape.save_pointer(0);
+            return Ok((tape, 0));
+        }
+        if &header[..8] != MAGIC {
+            return Err(invalid("not a tape file"));
+        }
+        let number = |range: std::ops::Range<usize>| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&header[range]);
+            u64::from_le_bytes(bytes) as usize
+        };
+        let (pointer, len) = (number(8..16), number(16..24));
+        if len == 0 || len > size - HEADER || pointer >= tape.limit() {
+            return Err(invalid("corrupt tape file"));
+        }
+        tape.len = len;
+        tape.grow(pointer + 1)?;
+        Ok((tape, pointer))
+    }
+
+    // The most cells the tape can hold.
+    pub fn limit(&self) -> usize {
+        match self.storage {
+            Storage::Heap(_) => isize::MAX as usize,
+            Storage::Mapped(ref map) | Storage::Paged(ref map, _) => map.size,
+            Storage::File(ref map, ..) => map.size - HEADER,
+        }
+    }
+
+    // Whether growing to `len` cells moves them, for Stats::tape_growths.
+    pub(crate) fn reallocates(&self, len: usize) -> bool {
+        match self.storage {
+            Storage::Heap(ref cells) => cells.capacity() < len,
+            _ => false,
+        }
+    }
+
+    // Adds zeroed cells up to `len`, which must be within the limit.
+    pub(crate) fn grow(&mut self, len: usize) -> io::Result<()> {
+        if len <= self.len {
+            return Ok(());
+        }
+        assert!(len <= self.limit(), "the tape cannot hold {} cells", len);
+        match self.storage {
+            Storage::Heap(ref mut cells) => {
+                cells.resize(len, 0);
+                self.cells = cells.as_mut_ptr();
+            }
+            Storage::File(ref map, ref file, ref mut size) => {
+                if *size < HEADER + len {
+                    // doubling, to resize the file seldom
+                    let doubled = (HEADER + len).max(*size * 2).min(map.size);
+                    file.set_len(doubled as u64)?;
+                    *size = doubled;
+                }
+                self.zero(len);
+            }
+            Storage::Paged(ref map, ref mut usable) => {
+                if *usable < len {
+                    let pages = len.next_multiple_of(PAGE_SIZE).min(map.size);
+                    map.commit(*usable, pages - *usable)?;
+                    *usable = pages;
+                }
+                self.zero(len);
+            }
+            Storage::Mapped(_) => self.zero(len),
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    // Zeroes the cells up to `len` that may have been written to before.
+    fn zero(&mut self, len: usize) {
+        if self.len < self.clean {
+            let end = len.min(self.clean);
+            unsafe { std::ptr::write_bytes(self.cells.add(self.len), 0, end - self.len) };
+        }
+        self.clean = self.clean.max(len);
+    }
+
+    // Replaces the cells with `cells`, keeping where they live.
+    pub(crate) fn assign(&mut self, cells: &[u8]) -> io::Result<()> {
+        if let Storage::Heap(ref mut vec) = self.storage {
+            vec.clear();
+            vec.extend_from_slice(cells);
+            self.cells = vec.as_mut_ptr();
+            self.len = vec.len();
+            return Ok(());
+        }
+        self.clean = self.clean.max(self.len);
+        self.len = 0;
+        self.grow(cells.len())?;
+        self.copy_from_slice(cells);
+        Ok(())
+    }
+
+    // Records the pointer and the size of a tape stored in a file.
+    pub(crate) fn save_pointer(&mut self, pointer: usize) {
+        if let Storage::File(..) = self.storage {
+            let header = unsafe { std::slice::from_raw_parts_mut(self.cells.sub(HEADER), HEADER) };
+            header[8..16].copy_from_slice(&(pointer as u64).to_le_bytes());
+            header[16..24].copy_from_slice(&(self.len as u64).to_le_bytes());
+        }
+    }
+}
+
+impl Deref for Tape {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.cells, self.len) }
+    }
+}
+
+impl DerefMut for Tape {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.cells, self.len) }
+    }
+}
+
+impl fmt::Debug for Tape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self[..].fmt(f)
+    }
+}
+
+impl PartialEq<Vec<u8>> for Tape {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    const PROT_NONE: i32 = 0;
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const MAP_SHARED: i32 = 1;
+    const MAP_PRIVATE: i32 = 2;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const MAP_ANONYMOUS: i32 = 0x20;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const MAP_NORESERVE: i32 = 0x4000;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const MAP_ANONYMOUS: i32 = 0x1000;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const MAP_NORESERVE: i32 = 0x40;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut u8,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: isize,
+        ) -> *mut u8;
+        fn munmap(addr: *mut u8, len: usize) -> i32;
+        fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+    }
+
+    pub struct Map {
+        pub base: *mut u8,
+        pub size: usize,
+    }
+
+    impl Map {
+        fn new(size: usize, prot: i32, flags: i32, fd: i32) -> io::Result<Self> {
+            let base = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    size,
+                    prot,
+                    flags | MAP_NORESERVE,
+                    fd,
+                    0,
+                )
+            };
+            // MAP_FAILED
+            if base as usize == usize::MAX {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { base, size })
+        }
+
+        pub fn anonymous(size: usize) -> io::Result<Self> {
+            Self::new(
+                size,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+            )
+        }
+
+        // Address space that cannot be touched until committed.
+        pub fn reserve(size: usize) -> io::Result<Self> {
+            Self::new(size, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1)
+        }
+
+        // Makes `len` bytes from `offset`, which is page-aligned, usable.
+        pub fn commit(&self, offset: usize, len: usize) -> io::Result<()> {
+            if unsafe { mprotect(self.base.add(offset), len, PROT_READ | PROT_WRITE) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        // Only the part within the file may be touched.
+        pub fn file(file: &File, size: usize) -> io::Result<Self> {
+            Self::new(size, PROT_READ | PROT_WRITE, MAP_SHARED, file.as_raw_fd())
+        }
+    }
+
+    impl Drop for Map {
+        fn drop(&mut self) {
+            unsafe { munmap(self.base, self.size) };
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+
+    pub struct Map {
+        pub base: *mut u8,
+        pub size: usize,
+    }
+
+    impl Map {
+        pub fn anonymous(_size: usize) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "memory maps need Linux or macOS",
+            ))
+        }
+
+        pub fn reserve(size: usize) -> io::Result<Self> {
+            Self::anonymous(size)
+        }
+
+        pub fn commit(&self, _offset: usize, _len: usize) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn file(_file: &File, size: usize) -> io::Result<Self> {
+            Self::anonymous(size)
+        }
+    }
+}
+
+#[test]
+fn test_tape_file() {
+    use crate::interpreter::InterpreterErrorKind;
+    use crate::interpreter::SimpleInterpreter;
+    use crate::lexer::Lexer;
+
+    let path =
+        std::env::temp_dir().join(format!("brainfuck_test_tape_{}.bftape", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let run = |source: &str| {
+        let mut machine = SimpleInterpreter::new();
+        machine.load(&Lexer::lex(source)).unwrap();
+        let (tape, pointer) = Tape::open_file(&path).unwrap();
+        machine.set_tape(tape, pointer);
+        let result = machine.run().map_err(|e| e.value);
+        (result, machine.cells().to_vec(), machine.pointer())
+    };
+    assert_eq!(run("+++>++"), (Ok(0), vec![3, 2], 1));
+    // the pointer and the cells carry on
+    assert_eq!(run("+<+>>>"), (Ok(0), vec![4, 3, 0, 0], 3));
+    // kept up to the error
+    assert_eq!(
+        run("+<<<<<"),
+        (Err(InterpreterErrorKind::PointerError), vec![4, 3, 0, 1], 0)
+    );
+    assert_eq!(run(""), (Ok(0), vec![4, 3, 0, 1], 0));
+
+    std::fs::write(&path, b"not a tape").unwrap();
+    assert!(Tape::open_file(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+
+    let mut tape = Tape::new(TapeKind::Mapped);
+    tape.grow(3).unwrap();
+    tape[2] = 7;
+    tape.assign(&[1]).unwrap();
+    tape.grow(3).unwrap();
+    assert_eq!(tape, vec![1, 0, 0]);
+}
+
+#[test]
+fn test_paged_tape() {
+    use crate::engine::EngineKind;
+    use crate::lexer::Lexer;
+
+    let mut tape = Tape::new(TapeKind::Paged);
+    assert_eq!(tape, vec![0]);
+    // grown a page at a time, in place
+    let cells = tape.as_ptr();
+    tape.grow(PAGE_SIZE + 1).unwrap();
+    tape[PAGE_SIZE] = 7;
+    assert_eq!((tape.as_ptr(), tape.reallocates(1 << 30)), (cells, false));
+    tape.assign(&[1]).unwrap();
+    tape.grow(PAGE_SIZE + 1).unwrap();
+    assert_eq!((tape[0], tape[PAGE_SIZE]), (1, 0));
+
+    let source = format!("{}+++.", ">".repeat(2 * PAGE_SIZE));
+    for &kind in [EngineKind::Simple, EngineKind::Optimized].iter() {
+        let mut interpreter = kind.create();
+        interpreter.machine_mut().set_tape_kind(TapeKind::Paged);
+        interpreter
+            .machine_mut()
+            .set_output(Box::new(std::io::sink()));
+        let outcome = interpreter.eval_outcome(&Lexer::lex(&source));
+        assert!(
+            matches!(outcome.reason, crate::engine::StopReason::Completed),
+            "{:?}",
+            kind
+        );
+        let cells = interpreter.machine().cells();
+        assert_eq!((cells.len(), cells[2 * PAGE_SIZE]), (2 * PAGE_SIZE + 1, 3));
+        assert_eq!(outcome.stats.tape_growths, 0);
+    }
+}
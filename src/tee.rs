@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+// An output for set_output writing every byte to each of its sinks, e.g.
+// stdout to watch a run, a file to keep it and a HashingOutput to check it.
+// A sink that fails fails the write, which stops the program with an output
+// error, and the sinks after it do not get the bytes.
+#[derive(Default)]
+pub struct Tee {
+    sinks: Vec<Box<dyn Write + Send>>,
+}
+
+impl Tee {
+    pub fn new(sinks: Vec<Box<dyn Write + Send>>) -> Self {
+        Self { sinks }
+    }
+
+    pub fn push(&mut self, sink: Box<dyn Write + Send>) {
+        self.sinks.push(sink);
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for sink in &mut self.sinks {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+    // Flushes every sink, even after one failed.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.flush() {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn test_tee() {
+    use crate::digest::HashingOutput;
+    use crate::spec::Capture;
+
+    let capture = Capture::default();
+    let hashing = HashingOutput::default();
+    let mut interpreter = crate::interpreter::SimpleInterpreter::new();
+    interpreter.set_output(Box::new(capture.clone()));
+    interpreter.add_output(Box::new(hashing.clone()));
+    let program = crate::lexer::Lexer::lex("+++++++[>++++++++++++++<-]>-.+.+.");
+    assert_eq!(interpreter.eval(&program), Ok(0));
+    assert_eq!(*capture.0.lock().unwrap(), b"abc");
+    assert_eq!(
+        hashing.digest(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+
+    struct Full;
+    impl Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+    }
+    let mut tee = Tee::new(vec![Box::new(Full)]);
+    tee.push(Box::new(capture.clone()));
+    assert!(tee.write(b"d").is_err());
+    assert!(tee.flush().is_err());
+    assert_eq!(*capture.0.lock().unwrap(), b"abc");
+}
@@ -0,0 +1,327 @@
+use std::io::{self, IsTerminal, Read};
+
+// Switches the terminal on stdin to non-canonical mode, so that each keypress
+// is read as soon as it is typed. Ctrl-C still interrupts. Without `echo`,
+// typed keys are not shown. The previous mode is restored when the value is
+// dropped.
+pub struct RawMode {
+    saved: imp::Mode,
+}
+
+impl RawMode {
+    pub fn enable(echo: bool) -> io::Result<Self> {
+        if !io::stdin().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stdin is not a terminal",
+            ));
+        }
+        let saved = imp::get()?;
+        imp::set(&imp::raw(&saved, echo))?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = imp::set(&self.saved);
+    }
+}
+
+// The reader `,` should use for stdin. On Windows, line ends are translated
+// to '\n' and Ctrl-Z typed on the console ends the input, so programs see
+// the same bytes as on Unix.
+pub fn stdin_input() -> Box<dyn Read + Send> {
+    if cfg!(windows) {
+        let ctrl_z_eof = io::stdin().is_terminal();
+        Box::new(ConsoleInput::new(io::stdin(), ctrl_z_eof))
+    } else {
+        Box::new(io::stdin())
+    }
+}
+
+const CTRL_Z: u8 = 0x1a;
+
+// Translates "\r\n" and a lone '\r' to '\n'. With `ctrl_z_eof`, a Ctrl-Z
+// byte ends the input.
+pub struct ConsoleInput<R> {
+    inner: R,
+    ctrl_z_eof: bool,
+    after_cr: bool,
+    eof: bool,
+}
+
+impl<R: Read> ConsoleInput<R> {
+    pub fn new(inner: R, ctrl_z_eof: bool) -> Self {
+        Self {
+            inner,
+            ctrl_z_eof,
+            after_cr: false,
+            eof: false,
+        }
+    }
+}
+
+impl<R: Read> Read for ConsoleInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.eof && !buf.is_empty() {
+            let read = self.inner.read(buf)?;
+            if read == 0 {
+                self.eof = true;
+                break;
+            }
+            let mut written = 0;
+            for i in 0..read {
+                let byte = buf[i];
+                let after_cr = self.after_cr;
+                self.after_cr = byte == b'\r';
+                match byte {
+                    b'\n' if after_cr => {}
+                    b'\r' => {
+                        buf[written] = b'\n';
+                        written += 1;
+                    }
+                    CTRL_Z if self.ctrl_z_eof => {
+                        self.eof = true;
+                        break;
+                    }
+                    _ => {
+                        buf[written] = byte;
+                        written += 1;
+                    }
+                }
+            }
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::io;
+
+    const ICANON: u32 = 0o2;
+    const ECHO: u32 = 0o10;
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+    const TCSANOW: i32 = 0;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Mode {
+        c_iflag: u32,
+        c_oflag: u32,
+        c_cflag: u32,
+        c_lflag: u32,
+        c_line: u8,
+        c_cc: [u8; 32],
+        c_ispeed: u32,
+        c_ospeed: u32,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: i32, termios: *mut Mode) -> i32;
+        fn tcsetattr(fd: i32, action: i32, termios: *const Mode) -> i32;
+    }
+
+    pub fn get() -> io::Result<Mode> {
+        let mut mode = Mode {
+            c_iflag: 0,
+            c_oflag: 0,
+            c_cflag: 0,
+            c_lflag: 0,
+            c_line: 0,
+            c_cc: [0; 32],
+            c_ispeed: 0,
+            c_ospeed: 0,
+        };
+        if unsafe { tcgetattr(0, &mut mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    pub fn set(mode: &Mode) -> io::Result<()> {
+        if unsafe { tcsetattr(0, TCSANOW, mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn raw(mode: &Mode, echo: bool) -> Mode {
+        let mut raw = *mode;
+        raw.c_lflag &= !ICANON;
+        if !echo {
+            raw.c_lflag &= !ECHO;
+        }
+        raw.c_cc[VMIN] = 1;
+        raw.c_cc[VTIME] = 0;
+        raw
+    }
+
+    #[test]
+    fn test_raw() {
+        let cooked = Mode {
+            c_iflag: 0,
+            c_oflag: 0,
+            c_cflag: 0,
+            c_lflag: ICANON | ECHO,
+            c_line: 0,
+            c_cc: [0; 32],
+            c_ispeed: 0,
+            c_ospeed: 0,
+        };
+        assert_eq!(raw(&cooked, true).c_lflag, ECHO);
+        // --no-echo
+        assert_eq!(raw(&cooked, false).c_lflag, 0);
+        assert_eq!(raw(&cooked, false).c_cc[VMIN], 1);
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod imp {
+    use std::io;
+
+    const ICANON: u64 = 0x100;
+    const ECHO: u64 = 0x8;
+    const VMIN: usize = 16;
+    const VTIME: usize = 17;
+    const TCSANOW: i32 = 0;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Mode {
+        c_iflag: u64,
+        c_oflag: u64,
+        c_cflag: u64,
+        c_lflag: u64,
+        c_cc: [u8; 20],
+        c_ispeed: u64,
+        c_ospeed: u64,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: i32, termios: *mut Mode) -> i32;
+        fn tcsetattr(fd: i32, action: i32, termios: *const Mode) -> i32;
+    }
+
+    pub fn get() -> io::Result<Mode> {
+        let mut mode = Mode {
+            c_iflag: 0,
+            c_oflag: 0,
+            c_cflag: 0,
+            c_lflag: 0,
+            c_cc: [0; 20],
+            c_ispeed: 0,
+            c_ospeed: 0,
+        };
+        if unsafe { tcgetattr(0, &mut mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    pub fn set(mode: &Mode) -> io::Result<()> {
+        if unsafe { tcsetattr(0, TCSANOW, mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn raw(mode: &Mode, echo: bool) -> Mode {
+        let mut raw = *mode;
+        raw.c_lflag &= !ICANON;
+        if !echo {
+            raw.c_lflag &= !ECHO;
+        }
+        raw.c_cc[VMIN] = 1;
+        raw.c_cc[VTIME] = 0;
+        raw
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::io;
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const ENABLE_LINE_INPUT: u32 = 0x2;
+    // echo needs line input, so typed keys are not echoed in raw mode
+    const ENABLE_ECHO_INPUT: u32 = 0x4;
+
+    #[derive(Clone, Copy)]
+    pub struct Mode(u32);
+
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut std::ffi::c_void;
+        fn GetConsoleMode(handle: *mut std::ffi::c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut std::ffi::c_void, mode: u32) -> i32;
+    }
+
+    pub fn get() -> io::Result<Mode> {
+        let mut mode = 0;
+        if unsafe { GetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), &mut mode) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mode(mode))
+    }
+
+    pub fn set(mode: &Mode) -> io::Result<()> {
+        if unsafe { SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), mode.0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // ENABLE_PROCESSED_INPUT is kept, so Ctrl-C still interrupts. The
+    // console never echoes without line input, whatever `echo` says.
+    pub fn raw(mode: &Mode, _echo: bool) -> Mode {
+        Mode(mode.0 & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+mod imp {
+    use std::io;
+
+    pub struct Mode;
+
+    pub fn get() -> io::Result<Mode> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "raw input is not supported on this platform",
+        ))
+    }
+
+    pub fn set(_mode: &Mode) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn raw(_mode: &Mode, _echo: bool) -> Mode {
+        Mode
+    }
+}
+
+#[test]
+fn test_console_input() {
+    let mut translated = Vec::new();
+    ConsoleInput::new(&b"a\r\nb\rc\n\x1ad"[..], true)
+        .read_to_end(&mut translated)
+        .unwrap();
+    assert_eq!(translated, b"a\nb\nc\n");
+
+    // a "\r\n" split between two reads
+    let mut input = ConsoleInput::new(io::Read::chain(&b"a\r"[..], &b"\nb"[..]), false);
+    let mut translated = Vec::new();
+    input.read_to_end(&mut translated).unwrap();
+    assert_eq!(translated, b"a\nb");
+}